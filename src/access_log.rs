@@ -0,0 +1,475 @@
+use crate::{DateTime, LoggingConfig};
+
+use serde::Serialize;
+use std::fmt::Write as _;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+/// The line format of the access log.
+///
+/// # Variants
+/// - `Common`: Common Log Format, exactly as CLF parsers expect it:
+///   `127.0.0.1 - - [15/Oct/2026:09:31:26 +0000] "GET / HTTP/1.1" 200 146`
+/// - `Combined`: Combined Log Format (Common plus referer and user agent). As a deliberate
+///   extension, the duration in microseconds follows as a last field, like Apache's `%D`:
+///   `127.0.0.1 - - [15/Oct/2026:09:31:26 +0000] "GET / HTTP/1.1" 200 146 "-" "curl/8.5.0" 312`
+/// - `Json`: One JSON object per line with the same fields, see [`AccessLogEntry::format`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogFormat {
+    Common,
+    #[default]
+    Combined,
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    /// Parses `common`, `combined` or `json` (case-insensitive), as used in the server configuration.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "common" => Ok(LogFormat::Common),
+            "combined" => Ok(LogFormat::Combined),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!(
+                "expected `common`, `combined` or `json`, got `{value}`"
+            )),
+        }
+    }
+}
+
+/// Everything the access log records about one request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccessLogEntry {
+    /// The client's address, if the socket still knew it.
+    pub remote_addr: Option<SocketAddr>,
+    /// When the request started to arrive.
+    pub time: SystemTime,
+    /// `<METHOD> <URL> <VERSION>`, or `None` if the request couldn't be parsed.
+    pub request_line: Option<String>,
+    pub status: u16,
+    /// Body bytes sent, without the status line and header fields.
+    pub bytes_sent: u64,
+    /// From the start of the request until the response was written.
+    pub duration: Duration,
+    pub referer: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+impl AccessLogEntry {
+    /// Formats the entry as a single log line, without the line terminator.
+    ///
+    /// Missing values are written as `-` in the Common and Combined formats and as `null` in JSON.
+    /// The JSON fields are `remote_addr`, `time` (RFC 3339, UTC), `request`, `status`, `bytes`,
+    /// `duration_us`, `referer` and `user_agent`.
+    pub fn format(&self, format: LogFormat) -> String {
+        let remote_addr = self.remote_addr.map(|address| address.ip().to_string());
+        let date = DateTime::from_system_time(self.time);
+        let duration_us = self.duration.as_micros();
+
+        if format == LogFormat::Json {
+            #[derive(Serialize)]
+            struct Line<'a> {
+                remote_addr: Option<&'a str>,
+                time: String,
+                request: Option<&'a str>,
+                status: u16,
+                bytes: u64,
+                duration_us: u128,
+                referer: Option<&'a str>,
+                user_agent: Option<&'a str>,
+            }
+
+            let line = Line {
+                remote_addr: remote_addr.as_deref(),
+                time: format!(
+                    "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+                    date.year, date.month, date.day, date.hour, date.minute, date.second
+                ),
+                request: self.request_line.as_deref(),
+                status: self.status,
+                bytes: self.bytes_sent,
+                duration_us,
+                referer: self.referer.as_deref(),
+                user_agent: self.user_agent.as_deref(),
+            };
+            return serde_json::to_string(&line).unwrap_or_default();
+        }
+
+        /* CLF writes "-" rather than 0 for an empty body. */
+        let bytes = match self.bytes_sent {
+            0 => "-".to_string(),
+            bytes => bytes.to_string(),
+        };
+        let mut line = format!(
+            "{} - - [{:02}/{}/{:04}:{:02}:{:02}:{:02} +0000] {} {} {bytes}",
+            remote_addr.as_deref().unwrap_or("-"),
+            date.day,
+            date.month_name(),
+            date.year,
+            date.hour,
+            date.minute,
+            date.second,
+            quote(self.request_line.as_deref()),
+            self.status,
+        );
+        if format == LogFormat::Combined {
+            let _ = write!(
+                line,
+                " {} {} {duration_us}",
+                quote(self.referer.as_deref()),
+                quote(self.user_agent.as_deref())
+            );
+        }
+        line
+    }
+}
+
+/// Where log lines go, see [`AccessLog`].
+enum Sink {
+    Stdout,
+    File(RotatingFile),
+}
+
+/// A log file that is rotated once it grows past `max_size` bytes:
+/// `access.log` becomes `access.log.1`, `access.log.1` becomes `access.log.2`, and so on,
+/// keeping at most `max_files` old files.
+struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    /// `0` never rotates.
+    max_size: u64,
+    max_files: usize,
+}
+
+impl RotatingFile {
+    fn open(path: &Path, max_size: u64, max_files: usize) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let size = file.metadata()?.len();
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+            size,
+            max_size,
+            max_files,
+        })
+    }
+
+    fn write_line(&mut self, line: &str) -> io::Result<()> {
+        let length = line.len() as u64 + 1;
+        if self.max_size > 0 && self.size > 0 && self.size + length > self.max_size {
+            self.rotate()?;
+        }
+        writeln!(self.file, "{line}")?;
+        self.size += length;
+        Ok(())
+    }
+
+    /// Shifts the old files up by one, dropping the oldest, and starts a new empty file.
+    fn rotate(&mut self) -> io::Result<()> {
+        let numbered = |index: usize| {
+            let mut path = self.path.clone().into_os_string();
+            path.push(format!(".{index}"));
+            PathBuf::from(path)
+        };
+
+        if self.max_files == 0 {
+            self.file = File::create(&self.path)?;
+        } else {
+            let _ = std::fs::remove_file(numbered(self.max_files));
+            for index in (1..self.max_files).rev() {
+                let _ = std::fs::rename(numbered(index), numbered(index + 1));
+            }
+            std::fs::rename(&self.path, numbered(1))?;
+            self.file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)?;
+        }
+        self.size = 0;
+        Ok(())
+    }
+}
+
+/// The access log: one line per request, in a [`LogFormat`], written to stdout or to a file.
+///
+/// Worker threads share a single `AccessLog`; each line is written whole, under a lock.
+/// A file log is rotated by size, see [`LoggingConfig`].
+pub struct AccessLog {
+    format: LogFormat,
+    sink: Mutex<Sink>,
+}
+
+impl AccessLog {
+    /// An access log written to stdout.
+    pub fn stdout(format: LogFormat) -> Self {
+        Self {
+            format,
+            sink: Mutex::new(Sink::Stdout),
+        }
+    }
+
+    /// An access log appended to the file at `path`, which is created if needed.
+    ///
+    /// # Arguments
+    /// - `max_size`: Size in bytes after which the file is rotated. `0` disables rotation.
+    /// - `max_files`: How many rotated files (`<path>.1`, `<path>.2`, ...) are kept. With `0` the
+    ///   file is truncated instead.
+    ///
+    /// # Returns
+    /// - `Ok(AccessLog)`: If the file could be opened.
+    /// - `Err(io::Error)`: If it couldn't.
+    pub fn file(
+        path: &Path,
+        format: LogFormat,
+        max_size: u64,
+        max_files: usize,
+    ) -> io::Result<Self> {
+        Ok(Self {
+            format,
+            sink: Mutex::new(Sink::File(RotatingFile::open(path, max_size, max_files)?)),
+        })
+    }
+
+    /// The access log described by `config`: a file if `config.file` is set, otherwise stdout.
+    ///
+    /// # Returns
+    /// - `Ok(AccessLog)`: The log.
+    /// - `Err(io::Error)`: If the log file can't be opened.
+    pub fn from_config(config: &LoggingConfig) -> io::Result<Self> {
+        match &config.file {
+            Some(path) => Self::file(path, config.format, config.max_size, config.max_files),
+            None => Ok(Self::stdout(config.format)),
+        }
+    }
+
+    /// The format lines are written in.
+    pub fn format(&self) -> LogFormat {
+        self.format
+    }
+
+    /// Writes one line for `entry`. A failed write is reported on stderr and otherwise ignored;
+    /// logging never fails a request.
+    pub fn log(&self, entry: &AccessLogEntry) {
+        let line = entry.format(self.format);
+        let mut sink = match self.sink.lock() {
+            Ok(sink) => sink,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        let result = match &mut *sink {
+            Sink::Stdout => writeln!(io::stdout().lock(), "{line}"),
+            Sink::File(file) => file.write_line(&line),
+        };
+        if let Err(error) = result {
+            eprintln!("Failed to write the access log: {error}");
+        }
+    }
+}
+
+/// Wraps `value` in double quotes for the Common and Combined formats. `"`, `\` and
+/// non-printable bytes are escaped as `\"`, `\\` and `\xHH`, so a client can't forge log fields.
+/// A missing value is written as `"-"`.
+fn quote(value: Option<&str>) -> String {
+    let Some(value) = value else {
+        return "\"-\"".to_string();
+    };
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for byte in value.bytes() {
+        match byte {
+            b'"' => quoted.push_str("\\\""),
+            b'\\' => quoted.push_str("\\\\"),
+            b' '..=b'~' => quoted.push(byte as char),
+            _ => {
+                let _ = write!(quoted, "\\x{byte:02x}");
+            }
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::UNIX_EPOCH;
+
+    /// A `GET /` from curl at 15/Oct/2026:09:31:26 that took 312µs.
+    fn entry() -> AccessLogEntry {
+        AccessLogEntry {
+            remote_addr: Some("127.0.0.1:51234".parse().unwrap()),
+            time: UNIX_EPOCH + Duration::from_secs(1_792_056_686),
+            request_line: Some("GET / HTTP/1.1".to_string()),
+            status: 200,
+            bytes_sent: 146,
+            duration: Duration::from_micros(312),
+            referer: None,
+            user_agent: Some("curl/8.5.0".to_string()),
+        }
+    }
+
+    /// A log file path in the temp directory, removed again (with its rotated files) when dropped.
+    struct LogPath(PathBuf);
+
+    impl LogPath {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!(
+                "rust_server_access_{name}_{}.log",
+                std::process::id()
+            ));
+            let log = Self(path);
+            log.cleanup();
+            log
+        }
+
+        fn numbered(&self, index: usize) -> PathBuf {
+            PathBuf::from(format!("{}.{index}", self.0.display()))
+        }
+
+        fn read(path: &Path) -> String {
+            std::fs::read_to_string(path).unwrap_or_default()
+        }
+
+        fn cleanup(&self) {
+            let _ = std::fs::remove_file(&self.0);
+            for index in 1..=4 {
+                let _ = std::fs::remove_file(self.numbered(index));
+            }
+        }
+    }
+
+    impl Drop for LogPath {
+        fn drop(&mut self) {
+            self.cleanup();
+        }
+    }
+
+    #[test]
+    fn common_lines_are_plain_clf() {
+        assert_eq!(
+            entry().format(LogFormat::Common),
+            r#"127.0.0.1 - - [15/Oct/2026:09:31:26 +0000] "GET / HTTP/1.1" 200 146"#
+        );
+    }
+
+    #[test]
+    fn combined_lines_add_referer_user_agent_and_the_duration() {
+        assert_eq!(
+            entry().format(LogFormat::Combined),
+            r#"127.0.0.1 - - [15/Oct/2026:09:31:26 +0000] "GET / HTTP/1.1" 200 146 "-" "curl/8.5.0" 312"#
+        );
+    }
+
+    #[test]
+    fn json_lines_carry_every_field() {
+        let line: serde_json::Value =
+            serde_json::from_str(&entry().format(LogFormat::Json)).unwrap();
+        assert_eq!(
+            line,
+            serde_json::json!({
+                "remote_addr": "127.0.0.1",
+                "time": "2026-10-15T09:31:26Z",
+                "request": "GET / HTTP/1.1",
+                "status": 200,
+                "bytes": 146,
+                "duration_us": 312,
+                "referer": null,
+                "user_agent": "curl/8.5.0",
+            })
+        );
+    }
+
+    #[test]
+    fn missing_fields_are_dashes_or_null() {
+        let entry = AccessLogEntry {
+            remote_addr: None,
+            request_line: None,
+            status: 400,
+            bytes_sent: 0,
+            user_agent: None,
+            ..entry()
+        };
+        assert_eq!(
+            entry.format(LogFormat::Combined),
+            r#"- - - [15/Oct/2026:09:31:26 +0000] "-" 400 - "-" "-" 312"#
+        );
+        let json = entry.format(LogFormat::Json);
+        assert!(json.contains(r#""remote_addr":null"#), "{json}");
+        assert!(json.contains(r#""request":null"#), "{json}");
+        assert!(json.contains(r#""bytes":0"#), "{json}");
+    }
+
+    #[test]
+    fn quotes_backslashes_and_control_bytes_are_escaped() {
+        let entry = AccessLogEntry {
+            request_line: Some(r#"GET /a"b\c HTTP/1.1"#.to_string()),
+            referer: Some("x\"\n127.0.0.1 - - forged".to_string()),
+            user_agent: Some(r#"evil\" "agent"#.to_string()),
+            ..entry()
+        };
+        assert_eq!(
+            entry.format(LogFormat::Combined),
+            r#"127.0.0.1 - - [15/Oct/2026:09:31:26 +0000] "GET /a\"b\\c HTTP/1.1" 200 146 "x\"\x0a127.0.0.1 - - forged" "evil\\\" \"agent" 312"#
+        );
+
+        let json: serde_json::Value = serde_json::from_str(&entry.format(LogFormat::Json)).unwrap();
+        assert_eq!(json["user_agent"], r#"evil\" "agent"#);
+    }
+
+    #[test]
+    fn log_files_rotate_at_the_size_limit() {
+        let log = LogPath::new("rotate");
+        /* Each line is 10 bytes with its terminator, so two fit under the limit. */
+        let mut file = RotatingFile::open(&log.0, 25, 2).unwrap();
+        for line in [
+            "line-0001",
+            "line-0002",
+            "line-0003",
+            "line-0004",
+            "line-0005",
+        ] {
+            file.write_line(line).unwrap();
+        }
+
+        assert_eq!(LogPath::read(&log.0), "line-0005\n");
+        assert_eq!(LogPath::read(&log.numbered(1)), "line-0003\nline-0004\n");
+        assert_eq!(LogPath::read(&log.numbered(2)), "line-0001\nline-0002\n");
+        assert!(!log.numbered(3).exists());
+    }
+
+    #[test]
+    fn rotation_counts_what_the_file_already_holds() {
+        let log = LogPath::new("reopen");
+        std::fs::write(&log.0, "line-0001\nline-0002\n").unwrap();
+        let mut file = RotatingFile::open(&log.0, 25, 1).unwrap();
+        file.write_line("line-0003").unwrap();
+
+        assert_eq!(LogPath::read(&log.0), "line-0003\n");
+        assert_eq!(LogPath::read(&log.numbered(1)), "line-0001\nline-0002\n");
+    }
+
+    #[test]
+    fn without_kept_files_the_log_is_truncated_and_without_a_limit_never_rotated() {
+        let log = LogPath::new("truncate");
+        let mut file = RotatingFile::open(&log.0, 25, 0).unwrap();
+        for line in ["line-0001", "line-0002", "line-0003"] {
+            file.write_line(line).unwrap();
+        }
+        assert_eq!(LogPath::read(&log.0), "line-0003\n");
+        assert!(!log.numbered(1).exists());
+
+        let unlimited = LogPath::new("unlimited");
+        let mut file = RotatingFile::open(&unlimited.0, 0, 2).unwrap();
+        for _ in 0..10 {
+            file.write_line("line-0001").unwrap();
+        }
+        assert_eq!(LogPath::read(&unlimited.0).lines().count(), 10);
+        assert!(!unlimited.numbered(1).exists());
+    }
+}
@@ -0,0 +1,297 @@
+use crate::request::read_line;
+use crate::{Headers, Limits, Request, RequestError};
+
+use std::io::{BufRead, ErrorKind, Read};
+
+/// How the length of a request body is determined (RFC 9112 section 6).
+enum Framing {
+    /// No `Content-Length` or `Transfer-Encoding`: the request has no body.
+    Empty,
+    /// `Content-Length: <n>`: exactly `n` bytes follow the head.
+    Length(u64),
+    /// `Transfer-Encoding: chunked`: a sequence of chunks ending with a zero-size chunk and optional trailers.
+    Chunked,
+}
+
+/// Reads the request body that follows a request head with the given `headers`.
+///
+/// # Arguments
+/// - `reader`: Buffered reader positioned right after the empty line that ends the head.
+/// - `headers`: The already parsed request headers, used to pick the framing.
+/// - `limits`: `max_body_size` caps the decoded body, `max_header_line_length` the chunk-size and trailer lines.
+///
+/// # Returns
+/// - `Ok((body, trailers))`: The decoded body and the trailer fields (only chunked bodies have trailers).
+/// - `Err(RequestError::PayloadTooLarge)`: If the body is larger than `limits.max_body_size`.
+/// - `Err(RequestError::InvalidBody)`: If `Content-Length` or the chunk framing is malformed or the stream ends early.
+/// - `Err(RequestError::UnsupportedTransferEncoding)`: If `Transfer-Encoding` is anything but `chunked`.
+/// - `Err(RequestError::Io(_))`: If reading fails.
+pub(crate) fn read_body<R: BufRead>(
+    reader: &mut R,
+    headers: &Headers,
+    limits: &Limits,
+) -> Result<(Vec<u8>, Headers), RequestError> {
+    match framing(headers)? {
+        Framing::Empty => Ok((Vec::new(), Headers::new())),
+        Framing::Length(length) => {
+            if length > limits.max_body_size as u64 {
+                return Err(RequestError::PayloadTooLarge);
+            }
+            let mut body = vec![0; length as usize];
+            read_exact(reader, &mut body)?;
+            Ok((body, Headers::new()))
+        }
+        Framing::Chunked => read_chunked(reader, limits),
+    }
+}
+
+/// This function is a private helper function for [`read_body`].
+/// - `Transfer-Encoding` wins over `Content-Length`, but a request carrying both is rejected
+///   since it is a classic request smuggling vector.
+/// - Repeated `Content-Length` fields must all agree.
+fn framing(headers: &Headers) -> Result<Framing, RequestError> {
+    let transfer_encodings: Vec<&str> = headers
+        .get_all("Transfer-Encoding")
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .filter(|coding| !coding.is_empty())
+        .collect();
+
+    if !transfer_encodings.is_empty() {
+        if headers.contains("Content-Length") {
+            return Err(RequestError::InvalidBody);
+        }
+        return match transfer_encodings.as_slice() {
+            [coding] if coding.eq_ignore_ascii_case("chunked") => Ok(Framing::Chunked),
+            _ => Err(RequestError::UnsupportedTransferEncoding),
+        };
+    }
+
+    let mut length = None;
+    for value in headers
+        .get_all("Content-Length")
+        .flat_map(|value| value.split(','))
+    {
+        let value = value.trim();
+        if value.is_empty() || !value.bytes().all(|byte| byte.is_ascii_digit()) {
+            return Err(RequestError::InvalidBody);
+        }
+        let value: u64 = value.parse().map_err(|_| RequestError::InvalidBody)?;
+        if length.is_some_and(|length| length != value) {
+            return Err(RequestError::InvalidBody);
+        }
+        length = Some(value);
+    }
+
+    Ok(match length {
+        Some(length) => Framing::Length(length),
+        None => Framing::Empty,
+    })
+}
+
+/// This function is a private helper function for [`read_body`].
+/// - Each chunk is `<hex size>[;extensions]\r\n<data>\r\n`. Chunk extensions are ignored.
+/// - A zero-size chunk ends the body and is followed by trailer fields and an empty line.
+fn read_chunked<R: BufRead>(
+    reader: &mut R,
+    limits: &Limits,
+) -> Result<(Vec<u8>, Headers), RequestError> {
+    let mut body = Vec::new();
+
+    loop {
+        let line =
+            read_line(reader, limits.max_header_line_length)?.ok_or(RequestError::InvalidBody)?;
+        let line = String::from_utf8(line).map_err(|_| RequestError::InvalidBody)?;
+        let size = line.split(';').next().unwrap_or_default().trim();
+        if size.is_empty() || !size.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+            return Err(RequestError::InvalidBody);
+        }
+        let size = u64::from_str_radix(size, 16).map_err(|_| RequestError::InvalidBody)?;
+
+        if size == 0 {
+            let trailers = Request::parse_headers(reader, limits).map_err(|error| match error {
+                RequestError::MalformedHeader => RequestError::InvalidBody,
+                error => error,
+            })?;
+            return Ok((body, trailers));
+        }
+        if size > (limits.max_body_size - body.len()) as u64 {
+            return Err(RequestError::PayloadTooLarge);
+        }
+
+        let start = body.len();
+        body.resize(start + size as usize, 0);
+        read_exact(reader, &mut body[start..])?;
+
+        let mut terminator = [0; 2];
+        read_exact(reader, &mut terminator)?;
+        if &terminator != b"\r\n" {
+            return Err(RequestError::InvalidBody);
+        }
+    }
+}
+
+/// Like [`Read::read_exact`], but a stream that ends early is an [`RequestError::InvalidBody`]
+/// (and one that times out a [`RequestError::Timeout`]).
+fn read_exact<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<(), RequestError> {
+    reader.read_exact(buf).map_err(|error| match error.kind() {
+        ErrorKind::UnexpectedEof => RequestError::InvalidBody,
+        _ => error.into(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(fields: &[(&str, &str)]) -> Headers {
+        let mut headers = Headers::new();
+        for (name, value) in fields {
+            headers.append(*name, *value);
+        }
+        headers
+    }
+
+    fn chunked(raw: &str) -> Result<(Vec<u8>, Headers), RequestError> {
+        chunked_with(raw, &Limits::default())
+    }
+
+    fn chunked_with(raw: &str, limits: &Limits) -> Result<(Vec<u8>, Headers), RequestError> {
+        let headers = headers(&[("Transfer-Encoding", "chunked")]);
+        read_body(&mut raw.as_bytes(), &headers, limits)
+    }
+
+    #[test]
+    fn chunks_are_joined_into_the_body() {
+        let (body, trailers) = chunked("5\r\nhello\r\n1\r\n \r\n5\r\nworld\r\n0\r\n\r\n").unwrap();
+        assert_eq!(body, b"hello world");
+        assert!(trailers.is_empty());
+
+        let (body, _) = chunked("A\r\n0123456789\r\na\r\nabcdefghij\r\n0\r\n\r\n").unwrap();
+        assert_eq!(body, b"0123456789abcdefghij");
+    }
+
+    #[test]
+    fn chunk_extensions_are_ignored() {
+        let (body, _) =
+            chunked("5;name=value\r\nhello\r\n3 ; a ; b=\"c\"\r\nabc\r\n0;last\r\n\r\n").unwrap();
+        assert_eq!(body, b"helloabc");
+    }
+
+    #[test]
+    fn trailers_are_returned_separately() {
+        let (body, trailers) =
+            chunked("2\r\nhi\r\n0\r\nExpires: never\r\nX-Checksum: abc\r\n\r\n").unwrap();
+        assert_eq!(body, b"hi");
+        assert_eq!(trailers.get("expires"), Some("never"));
+        assert_eq!(trailers.get("X-Checksum"), Some("abc"));
+    }
+
+    #[test]
+    fn malformed_trailers_are_an_invalid_body() {
+        let error = chunked("2\r\nhi\r\n0\r\nno colon here\r\n\r\n").unwrap_err();
+        assert!(matches!(error, RequestError::InvalidBody), "{error:?}");
+    }
+
+    #[test]
+    fn chunks_beyond_the_body_limit_are_too_large() {
+        let limits = Limits {
+            max_body_size: 8,
+            ..Limits::default()
+        };
+        assert!(chunked_with("8\r\n01234567\r\n0\r\n\r\n", &limits).is_ok());
+
+        let error = chunked_with("9\r\n012345678\r\n0\r\n\r\n", &limits).unwrap_err();
+        assert!(matches!(error, RequestError::PayloadTooLarge), "{error:?}");
+
+        /* The limit is on the whole body, not each chunk. */
+        let error = chunked_with("5\r\n01234\r\n5\r\n56789\r\n0\r\n\r\n", &limits).unwrap_err();
+        assert!(matches!(error, RequestError::PayloadTooLarge), "{error:?}");
+
+        /* A size too large for u64 is rejected before anything is allocated. */
+        let error = chunked("FFFFFFFFFFFFFFFFFFFF\r\n").unwrap_err();
+        assert!(matches!(error, RequestError::InvalidBody), "{error:?}");
+    }
+
+    #[test]
+    fn broken_chunk_framing_is_an_invalid_body() {
+        for raw in [
+            /* No CRLF after the chunk data. */
+            "5\r\nhello0\r\n\r\n",
+            "5\r\nhelloXY0\r\n\r\n",
+            /* The stream ends inside a chunk, after it, or before the last chunk. */
+            "5\r\nhel",
+            "5\r\nhello",
+            "5\r\nhello\r\n",
+            "",
+            /* Sizes that aren't hex. */
+            "\r\nhello\r\n0\r\n\r\n",
+            "-5\r\nhello\r\n0\r\n\r\n",
+            "0x5\r\nhello\r\n0\r\n\r\n",
+            "g\r\n",
+        ] {
+            let error = chunked(raw).unwrap_err();
+            assert!(
+                matches!(error, RequestError::InvalidBody),
+                "{raw:?}: {error:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn content_length_with_transfer_encoding_is_rejected() {
+        let headers = headers(&[("Content-Length", "5"), ("Transfer-Encoding", "chunked")]);
+        let raw = "5\r\nhello\r\n0\r\n\r\n";
+        let error = read_body(&mut raw.as_bytes(), &headers, &Limits::default()).unwrap_err();
+        assert!(matches!(error, RequestError::InvalidBody), "{error:?}");
+    }
+
+    #[test]
+    fn transfer_codings_other_than_chunked_are_unsupported() {
+        for coding in ["gzip", "gzip, chunked", "chunked, chunked"] {
+            let headers = headers(&[("Transfer-Encoding", coding)]);
+            let error = read_body(&mut "".as_bytes(), &headers, &Limits::default()).unwrap_err();
+            assert!(
+                matches!(error, RequestError::UnsupportedTransferEncoding),
+                "{coding}: {error:?}"
+            );
+        }
+        let headers = headers(&[("Transfer-Encoding", "Chunked")]);
+        let raw = "0\r\n\r\n";
+        assert!(read_body(&mut raw.as_bytes(), &headers, &Limits::default()).is_ok());
+    }
+
+    #[test]
+    fn content_length_bodies_are_read_exactly() {
+        let read = |fields: &[(&str, &str)], raw: &str| {
+            read_body(&mut raw.as_bytes(), &headers(fields), &Limits::default())
+        };
+        let (body, _) = read(&[("Content-Length", "5")], "hello, and more").unwrap();
+        assert_eq!(body, b"hello");
+        let (body, _) = read(
+            &[("Content-Length", "5, 5"), ("Content-Length", "5")],
+            "hello",
+        )
+        .unwrap();
+        assert_eq!(body, b"hello");
+        let (body, _) = read(&[], "ignored").unwrap();
+        assert!(body.is_empty());
+
+        for fields in [
+            [("Content-Length", "5, 6")],
+            [("Content-Length", "-5")],
+            [("Content-Length", "")],
+            [("Content-Length", "0x5")],
+            [("Content-Length", "99999999999999999999999")],
+        ] {
+            let error = read(&fields, "hello").unwrap_err();
+            assert!(
+                matches!(error, RequestError::InvalidBody),
+                "{fields:?}: {error:?}"
+            );
+        }
+
+        let error = read(&[("Content-Length", "10")], "short").unwrap_err();
+        assert!(matches!(error, RequestError::InvalidBody), "{error:?}");
+    }
+}
@@ -0,0 +1,399 @@
+use crate::mime::is_text;
+use crate::{Body, CompressionConfig, Request, Response, StatusCode};
+
+use flate2::Compression;
+use flate2::read::{GzEncoder, ZlibEncoder};
+use std::fmt::{Display, Formatter};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+/// The content codings the server can produce, in order of preference when the client weighs
+/// them equally.
+pub static SUPPORTED_ENCODINGS: &[Encoding] = &[Encoding::Gzip, Encoding::Deflate];
+
+/// The codings of precompressed sibling files (`page.html.br`, `page.html.gz`), in order of
+/// preference when the client weighs them equally. See [`precompressed_variants`].
+pub static PRECOMPRESSED_ENCODINGS: &[Encoding] = &[Encoding::Brotli, Encoding::Gzip];
+
+/// A content coding, as named in `Accept-Encoding` and `Content-Encoding` (RFC 9110 section 8.4.1).
+///
+/// # Variants
+/// - `Gzip`: The gzip file format (RFC 1952).
+/// - `Deflate`: The zlib format (RFC 1950), which is what HTTP calls `deflate`.
+/// - `Brotli`: Brotli (RFC 7932). Only served from precompressed `.br` files.
+/// - `Identity`: No compression.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Encoding {
+    Gzip,
+    Deflate,
+    Brotli,
+    Identity,
+}
+
+impl Encoding {
+    /// Returns the coding name as it appears in header fields.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Encoding::Gzip => "gzip",
+            Encoding::Deflate => "deflate",
+            Encoding::Brotli => "br",
+            Encoding::Identity => "identity",
+        }
+    }
+
+    /// The file extension of precompressed files in this coding (`gz`, `br`), if there is one.
+    pub fn file_extension(&self) -> Option<&'static str> {
+        match self {
+            Encoding::Gzip => Some("gz"),
+            Encoding::Brotli => Some("br"),
+            Encoding::Deflate | Encoding::Identity => None,
+        }
+    }
+}
+
+impl Display for Encoding {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Picks the content coding for a response from the request's `Accept-Encoding` value.
+///
+/// Each coding in `accept_encoding` may carry a weight (`gzip;q=0.8`); codings that aren't
+/// listed take the weight of `*`, or `0` if there is no `*`. The coding from `supported` with the
+/// highest non-zero weight wins, unless `identity` (or `*`) is explicitly weighted higher; ties
+/// go to the earlier entry in `supported`.
+///
+/// # Returns
+/// - The chosen coding, or [`Encoding::Identity`] if the header is missing or nothing in
+///   `supported` is acceptable.
+pub fn negotiate_encoding(accept_encoding: Option<&str>, supported: &[Encoding]) -> Encoding {
+    let Some(accept_encoding) = accept_encoding else {
+        return Encoding::Identity;
+    };
+
+    let weights: Vec<(&str, f32)> = accept_encoding
+        .split(',')
+        .filter_map(|item| {
+            let mut parameters = item.split(';');
+            let coding = parameters.next()?.trim();
+            if coding.is_empty() {
+                return None;
+            }
+            let weight = parameters
+                .filter_map(|parameter| parameter.trim().split_once('='))
+                .find(|(name, _)| name.trim().eq_ignore_ascii_case("q"))
+                .map_or(Some(1.0), |(_, value)| value.trim().parse::<f32>().ok())?;
+            Some((coding, weight.clamp(0.0, 1.0)))
+        })
+        .collect();
+    let weight_of = |coding: &str| {
+        weights
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(coding))
+            .or_else(|| weights.iter().find(|(name, _)| *name == "*"))
+            .map(|(_, weight)| *weight)
+    };
+
+    /* identity is always acceptable, but only competes when the client gives it a weight. */
+    let identity = weight_of("identity").unwrap_or(0.0);
+    let mut best = (Encoding::Identity, 0.0);
+    for encoding in supported {
+        let weight = weight_of(encoding.as_str()).unwrap_or(0.0);
+        if weight > best.1 {
+            best = (*encoding, weight);
+        }
+    }
+
+    if best.1 > 0.0 && best.1 >= identity {
+        best.0
+    } else {
+        Encoding::Identity
+    }
+}
+
+/// Returns `true` if a body with this `Content-Type` is worth compressing: text types (see
+/// [`is_text`]) plus a few binary formats that aren't compressed already.
+pub fn is_compressible(content_type: &str) -> bool {
+    let essence = content_type.split(';').next().unwrap_or_default().trim();
+    is_text(content_type) || matches!(essence, "application/wasm" | "image/x-icon" | "image/bmp")
+}
+
+/// Compresses `response` if the client accepts it and it is worth it.
+///
+/// Only `200 OK` responses with a compressible `Content-Type` (see [`is_compressible`]), no
+/// `Content-Encoding` yet and a body of at least `config.min_size` bytes are compressed. Those
+/// always get `Vary: Accept-Encoding`, whatever coding is chosen, so caches keep the variants apart.
+///
+/// - An in-memory body is compressed up front and keeps a `Content-Length`.
+/// - A file or reader is compressed while it is sent; its compressed length isn't known in
+///   advance, so it goes out with `Transfer-Encoding: chunked`. `HTTP/1.0` clients, which
+///   don't understand chunked framing, get these uncompressed.
+/// - A strong `ETag` is turned into a weak one, because the compressed bytes differ from the
+///   file while conditional requests still compare against the file.
+///
+/// # Returns
+/// - `Ok(Response)`: The response, compressed or unchanged.
+/// - `Err(io::Error)`: If compressing an in-memory body fails.
+pub fn compress_response(
+    mut response: Response,
+    request: &Request,
+    config: &CompressionConfig,
+) -> io::Result<Response> {
+    let compressible = response
+        .headers()
+        .get("Content-Type")
+        .is_some_and(is_compressible);
+    if !config.enabled
+        || response.status() != StatusCode::Ok
+        || !compressible
+        || response.headers().contains("Content-Encoding")
+        || response
+            .body()
+            .len()?
+            .is_some_and(|length| length < config.min_size)
+    {
+        return Ok(response);
+    }
+
+    add_vary_accept_encoding(&mut response);
+    let encoding = negotiate_encoding(request.header("Accept-Encoding"), SUPPORTED_ENCODINGS);
+    /* HTTP/1.0 has no chunked framing, so only bodies compressed up front are possible there. */
+    let streamed = !matches!(response.body(), Body::Bytes(_) | Body::Empty);
+    if encoding == Encoding::Identity || (streamed && request.version() == "HTTP/1.0") {
+        return Ok(response);
+    }
+
+    let level = Compression::new(config.level);
+    let body = match response.take_body() {
+        Body::Bytes(bytes) => Body::Bytes(compress_bytes(&bytes, encoding, level)?),
+        Body::Empty => Body::Empty,
+        Body::File(file) => Body::Reader(encoder(file, encoding, level), None),
+        Body::Reader(reader, _) => Body::Reader(encoder(reader, encoding, level), None),
+    };
+
+    let headers = response.headers_mut();
+    headers.insert("Content-Encoding", encoding.as_str());
+    if let Some(etag) = headers.get("ETag").filter(|etag| etag.starts_with('"')) {
+        let weak = format!("W/{etag}");
+        headers.insert("ETag", weak);
+    }
+
+    Ok(response.with_body(body))
+}
+
+/// Finds the precompressed siblings of the file at `path`: `path` with `.br` or `.gz` appended
+/// (see [`PRECOMPRESSED_ENCODINGS`]).
+///
+/// Each sibling is canonicalized and must be a regular file inside `document_root`, the same
+/// check [`Request::path_exists`] applies, so a symlinked `.gz` can't escape the document root.
+///
+/// # Returns
+/// - The `(coding, path)` of every usable sibling, in order of preference. Empty if there are none
+///   or the document root can't be canonicalized.
+pub fn precompressed_variants(path: &Path, document_root: &Path) -> Vec<(Encoding, PathBuf)> {
+    let Some(file_name) = path.file_name() else {
+        return Vec::new();
+    };
+    let document_root = if document_root.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        document_root
+    };
+    let Ok(document_root) = document_root.canonicalize() else {
+        return Vec::new();
+    };
+
+    PRECOMPRESSED_ENCODINGS
+        .iter()
+        .filter_map(|encoding| {
+            let mut sibling_name = file_name.to_os_string();
+            sibling_name.push(".");
+            sibling_name.push(encoding.file_extension()?);
+            let sibling = path.with_file_name(sibling_name).canonicalize().ok()?;
+            (sibling.starts_with(&document_root) && sibling.is_file())
+                .then_some((*encoding, sibling))
+        })
+        .collect()
+}
+
+/// Adds `Vary: Accept-Encoding` to `response` unless it is already there.
+pub fn add_vary_accept_encoding(response: &mut Response) {
+    let varies = response.headers().get_all("Vary").any(|vary| {
+        vary.split(',')
+            .any(|field| field.trim().eq_ignore_ascii_case("Accept-Encoding"))
+    });
+    if !varies {
+        response.headers_mut().append("Vary", "Accept-Encoding");
+    }
+}
+
+/// Wraps `reader` in a streaming encoder for `encoding`.
+fn encoder<R: Read + Send + 'static>(
+    reader: R,
+    encoding: Encoding,
+    level: Compression,
+) -> Box<dyn Read + Send> {
+    match encoding {
+        Encoding::Gzip => Box::new(GzEncoder::new(reader, level)),
+        Encoding::Deflate => Box::new(ZlibEncoder::new(reader, level)),
+        /* Brotli is never negotiated here, see SUPPORTED_ENCODINGS. */
+        Encoding::Brotli | Encoding::Identity => Box::new(reader),
+    }
+}
+
+/// Compresses an in-memory buffer with `encoding`.
+fn compress_bytes(bytes: &[u8], encoding: Encoding, level: Compression) -> io::Result<Vec<u8>> {
+    match encoding {
+        Encoding::Gzip => {
+            let mut encoder = flate2::write::GzEncoder::new(Vec::new(), level);
+            encoder.write_all(bytes)?;
+            encoder.finish()
+        }
+        Encoding::Deflate => {
+            let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), level);
+            encoder.write_all(bytes)?;
+            encoder.finish()
+        }
+        Encoding::Brotli | Encoding::Identity => Ok(bytes.to_vec()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+
+    fn negotiate(accept_encoding: &str) -> Encoding {
+        negotiate_encoding(Some(accept_encoding), SUPPORTED_ENCODINGS)
+    }
+
+    fn precompressed(accept_encoding: &str) -> Encoding {
+        negotiate_encoding(Some(accept_encoding), PRECOMPRESSED_ENCODINGS)
+    }
+
+    #[test]
+    fn no_header_or_nothing_acceptable_is_identity() {
+        assert_eq!(
+            negotiate_encoding(None, SUPPORTED_ENCODINGS),
+            Encoding::Identity
+        );
+        assert_eq!(negotiate(""), Encoding::Identity);
+        assert_eq!(negotiate("br, zstd"), Encoding::Identity);
+        assert_eq!(negotiate("identity"), Encoding::Identity);
+        assert_eq!(negotiate_encoding(Some("gzip"), &[]), Encoding::Identity);
+    }
+
+    #[test]
+    fn the_highest_q_value_wins() {
+        assert_eq!(negotiate("gzip"), Encoding::Gzip);
+        assert_eq!(negotiate("deflate"), Encoding::Deflate);
+        assert_eq!(negotiate("gzip;q=0.5, deflate;q=0.8"), Encoding::Deflate);
+        assert_eq!(negotiate("deflate;q=0.5, gzip"), Encoding::Gzip);
+        assert_eq!(negotiate("GZIP ; Q=0.9 , deflate;q=0.1"), Encoding::Gzip);
+        /* Out of range weights are clamped, unparsable ones drop the coding. */
+        assert_eq!(negotiate("gzip;q=7, deflate"), Encoding::Gzip);
+        assert_eq!(negotiate("gzip;q=high, deflate;q=0.1"), Encoding::Deflate);
+    }
+
+    #[test]
+    fn ties_go_to_the_server_preference() {
+        assert_eq!(negotiate("deflate, gzip"), Encoding::Gzip);
+        assert_eq!(negotiate("deflate;q=0.5, gzip;q=0.5"), Encoding::Gzip);
+    }
+
+    #[test]
+    fn q_zero_refuses_a_coding() {
+        assert_eq!(negotiate("gzip;q=0, deflate"), Encoding::Deflate);
+        assert_eq!(negotiate("gzip;q=0, deflate;q=0.0"), Encoding::Identity);
+        assert_eq!(negotiate("gzip;q=0"), Encoding::Identity);
+    }
+
+    #[test]
+    fn identity_competes_only_when_weighted() {
+        assert_eq!(negotiate("gzip;q=0.5, identity"), Encoding::Identity);
+        assert_eq!(negotiate("gzip, identity;q=0.5"), Encoding::Gzip);
+        assert_eq!(negotiate("gzip;q=0.5, identity;q=0.5"), Encoding::Gzip);
+        /* With identity refused and nothing else acceptable, identity is still all there is. */
+        assert_eq!(negotiate("identity;q=0"), Encoding::Identity);
+        assert_eq!(negotiate("identity;q=0, deflate"), Encoding::Deflate);
+    }
+
+    #[test]
+    fn star_weights_every_coding_not_listed() {
+        assert_eq!(negotiate("*"), Encoding::Gzip);
+        assert_eq!(negotiate("gzip;q=0, *"), Encoding::Deflate);
+        assert_eq!(negotiate("*;q=0"), Encoding::Identity);
+        assert_eq!(negotiate("deflate, *;q=0"), Encoding::Deflate);
+        assert_eq!(negotiate("gzip;q=0.5, *;q=0.9"), Encoding::Deflate);
+        /* `*` weights identity too when it isn't listed; a tie still goes to compression. */
+        assert_eq!(negotiate("gzip;q=0.5, *"), Encoding::Deflate);
+        assert_eq!(
+            negotiate("gzip;q=0.5, deflate;q=0.5, *"),
+            Encoding::Identity
+        );
+    }
+
+    #[test]
+    fn precompressed_siblings_prefer_brotli_unless_weighted_lower() {
+        assert_eq!(precompressed("gzip, deflate, br"), Encoding::Brotli);
+        assert_eq!(precompressed("br;q=0.5, gzip"), Encoding::Gzip);
+        assert_eq!(precompressed("deflate"), Encoding::Identity);
+        assert_eq!(
+            negotiate_encoding(Some("gzip, br"), &[Encoding::Gzip]),
+            Encoding::Gzip
+        );
+        assert_eq!(
+            negotiate_encoding(Some("br"), &[Encoding::Gzip]),
+            Encoding::Identity
+        );
+    }
+
+    #[test]
+    fn precompressed_variants_are_files_inside_the_root() {
+        let root =
+            std::env::temp_dir().join(format!("rust_server_precompressed_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("dir.gz")).unwrap();
+        fs::write(root.join("page.html"), "page").unwrap();
+        fs::write(root.join("page.html.gz"), "gz").unwrap();
+        fs::write(root.join("page.html.br"), "br").unwrap();
+        fs::write(root.join("plain.txt"), "plain").unwrap();
+        fs::write(root.join("dir"), "file").unwrap();
+
+        let variants = precompressed_variants(&root.join("page.html"), &root)
+            .into_iter()
+            .map(|(encoding, path)| (encoding, path.file_name().unwrap().to_owned()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            variants,
+            [
+                (Encoding::Brotli, "page.html.br".into()),
+                (Encoding::Gzip, "page.html.gz".into()),
+            ]
+        );
+        assert!(precompressed_variants(&root.join("plain.txt"), &root).is_empty());
+        /* A directory named like a sibling is not a variant. */
+        assert!(precompressed_variants(&root.join("dir"), &root).is_empty());
+        /* Siblings outside the document root are never used. */
+        let outside = root.join("page.html");
+        assert!(precompressed_variants(&outside, &root.join("dir.gz")).is_empty());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn vary_is_added_once() {
+        let mut response =
+            Response::new(StatusCode::Ok).with_header("Vary", "Origin, accept-encoding");
+        add_vary_accept_encoding(&mut response);
+        assert_eq!(response.headers().get_all("Vary").count(), 1);
+
+        let mut response = Response::new(StatusCode::Ok).with_header("Vary", "Origin");
+        add_vary_accept_encoding(&mut response);
+        add_vary_accept_encoding(&mut response);
+        let vary = response.headers().get_all("Vary").collect::<Vec<_>>();
+        assert_eq!(vary, ["Origin", "Accept-Encoding"]);
+    }
+}
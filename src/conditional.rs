@@ -0,0 +1,362 @@
+use crate::{Method, Request, Response, StatusCode, http_date, parse_http_date};
+
+use std::fs::Metadata;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The cache validators of a static file: a strong `ETag` and the `Last-Modified` time.
+///
+/// The entity tag is derived from the file size and modification time (with sub-second
+/// precision where the filesystem has it), so it changes whenever the file is rewritten without
+/// having to hash the contents on every request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Validators {
+    etag: String,
+    last_modified: SystemTime,
+}
+
+impl Validators {
+    /// Builds the validators for a file from its metadata.
+    ///
+    /// # Returns
+    /// - `Some(Validators)`: If the platform reports a modification time.
+    /// - `None`: Otherwise. The file is then served without validators.
+    pub fn from_metadata(metadata: &Metadata) -> Option<Self> {
+        let modified = metadata.modified().ok()?;
+        let since_epoch = modified
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or(Duration::ZERO);
+        let etag = format!(
+            "\"{:x}-{:x}-{:x}\"",
+            since_epoch.as_secs(),
+            since_epoch.subsec_nanos(),
+            metadata.len()
+        );
+
+        /* HTTP dates have whole seconds, and a file can't claim to be modified in the future. */
+        let last_modified = UNIX_EPOCH + Duration::from_secs(since_epoch.as_secs()).min(unix_now());
+
+        Some(Self {
+            etag,
+            last_modified,
+        })
+    }
+
+    /// The strong entity tag, including its double quotes.
+    pub fn etag(&self) -> &str {
+        &self.etag
+    }
+
+    /// The modification time, truncated to whole seconds.
+    pub fn last_modified(&self) -> SystemTime {
+        self.last_modified
+    }
+
+    /// Adds the `ETag` and `Last-Modified` header fields to `response`.
+    ///
+    /// A response that already has a `Content-Encoding` (a precompressed sibling) gets the weak
+    /// form of the tag: its bytes differ from the file the tag was derived from.
+    pub fn apply(&self, response: Response) -> Response {
+        let etag = if response.headers().contains("Content-Encoding") {
+            format!("W/{}", self.etag)
+        } else {
+            self.etag.clone()
+        };
+        response
+            .with_header("ETag", etag)
+            .with_header("Last-Modified", http_date(self.last_modified))
+    }
+
+    /// Evaluates the conditional header fields of `request` against these validators,
+    /// in the order given by RFC 9110 section 13.2.2.
+    ///
+    /// 1. `If-Match` fails unless one of its tags strongly matches the `ETag` (or it is `*`).
+    /// 2. Without `If-Match`, `If-Unmodified-Since` fails if the file changed after the given date.
+    /// 3. `If-None-Match` fails if one of its tags weakly matches the `ETag` (or it is `*`).
+    /// 4. Without `If-None-Match`, `If-Modified-Since` fails for `GET` and `HEAD` if the file has
+    ///    not changed since the given date.
+    ///
+    /// Dates that can't be parsed are ignored, as the RFC requires.
+    ///
+    /// # Returns
+    /// - `None`: If every precondition holds and the request should be served normally.
+    /// - `Some(StatusCode::NotModified)`: If a `GET` or `HEAD` can be answered with `304`.
+    /// - `Some(StatusCode::PreconditionFailed)`: If the request must be answered with `412`.
+    pub fn evaluate(&self, request: &Request) -> Option<StatusCode> {
+        let headers = request.headers();
+        let safe = matches!(request.method(), Method::Get | Method::Head);
+
+        if headers.contains("If-Match") {
+            let if_match = headers.get_all("If-Match").collect::<Vec<_>>().join(",");
+            if !etag_matches(&if_match, &self.etag, true) {
+                return Some(StatusCode::PreconditionFailed);
+            }
+        } else if let Some(date) = headers.get("If-Unmodified-Since").and_then(parse_http_date)
+            && self.last_modified > date
+        {
+            return Some(StatusCode::PreconditionFailed);
+        }
+
+        if headers.contains("If-None-Match") {
+            let if_none_match = headers
+                .get_all("If-None-Match")
+                .collect::<Vec<_>>()
+                .join(",");
+            if etag_matches(&if_none_match, &self.etag, false) {
+                return Some(if safe {
+                    StatusCode::NotModified
+                } else {
+                    StatusCode::PreconditionFailed
+                });
+            }
+        } else if let Some(date) = headers.get("If-Modified-Since").and_then(parse_http_date)
+            && safe
+            && self.last_modified <= date
+        {
+            return Some(StatusCode::NotModified);
+        }
+
+        None
+    }
+
+    /// Returns `true` if the `If-Range` value `value` still describes this file, so a `Range`
+    /// request may be answered with part of it (RFC 9110 section 13.1.5).
+    ///
+    /// - An entity tag must strongly match the `ETag`; weak tags never match.
+    /// - A date must equal `Last-Modified` exactly.
+    pub fn matches_if_range(&self, value: &str) -> bool {
+        let value = value.trim();
+        if value.starts_with('"') || value.starts_with("W/") {
+            value == self.etag
+        } else {
+            parse_http_date(value) == Some(self.last_modified)
+        }
+    }
+}
+
+/// The header fields a `304 Not Modified` repeats from the `200 OK` it stands for
+/// (RFC 9110 section 15.4.5).
+pub static NOT_MODIFIED_HEADERS: &[&str] = &[
+    "Cache-Control",
+    "Content-Location",
+    "ETag",
+    "Expires",
+    "Last-Modified",
+    "Vary",
+];
+
+/// Builds the `304 Not Modified` answer for a request whose `200 OK` would be `response`.
+///
+/// The `304` carries the [`NOT_MODIFIED_HEADERS`] of `response`, so the client gets the very
+/// `ETag` and `Vary` it stored with the `200`: weak if the `200` was compressed, with
+/// `Vary: Accept-Encoding` if it had one. The body of `response` is dropped unread.
+pub fn not_modified(response: &Response) -> Response {
+    let mut not_modified = Response::new(StatusCode::NotModified);
+    for (name, value) in response.headers().iter() {
+        if NOT_MODIFIED_HEADERS
+            .iter()
+            .any(|kept| kept.eq_ignore_ascii_case(name))
+        {
+            not_modified.headers_mut().append(name, value);
+        }
+    }
+    not_modified
+}
+
+/// Returns `true` if the `If-Match`/`If-None-Match` value `list` matches `etag`.
+///
+/// `list` is either `*` or a comma-separated list of entity tags such as `"abc", W/"def"`.
+/// With `strong` set, weak tags (`W/"..."`) never match (strong comparison); otherwise the
+/// `W/` prefix is ignored (weak comparison). See RFC 9110 section 8.8.3.2.
+fn etag_matches(list: &str, etag: &str, strong: bool) -> bool {
+    if list.trim() == "*" {
+        return true;
+    }
+
+    let mut rest = list;
+    loop {
+        rest = rest.trim_start_matches(|c: char| c == ',' || c.is_ascii_whitespace());
+        if rest.is_empty() {
+            return false;
+        }
+
+        let weak = rest.starts_with("W/");
+        let tag_start = if weak { &rest[2..] } else { rest };
+        /* A malformed list can't be trusted to match anything. */
+        let Some(tag_body) = tag_start.strip_prefix('"') else {
+            return false;
+        };
+        let Some(end) = tag_body.find('"') else {
+            return false;
+        };
+        let tag = &tag_start[..end + 2];
+
+        if tag == etag && !(strong && weak) {
+            return true;
+        }
+        rest = &tag_body[end + 1..];
+    }
+}
+
+/// The current time as a duration since the Unix epoch.
+fn unix_now() -> Duration {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or(Duration::ZERO)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::BufReader;
+
+    /// Validators for a file last modified at `Sun, 06 Nov 1994 08:49:37 GMT`.
+    fn validators() -> Validators {
+        Validators {
+            etag: "\"abc\"".to_string(),
+            last_modified: UNIX_EPOCH + Duration::from_secs(784_111_777),
+        }
+    }
+
+    /// Evaluates the validators against `method /` with the header lines `head`.
+    fn evaluate(method: &str, head: &str) -> Option<StatusCode> {
+        let raw = format!("{method} / HTTP/1.1\r\nHost: localhost\r\n{head}\r\n");
+        let request = Request::new(&mut BufReader::new(raw.as_bytes())).unwrap();
+        validators().evaluate(&request)
+    }
+
+    #[test]
+    fn if_none_match_compares_weakly() {
+        for value in ["\"abc\"", "W/\"abc\"", "\"x\", W/\"abc\"", "*"] {
+            assert_eq!(
+                evaluate("GET", &format!("If-None-Match: {value}\r\n")),
+                Some(StatusCode::NotModified),
+                "{value}"
+            );
+        }
+        assert_eq!(evaluate("GET", "If-None-Match: \"abd\"\r\n"), None);
+        assert_eq!(
+            evaluate("HEAD", "If-None-Match: *\r\n"),
+            Some(StatusCode::NotModified)
+        );
+        /* Other methods fail the precondition instead. */
+        assert_eq!(
+            evaluate("POST", "If-None-Match: *\r\n"),
+            Some(StatusCode::PreconditionFailed)
+        );
+    }
+
+    #[test]
+    fn if_match_compares_strongly() {
+        assert_eq!(evaluate("GET", "If-Match: \"abc\"\r\n"), None);
+        assert_eq!(evaluate("GET", "If-Match: \"x\", \"abc\"\r\n"), None);
+        assert_eq!(evaluate("GET", "If-Match: *\r\n"), None);
+        for value in ["W/\"abc\"", "\"abd\"", "abc"] {
+            assert_eq!(
+                evaluate("GET", &format!("If-Match: {value}\r\n")),
+                Some(StatusCode::PreconditionFailed),
+                "{value}"
+            );
+        }
+    }
+
+    #[test]
+    fn if_none_match_takes_precedence_over_if_modified_since() {
+        /* The date alone would give 304, but the tag doesn't match. */
+        assert_eq!(
+            evaluate(
+                "GET",
+                "If-None-Match: \"other\"\r\nIf-Modified-Since: Sun, 06 Nov 1994 08:49:37 GMT\r\n"
+            ),
+            None
+        );
+        assert_eq!(
+            evaluate(
+                "GET",
+                "If-Modified-Since: Sun, 06 Nov 1994 08:49:37 GMT\r\n"
+            ),
+            Some(StatusCode::NotModified)
+        );
+        assert_eq!(
+            evaluate(
+                "GET",
+                "If-Modified-Since: Sun, 06 Nov 1994 08:49:36 GMT\r\n"
+            ),
+            None
+        );
+        assert_eq!(evaluate("GET", "If-Modified-Since: yesterday\r\n"), None);
+    }
+
+    #[test]
+    fn if_match_takes_precedence_over_if_unmodified_since() {
+        assert_eq!(
+            evaluate(
+                "GET",
+                "If-Match: \"abc\"\r\nIf-Unmodified-Since: Sat, 05 Nov 1994 00:00:00 GMT\r\n"
+            ),
+            None
+        );
+        assert_eq!(
+            evaluate(
+                "GET",
+                "If-Unmodified-Since: Sat, 05 Nov 1994 00:00:00 GMT\r\n"
+            ),
+            Some(StatusCode::PreconditionFailed)
+        );
+    }
+
+    #[test]
+    fn if_range_needs_a_strong_tag_or_the_exact_date() {
+        let validators = validators();
+        assert!(validators.matches_if_range("\"abc\""));
+        assert!(!validators.matches_if_range("W/\"abc\""));
+        assert!(!validators.matches_if_range("\"abd\""));
+        assert!(validators.matches_if_range("Sun, 06 Nov 1994 08:49:37 GMT"));
+        assert!(!validators.matches_if_range("Sun, 06 Nov 1994 08:49:38 GMT"));
+        assert!(!validators.matches_if_range("not a date"));
+    }
+
+    #[test]
+    fn etag_lists_skip_empty_entries_and_malformed_ones_never_match() {
+        assert!(etag_matches(" \"a\" ,, W/\"abc\"", "\"abc\"", false));
+        assert!(!etag_matches("\"a\", W/\"abc\"", "\"abc\"", true));
+        assert!(!etag_matches("abc", "\"abc\"", false));
+        assert!(!etag_matches("\"abc", "\"abc\"", false));
+        assert!(!etag_matches("", "\"abc\"", false));
+    }
+
+    #[test]
+    fn compressed_responses_get_the_weak_tag() {
+        let response = validators().apply(Response::new(StatusCode::Ok));
+        assert_eq!(response.headers().get("ETag"), Some("\"abc\""));
+        assert_eq!(
+            response.headers().get("Last-Modified"),
+            Some("Sun, 06 Nov 1994 08:49:37 GMT")
+        );
+
+        let compressed = Response::new(StatusCode::Ok).with_header("Content-Encoding", "br");
+        let response = validators().apply(compressed);
+        assert_eq!(response.headers().get("ETag"), Some("W/\"abc\""));
+    }
+
+    #[test]
+    fn not_modified_repeats_the_etag_and_vary_of_the_compressed_200() {
+        let ok = Response::new(StatusCode::Ok)
+            .with_header("Content-Type", "text/plain")
+            .with_header("Content-Encoding", "gzip")
+            .with_header("ETag", "W/\"5f-0-400\"")
+            .with_header("Last-Modified", "Thu, 01 Jan 1970 00:01:35 GMT")
+            .with_header("Vary", "Accept-Encoding")
+            .with_bytes("compressed bytes");
+
+        let response = not_modified(&ok);
+        assert_eq!(response.status(), StatusCode::NotModified);
+        assert_eq!(response.headers().get("ETag"), Some("W/\"5f-0-400\""));
+        assert_eq!(response.headers().get("Vary"), Some("Accept-Encoding"));
+        assert_eq!(
+            response.headers().get("Last-Modified"),
+            Some("Thu, 01 Jan 1970 00:01:35 GMT")
+        );
+        assert_eq!(response.headers().get("Content-Type"), None);
+        assert_eq!(response.headers().get("Content-Encoding"), None);
+    }
+}
@@ -0,0 +1,1117 @@
+use crate::{
+    ACCESS_LOG_MAX_FILES, ACCESS_LOG_MAX_SIZE, ADDRESS, BASE_DIR, BODY_TIMEOUT, CLIENT_IP_HEADER,
+    COMPRESSION_LEVEL, COMPRESSION_MIN_SIZE, ERROR_PAGE_DIR, HEADERS_TIMEOUT, KEEP_ALIVE_TIMEOUT,
+    LIVENESS_PATH, LogFormat, MAX_BODY_SIZE, MAX_CONNECTIONS_PER_IP, MAX_DURATION,
+    MAX_HEADER_COUNT, MAX_HEADER_LINE_LENGTH, MAX_KEEP_ALIVE_REQUESTS, MAX_REQUEST_LINE_LENGTH,
+    METRICS_PATH, MIN_RATE_GRACE_PERIOD, MIN_TRANSFER_RATE, MimeTypes, QUEUE_CAPACITY,
+    QUEUE_POLICY, QueuePolicy, RATE_LIMIT_BURST, RATE_LIMIT_MAX_CLIENTS,
+    RATE_LIMIT_REQUESTS_PER_SECOND, READINESS_PATH, REQUEST_LINE_TIMEOUT, SHUTDOWN_TIMEOUT,
+    TLS_RELOAD_INTERVAL, WORKER_COUNT, WRITE_TIMEOUT,
+};
+
+use serde::{Deserialize, Deserializer};
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::io;
+use std::net::{IpAddr, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+/// The configuration file read when neither `--config` nor `RUST_SERVER_CONFIG` names one.
+/// A missing default file is not an error; the built-in defaults are used instead.
+pub static DEFAULT_CONFIG_FILE: &str = "server.toml";
+
+/// Prefix of the environment variables that override configuration values.
+pub static ENV_PREFIX: &str = "RUST_SERVER_";
+
+/// Command-line help, printed for `--help`.
+pub static USAGE: &str = "\
+Usage: rust_server [--config <file>] [--<key> <value>]...
+
+Values are read from the built-in defaults, then the TOML config file (default: server.toml),
+then RUST_SERVER_<KEY> environment variables, then command-line flags. Later sources win.
+
+Keys:
+  --config <file>                   TOML configuration file
+  --address <host:port>             Listen address
+  --document-root <dir>             Directory the static files are served from
+  --workers <n>                     Number of worker threads
+  --queue-capacity <n>              Connections waiting for a free worker
+  --queue-policy <block|reject>     What to do when the queue is full
+  --keep-alive-timeout <seconds>    Idle time before a persistent connection is closed
+  --shutdown-timeout <seconds>      How long a shutdown waits for in-flight connections
+  --request-line-timeout <seconds>  Time to send the request line before a 408
+  --headers-timeout <seconds>       Time to send the whole request head before a 408
+  --body-timeout <seconds>          Longest pause while sending the request body before a 408
+  --write-timeout <seconds>         Longest a response write may block before disconnecting
+  --min-transfer-rate <bytes>       Minimum bytes per second for bodies and responses (0: off)
+  --min-rate-grace <seconds>        Time before the minimum transfer rate is enforced
+  --max-request-line-length <bytes> Longer request lines get 414
+  --max-header-count <n>            More header fields get 431
+  --max-header-line-length <bytes>  Longer header lines get 431
+  --max-body-size <bytes>           Larger request bodies get 413
+  --max-keep-alive-requests <n>     Requests served per persistent connection
+  --error-page-dir <dir>            Directory holding error<code>.html pages
+  --compression <true|false>        Compress responses with gzip or deflate when the client accepts it
+  --compression-min-size <bytes>    Smaller responses are sent uncompressed
+  --compression-level <0-9>         Compression level
+  --precompressed <true|false>      Serve <file>.br / <file>.gz siblings when the client accepts them
+  --listings <true|false>           List directories that have no index.html
+  --log-requests <true|false>       Write an access log line for every request
+  --log-format <format>             Access log format: common, combined or json
+  --log-file <file>                 Append the access log to this file (`-`: stdout)
+  --log-max-size <bytes>            Rotate the access log file at this size (0: never)
+  --log-max-files <n>               Rotated access log files kept
+  --health <true|false>             Answer the liveness and readiness probes
+  --liveness-path <path>            Path of the liveness probe
+  --readiness-path <path>           Path of the readiness probe
+  --tls <true|false>                Serve HTTPS (needs the `tls` cargo feature)
+  --tls-cert <file>                 PEM certificate chain of the default certificate
+  --tls-key <file>                  PEM private key of the default certificate
+  --tls-reload-interval <seconds>   How often changed certificate files are reloaded (0: never)
+  --tls-redirect-address <host:port>  Redirect plain HTTP on this address to HTTPS (`-`: off)
+  --metrics <true|false>            Serve Prometheus metrics
+  --metrics-path <path>             Path the metrics are served on
+  --metrics-address <host:port>     Serve the metrics on this separate admin listener (`-`: main listener)
+  --rate-limit <true|false>         Limit requests and connections per client IP
+  --rate-limit-rps <n>              Average requests per second per client IP
+  --rate-limit-burst <n>            Requests per client IP allowed at once
+  --max-connections-per-ip <n>      Open connections per client IP (0: no cap)
+  --rate-limit-max-clients <n>      Client IPs the rate limiter keeps track of
+  --trusted-proxies <ip,...>        Proxies whose client IP header is believed
+  --client-ip-header <name>         Header holding the client IP behind a trusted proxy
+";
+
+/// Errors that can occur while loading or validating a [`ServerConfig`].
+///
+/// # Variants
+/// - `Help`: `--help` was given. Not a failure; the caller should print [`USAGE`].
+/// - `Read`: The configuration file could not be read.
+/// - `Parse`: The configuration file is not valid TOML or has unknown keys or wrongly typed values.
+/// - `UnknownKey`: A flag or `RUST_SERVER_*` variable names a key that doesn't exist.
+/// - `MissingValue`: A flag was given without a value.
+/// - `InvalidValue`: A flag or environment value could not be parsed.
+/// - `Invalid`: The merged configuration failed validation. Lists every problem found.
+#[derive(Debug, thiserror::Error)]
+pub enum ConfigError {
+    #[error("Help requested.")]
+    Help,
+    #[error("Could not read config file `{path}`: {source}")]
+    Read { path: PathBuf, source: io::Error },
+    #[error("Invalid config file `{path}`: {source}")]
+    Parse {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+    #[error("Unknown configuration key `{0}`.")]
+    UnknownKey(String),
+    #[error("Missing value for `--{0}`.")]
+    MissingValue(String),
+    #[error("Invalid value `{value}` for `{key}`: {reason}")]
+    InvalidValue {
+        key: String,
+        value: String,
+        reason: String,
+    },
+    #[error("Invalid configuration:\n  - {}", .0.join("\n  - "))]
+    Invalid(Vec<String>),
+}
+
+/// Everything that can be tuned about a running [`crate::Server`].
+///
+/// Every field has a default (see [`crate::constants`]), so a config file only needs the keys it changes.
+///
+/// ```toml
+/// address = "0.0.0.0:8080"
+/// document_root = "public"
+/// workers = 8
+///
+/// [timeouts]
+/// keep_alive = 10
+///
+/// [error_pages]
+/// 404 = "public/not-found.html"
+///
+/// [mime_types]
+/// log = "text/plain"
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    /// Listen address, e.g. `127.0.0.1:7878`.
+    pub address: String,
+    /// Directory the static files are served from.
+    pub document_root: PathBuf,
+    /// Number of worker threads.
+    pub workers: usize,
+    /// Maximum number of accepted connections waiting for a free worker.
+    pub queue_capacity: usize,
+    /// What happens to a new connection when the queue is full.
+    #[serde(deserialize_with = "from_str")]
+    pub queue_policy: QueuePolicy,
+    pub timeouts: TimeoutConfig,
+    pub limits: Limits,
+    /// Directory holding the error pages, named `error<code>.html`.
+    pub error_page_dir: PathBuf,
+    /// Error pages for specific status codes, overriding the ones in `error_page_dir`.
+    #[serde(deserialize_with = "error_pages")]
+    pub error_pages: HashMap<u16, PathBuf>,
+    /// Extension to MIME type table: the built-in table plus the overrides from the config file.
+    #[serde(deserialize_with = "mime_types")]
+    pub mime_types: MimeTypes,
+    pub compression: CompressionConfig,
+    pub listings: ListingConfig,
+    pub logging: LoggingConfig,
+    pub health: HealthConfig,
+    pub metrics: MetricsConfig,
+    pub tls: TlsConfig,
+    pub rate_limit: RateLimitConfig,
+}
+
+/// Connection timeouts. In the config file they are given in seconds (fractions allowed).
+///
+/// A client too slow to send its request (the request line, the head or the body) gets
+/// `408 Request Timeout` and the connection is closed. A client too slow to read the response
+/// is disconnected.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TimeoutConfig {
+    /// How long a connection may sit idle waiting for its next request.
+    #[serde(deserialize_with = "seconds")]
+    pub keep_alive: Duration,
+    /// How long a shutdown waits for in-flight connections.
+    #[serde(deserialize_with = "seconds")]
+    pub shutdown: Duration,
+    /// Time to send the request line, from the connection (or, on a persistent connection, from
+    /// the first byte of the request).
+    #[serde(deserialize_with = "seconds")]
+    pub request_line: Duration,
+    /// Time to send the whole request head, counted like `request_line`.
+    #[serde(deserialize_with = "seconds")]
+    pub headers: Duration,
+    /// The longest pause between two reads of the request body.
+    #[serde(deserialize_with = "seconds")]
+    pub body: Duration,
+    /// The longest a single write of the response may block.
+    #[serde(deserialize_with = "seconds")]
+    pub write: Duration,
+    /// Minimum average rate in bytes per second of request bodies and responses. `0` disables it.
+    pub min_rate: u64,
+    /// How long a body or response transfer may run before `min_rate` is enforced.
+    #[serde(deserialize_with = "seconds")]
+    pub min_rate_grace: Duration,
+}
+
+/// Size and count limits applied while reading requests.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Limits {
+    pub max_request_line_length: usize,
+    pub max_header_count: usize,
+    pub max_header_line_length: usize,
+    pub max_body_size: usize,
+    pub max_keep_alive_requests: usize,
+}
+
+/// On-the-fly response compression, see [`crate::compression`].
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CompressionConfig {
+    /// Compress responses when the client accepts gzip or deflate.
+    pub enabled: bool,
+    /// Responses smaller than this many bytes are sent uncompressed.
+    pub min_size: u64,
+    /// Compression level, `0..=9`.
+    pub level: u32,
+    /// Serve `<file>.br` / `<file>.gz` siblings when they exist and the client accepts them.
+    pub precompressed: bool,
+}
+
+/// Auto-generated directory listings for directories without an `index.html`, see [`crate::listing`].
+///
+/// Listings are off unless `enabled` is set (every directory) or the directory is one of
+/// `directories` or below one of them.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ListingConfig {
+    /// List every directory under the document root.
+    pub enabled: bool,
+    /// Directories, relative to the document root, whose contents (and subdirectories) are listed.
+    pub directories: Vec<PathBuf>,
+}
+
+/// The access log, see [`crate::AccessLog`].
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoggingConfig {
+    /// Write an access log line for every request.
+    pub requests: bool,
+    /// The line format: `common`, `combined` or `json`.
+    #[serde(deserialize_with = "from_str")]
+    pub format: LogFormat,
+    /// Append to this file instead of writing to stdout.
+    pub file: Option<PathBuf>,
+    /// Rotate the file once it grows past this many bytes. `0` never rotates.
+    pub max_size: u64,
+    /// How many rotated files (`<file>.1`, `<file>.2`, ...) are kept.
+    pub max_files: usize,
+}
+
+/// The liveness and readiness probes, see [`crate::Health`].
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HealthConfig {
+    /// Answer the probes. Their paths are not served from the document root then.
+    pub enabled: bool,
+    /// Path of the liveness probe, e.g. `/healthz`.
+    pub liveness_path: String,
+    /// Path of the readiness probe, e.g. `/readyz`.
+    pub readiness_path: String,
+}
+
+/// HTTPS, with the `tls` cargo feature. See [`crate::tls`].
+///
+/// ```toml
+/// [tls]
+/// enabled = true
+/// cert = "certs/default.pem"
+/// key = "certs/default.key"
+///
+/// [[tls.certificates]]
+/// names = ["example.com", "*.example.com"]
+/// cert = "certs/example.pem"
+/// key = "certs/example.key"
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TlsConfig {
+    /// Serve HTTPS on `address` instead of plain HTTP.
+    pub enabled: bool,
+    /// PEM certificate chain sent to clients that ask for no name or a name without its own certificate.
+    pub cert: Option<PathBuf>,
+    /// PEM private key of `cert`.
+    pub key: Option<PathBuf>,
+    /// Certificates chosen by the SNI server name.
+    pub certificates: Vec<SniCertificate>,
+    /// How often the certificate and key files are checked and reloaded when changed. `0` never checks.
+    #[serde(deserialize_with = "seconds")]
+    pub reload_interval: Duration,
+    /// Plain HTTP listener that redirects every request to HTTPS.
+    pub redirect_address: Option<String>,
+}
+
+/// Per-client-IP limits, see [`crate::RateLimiter`].
+///
+/// ```toml
+/// [rate_limit]
+/// enabled = true
+/// requests_per_second = 5
+/// burst = 10
+/// trusted_proxies = ["127.0.0.1"]
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitConfig {
+    /// Limit every client IP to the rates below.
+    pub enabled: bool,
+    /// Average requests per second a client IP may make.
+    pub requests_per_second: f64,
+    /// Requests a client IP may make at once before the average rate applies.
+    pub burst: u32,
+    /// Connections a client IP may hold open at once. `0` means no cap.
+    pub max_connections_per_ip: usize,
+    /// Client IPs the limiter keeps state for, which bounds its memory.
+    pub max_clients: usize,
+    /// Proxies whose `client_ip_header` is believed. Requests from anywhere else are keyed by
+    /// the address of their connection.
+    pub trusted_proxies: Vec<IpAddr>,
+    /// Header holding the client address behind a trusted proxy, e.g. `X-Forwarded-For`.
+    pub client_ip_header: String,
+}
+
+/// A certificate for specific server names, see [`TlsConfig::certificates`].
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SniCertificate {
+    /// Server names (`example.com`) or one-label wildcards (`*.example.com`).
+    pub names: Vec<String>,
+    /// PEM certificate chain.
+    pub cert: PathBuf,
+    /// PEM private key.
+    pub key: PathBuf,
+}
+
+/// The Prometheus metrics endpoint, see [`crate::Metrics`].
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MetricsConfig {
+    /// Collect metrics and serve them on `path`.
+    pub enabled: bool,
+    /// Path the metrics are served on, e.g. `/metrics`.
+    pub path: String,
+    /// Serve the metrics on a separate admin listener at this address instead of the main one.
+    pub address: Option<String>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            address: ADDRESS.to_string(),
+            document_root: PathBuf::from(BASE_DIR),
+            workers: WORKER_COUNT,
+            queue_capacity: QUEUE_CAPACITY,
+            queue_policy: QUEUE_POLICY,
+            timeouts: TimeoutConfig::default(),
+            limits: Limits::default(),
+            error_page_dir: PathBuf::from(ERROR_PAGE_DIR),
+            error_pages: HashMap::new(),
+            mime_types: MimeTypes::new(),
+            compression: CompressionConfig::default(),
+            listings: ListingConfig::default(),
+            logging: LoggingConfig::default(),
+            health: HealthConfig::default(),
+            metrics: MetricsConfig::default(),
+            tls: TlsConfig::default(),
+            rate_limit: RateLimitConfig::default(),
+        }
+    }
+}
+
+impl Default for TimeoutConfig {
+    fn default() -> Self {
+        Self {
+            keep_alive: KEEP_ALIVE_TIMEOUT,
+            shutdown: SHUTDOWN_TIMEOUT,
+            request_line: REQUEST_LINE_TIMEOUT,
+            headers: HEADERS_TIMEOUT,
+            body: BODY_TIMEOUT,
+            write: WRITE_TIMEOUT,
+            min_rate: MIN_TRANSFER_RATE,
+            min_rate_grace: MIN_RATE_GRACE_PERIOD,
+        }
+    }
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_request_line_length: MAX_REQUEST_LINE_LENGTH,
+            max_header_count: MAX_HEADER_COUNT,
+            max_header_line_length: MAX_HEADER_LINE_LENGTH,
+            max_body_size: MAX_BODY_SIZE,
+            max_keep_alive_requests: MAX_KEEP_ALIVE_REQUESTS,
+        }
+    }
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            min_size: COMPRESSION_MIN_SIZE,
+            level: COMPRESSION_LEVEL,
+            precompressed: true,
+        }
+    }
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            requests: true,
+            format: LogFormat::default(),
+            file: None,
+            max_size: ACCESS_LOG_MAX_SIZE,
+            max_files: ACCESS_LOG_MAX_FILES,
+        }
+    }
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            liveness_path: LIVENESS_PATH.to_string(),
+            readiness_path: READINESS_PATH.to_string(),
+        }
+    }
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            path: METRICS_PATH.to_string(),
+            address: None,
+        }
+    }
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            requests_per_second: RATE_LIMIT_REQUESTS_PER_SECOND,
+            burst: RATE_LIMIT_BURST,
+            max_connections_per_ip: MAX_CONNECTIONS_PER_IP,
+            max_clients: RATE_LIMIT_MAX_CLIENTS,
+            trusted_proxies: Vec::new(),
+            client_ip_header: CLIENT_IP_HEADER.to_string(),
+        }
+    }
+}
+
+impl Default for TlsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            cert: None,
+            key: None,
+            certificates: Vec::new(),
+            reload_interval: TLS_RELOAD_INTERVAL,
+            redirect_address: None,
+        }
+    }
+}
+
+impl ServerConfig {
+    /// Builds the configuration from the defaults, the config file, the environment and the command line.
+    ///
+    /// Later sources win: defaults < config file < `RUST_SERVER_<KEY>` variables < `--<key>` flags.
+    /// The config file is the one named by `--config` or `RUST_SERVER_CONFIG`, or [`DEFAULT_CONFIG_FILE`]
+    /// if it exists. The result is validated before it is returned.
+    ///
+    /// # Arguments
+    /// - `args`: Command-line arguments without the program name.
+    /// - `vars`: Environment variables, usually [`std::env::vars`].
+    ///
+    /// # Returns
+    /// - `Ok(ServerConfig)`: If every source was read and the result is valid.
+    /// - `Err(ConfigError)`: See [`ConfigError`] for each failure.
+    pub fn load(
+        args: impl IntoIterator<Item = String>,
+        vars: impl IntoIterator<Item = (String, String)>,
+    ) -> Result<Self, ConfigError> {
+        let flags = parse_args(args)?;
+        let env: Vec<(String, String)> = vars
+            .into_iter()
+            .filter_map(|(name, value)| {
+                let key = name.strip_prefix(ENV_PREFIX)?;
+                Some((key.to_ascii_lowercase().replace('_', "-"), value))
+            })
+            .collect();
+
+        let config_file = env
+            .iter()
+            .chain(&flags)
+            .rev()
+            .find(|(key, _)| key == "config")
+            .map(|(_, value)| PathBuf::from(value));
+        let mut config = match config_file {
+            Some(path) => Self::from_file(&path)?,
+            None if Path::new(DEFAULT_CONFIG_FILE).is_file() => {
+                Self::from_file(Path::new(DEFAULT_CONFIG_FILE))?
+            }
+            None => Self::default(),
+        };
+
+        for (key, value) in env.iter().chain(&flags) {
+            if key != "config" {
+                config.set(key, value)?;
+            }
+        }
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Reads a TOML configuration file. Keys missing from the file keep their defaults.
+    ///
+    /// The result is not validated, see [`Self::validate`].
+    pub fn from_file(path: &Path) -> Result<Self, ConfigError> {
+        let text = std::fs::read_to_string(path).map_err(|source| ConfigError::Read {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        toml::from_str(&text).map_err(|source| ConfigError::Parse {
+            path: path.to_path_buf(),
+            source,
+        })
+    }
+
+    /// Sets a single value by its flag name (`document-root`, `max-body-size`, ...), as used by
+    /// command-line flags and `RUST_SERVER_*` variables.
+    ///
+    /// # Returns
+    /// - `Ok(())`: If the key exists and the value parses.
+    /// - `Err(ConfigError::UnknownKey)`: If there is no such key.
+    /// - `Err(ConfigError::InvalidValue)`: If the value can't be parsed for that key.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), ConfigError> {
+        match key {
+            "address" => self.address = value.to_string(),
+            "document-root" => self.document_root = PathBuf::from(value),
+            "workers" => self.workers = parse(key, value)?,
+            "queue-capacity" => self.queue_capacity = parse(key, value)?,
+            "queue-policy" => self.queue_policy = parse(key, value)?,
+            "keep-alive-timeout" => self.timeouts.keep_alive = parse_seconds(key, value)?,
+            "shutdown-timeout" => self.timeouts.shutdown = parse_seconds(key, value)?,
+            "request-line-timeout" => self.timeouts.request_line = parse_seconds(key, value)?,
+            "headers-timeout" => self.timeouts.headers = parse_seconds(key, value)?,
+            "body-timeout" => self.timeouts.body = parse_seconds(key, value)?,
+            "write-timeout" => self.timeouts.write = parse_seconds(key, value)?,
+            "min-transfer-rate" => self.timeouts.min_rate = parse(key, value)?,
+            "min-rate-grace" => self.timeouts.min_rate_grace = parse_seconds(key, value)?,
+            "max-request-line-length" => self.limits.max_request_line_length = parse(key, value)?,
+            "max-header-count" => self.limits.max_header_count = parse(key, value)?,
+            "max-header-line-length" => self.limits.max_header_line_length = parse(key, value)?,
+            "max-body-size" => self.limits.max_body_size = parse(key, value)?,
+            "max-keep-alive-requests" => self.limits.max_keep_alive_requests = parse(key, value)?,
+            "error-page-dir" => self.error_page_dir = PathBuf::from(value),
+            "compression" => self.compression.enabled = parse(key, value)?,
+            "compression-min-size" => self.compression.min_size = parse(key, value)?,
+            "compression-level" => self.compression.level = parse(key, value)?,
+            "precompressed" => self.compression.precompressed = parse(key, value)?,
+            "listings" => self.listings.enabled = parse(key, value)?,
+            "log-requests" => self.logging.requests = parse(key, value)?,
+            "log-format" => self.logging.format = parse(key, value)?,
+            "log-file" => {
+                self.logging.file = match value {
+                    "" | "-" => None,
+                    path => Some(PathBuf::from(path)),
+                }
+            }
+            "log-max-size" => self.logging.max_size = parse(key, value)?,
+            "log-max-files" => self.logging.max_files = parse(key, value)?,
+            "health" => self.health.enabled = parse(key, value)?,
+            "liveness-path" => self.health.liveness_path = value.to_string(),
+            "readiness-path" => self.health.readiness_path = value.to_string(),
+            "tls" => self.tls.enabled = parse(key, value)?,
+            "tls-cert" => self.tls.cert = Some(PathBuf::from(value)),
+            "tls-key" => self.tls.key = Some(PathBuf::from(value)),
+            "tls-reload-interval" => self.tls.reload_interval = parse_seconds(key, value)?,
+            "tls-redirect-address" => {
+                self.tls.redirect_address = match value {
+                    "" | "-" => None,
+                    address => Some(address.to_string()),
+                }
+            }
+            "metrics" => self.metrics.enabled = parse(key, value)?,
+            "metrics-path" => self.metrics.path = value.to_string(),
+            "metrics-address" => {
+                self.metrics.address = match value {
+                    "" | "-" => None,
+                    address => Some(address.to_string()),
+                }
+            }
+            "rate-limit" => self.rate_limit.enabled = parse(key, value)?,
+            "rate-limit-rps" => self.rate_limit.requests_per_second = parse(key, value)?,
+            "rate-limit-burst" => self.rate_limit.burst = parse(key, value)?,
+            "max-connections-per-ip" => self.rate_limit.max_connections_per_ip = parse(key, value)?,
+            "rate-limit-max-clients" => self.rate_limit.max_clients = parse(key, value)?,
+            "trusted-proxies" => {
+                self.rate_limit.trusted_proxies = value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|proxy| !proxy.is_empty())
+                    .map(|proxy| parse(key, proxy))
+                    .collect::<Result<_, _>>()?
+            }
+            "client-ip-header" => self.rate_limit.client_ip_header = value.to_string(),
+            _ => return Err(ConfigError::UnknownKey(key.to_string())),
+        }
+        Ok(())
+    }
+
+    /// Checks the configuration for values the server can't run with.
+    ///
+    /// # Returns
+    /// - `Ok(())`: If the configuration is usable.
+    /// - `Err(ConfigError::Invalid(problems))`: With one message per problem found.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let mut problems = Vec::new();
+
+        match self.address.to_socket_addrs() {
+            Ok(addresses) if addresses.len() > 0 => {}
+            Ok(_) => problems.push(format!("address `{}` resolves to nothing", self.address)),
+            Err(error) => problems.push(format!("address `{}` is invalid: {error}", self.address)),
+        }
+        if !self.document_root.is_dir() {
+            problems.push(format!(
+                "document_root `{}` is not a directory",
+                self.document_root.display()
+            ));
+        }
+        if !self.error_page_dir.is_dir() {
+            problems.push(format!(
+                "error_page_dir `{}` is not a directory",
+                self.error_page_dir.display()
+            ));
+        }
+        for (code, path) in &self.error_pages {
+            if !(400..=599).contains(code) {
+                problems.push(format!("error_pages: {code} is not a 4xx or 5xx status"));
+            }
+            if !path.is_file() {
+                problems.push(format!(
+                    "error_pages: page for {code} `{}` is not a file",
+                    path.display()
+                ));
+            }
+        }
+
+        for directory in &self.listings.directories {
+            if !self.document_root.join(directory).is_dir() {
+                problems.push(format!(
+                    "listings.directories: `{}` is not a directory under document_root",
+                    directory.display()
+                ));
+            }
+        }
+
+        if let Some(file) = &self.logging.file {
+            let directory = file
+                .parent()
+                .filter(|parent| !parent.as_os_str().is_empty());
+            if directory.is_some_and(|directory| !directory.is_dir()) || file.is_dir() {
+                problems.push(format!(
+                    "logging.file `{}` is not a file in an existing directory",
+                    file.display()
+                ));
+            }
+        }
+
+        let paths = [
+            ("health.liveness_path", &self.health.liveness_path),
+            ("health.readiness_path", &self.health.readiness_path),
+            ("metrics.path", &self.metrics.path),
+        ];
+        for (name, path) in paths {
+            if !path.starts_with('/') {
+                problems.push(format!("{name} `{path}` must start with `/`"));
+            }
+        }
+        if let Some(address) = &self.metrics.address {
+            match address.to_socket_addrs() {
+                Ok(addresses) if addresses.len() > 0 => {}
+                Ok(_) => problems.push(format!("metrics.address `{address}` resolves to nothing")),
+                Err(error) => {
+                    problems.push(format!("metrics.address `{address}` is invalid: {error}"))
+                }
+            }
+        }
+
+        if self.tls.enabled {
+            self.validate_tls(&mut problems);
+        }
+
+        let positive = [
+            ("workers", self.workers),
+            ("queue_capacity", self.queue_capacity),
+            (
+                "limits.max_request_line_length",
+                self.limits.max_request_line_length,
+            ),
+            ("limits.max_header_count", self.limits.max_header_count),
+            (
+                "limits.max_header_line_length",
+                self.limits.max_header_line_length,
+            ),
+            (
+                "limits.max_keep_alive_requests",
+                self.limits.max_keep_alive_requests,
+            ),
+        ];
+        for (name, value) in positive {
+            if value == 0 {
+                problems.push(format!("{name} must be at least 1"));
+            }
+        }
+        let timeouts = [
+            ("timeouts.keep_alive", self.timeouts.keep_alive),
+            ("timeouts.request_line", self.timeouts.request_line),
+            ("timeouts.headers", self.timeouts.headers),
+            ("timeouts.body", self.timeouts.body),
+            ("timeouts.write", self.timeouts.write),
+        ];
+        for (name, timeout) in timeouts {
+            if timeout.is_zero() {
+                problems.push(format!("{name} must be greater than 0"));
+            }
+        }
+        let durations = timeouts.into_iter().chain([
+            ("timeouts.shutdown", self.timeouts.shutdown),
+            ("timeouts.min_rate_grace", self.timeouts.min_rate_grace),
+            ("tls.reload_interval", self.tls.reload_interval),
+        ]);
+        for (name, duration) in durations {
+            if duration > MAX_DURATION {
+                problems.push(format!(
+                    "{name} must be at most {} seconds",
+                    MAX_DURATION.as_secs()
+                ));
+            }
+        }
+        if self.rate_limit.enabled {
+            let rate_limit = &self.rate_limit;
+            if !(rate_limit.requests_per_second > 0.0 && rate_limit.requests_per_second.is_finite())
+            {
+                problems.push("rate_limit.requests_per_second must be greater than 0".to_string());
+            }
+            if rate_limit.burst == 0 {
+                problems.push("rate_limit.burst must be at least 1".to_string());
+            }
+            if rate_limit.max_clients == 0 {
+                problems.push("rate_limit.max_clients must be at least 1".to_string());
+            }
+        }
+        if self.compression.level > 9 {
+            problems.push("compression.level must be between 0 and 9".to_string());
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigError::Invalid(problems))
+        }
+    }
+
+    /// This function is a private helper function for [`Self::validate`].
+    /// - It checks the `[tls]` section, which only matters when TLS is enabled.
+    fn validate_tls(&self, problems: &mut Vec<String>) {
+        if !cfg!(feature = "tls") {
+            problems.push("tls.enabled needs a build with the `tls` cargo feature".to_string());
+        }
+        match (&self.tls.cert, &self.tls.key) {
+            (Some(_), Some(_)) => {}
+            _ => problems.push("tls.cert and tls.key must both be set".to_string()),
+        }
+        let files = self
+            .tls
+            .certificates
+            .iter()
+            .flat_map(|certificate| [&certificate.cert, &certificate.key])
+            .chain(self.tls.cert.iter())
+            .chain(self.tls.key.iter());
+        for path in files {
+            if !path.is_file() {
+                problems.push(format!("tls: `{}` is not a file", path.display()));
+            }
+        }
+        for certificate in &self.tls.certificates {
+            if certificate.names.is_empty() {
+                problems.push(format!(
+                    "tls.certificates: `{}` has no names",
+                    certificate.cert.display()
+                ));
+            }
+        }
+        if let Some(address) = &self.tls.redirect_address
+            && !address
+                .to_socket_addrs()
+                .is_ok_and(|mut addresses| addresses.next().is_some())
+        {
+            problems.push(format!("tls.redirect_address `{address}` is invalid"));
+        }
+    }
+
+    /// Returns `true` if `directory` (a canonical path, see [`crate::Request::directory_exists`])
+    /// may be shown as a directory listing.
+    pub fn listing_allowed(&self, directory: &Path) -> bool {
+        self.listings.enabled
+            || self.listings.directories.iter().any(|allowed| {
+                self.document_root
+                    .join(allowed)
+                    .canonicalize()
+                    .is_ok_and(|allowed| directory.starts_with(allowed))
+            })
+    }
+
+    /// Returns the path of the error page for the status `code`: the `error_pages` entry if there
+    /// is one, otherwise `error_page_dir/error<code>.html`. The file may not exist.
+    pub fn error_page_path(&self, code: u16) -> PathBuf {
+        match self.error_pages.get(&code) {
+            Some(path) => path.clone(),
+            None => self.error_page_dir.join(format!("error{code}.html")),
+        }
+    }
+}
+
+/// This function is a private helper function for [`ServerConfig::load`].
+/// - It accepts `--key value` and `--key=value`.
+fn parse_args(
+    args: impl IntoIterator<Item = String>,
+) -> Result<Vec<(String, String)>, ConfigError> {
+    let mut flags = Vec::new();
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        if arg == "--help" || arg == "-h" {
+            return Err(ConfigError::Help);
+        }
+        let Some(flag) = arg.strip_prefix("--") else {
+            return Err(ConfigError::UnknownKey(arg));
+        };
+        let (key, value) = match flag.split_once('=') {
+            Some((key, value)) => (key.to_string(), value.to_string()),
+            None => {
+                let value = args
+                    .next()
+                    .ok_or_else(|| ConfigError::MissingValue(flag.to_string()))?;
+                (flag.to_string(), value)
+            }
+        };
+        flags.push((key, value));
+    }
+
+    Ok(flags)
+}
+
+/// Parses a flag or environment value, turning a parse failure into [`ConfigError::InvalidValue`].
+fn parse<T>(key: &str, value: &str) -> Result<T, ConfigError>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    value
+        .parse()
+        .map_err(|error: T::Err| ConfigError::InvalidValue {
+            key: key.to_string(),
+            value: value.to_string(),
+            reason: error.to_string(),
+        })
+}
+
+/// Parses a number of seconds (fractions allowed) into a [`Duration`].
+fn parse_seconds(key: &str, value: &str) -> Result<Duration, ConfigError> {
+    let seconds: f64 = parse(key, value)?;
+    Duration::try_from_secs_f64(seconds).map_err(|error| ConfigError::InvalidValue {
+        key: key.to_string(),
+        value: value.to_string(),
+        reason: error.to_string(),
+    })
+}
+
+/// Deserializes a value from its string form with [`FromStr`].
+fn from_str<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: Display,
+{
+    let value = String::deserialize(deserializer)?;
+    value.parse().map_err(serde::de::Error::custom)
+}
+
+/// Deserializes a number of seconds (integer or float) into a [`Duration`].
+fn seconds<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Seconds {
+        Whole(u64),
+        Fraction(f64),
+    }
+
+    match Seconds::deserialize(deserializer)? {
+        Seconds::Whole(seconds) => Ok(Duration::from_secs(seconds)),
+        Seconds::Fraction(seconds) => {
+            Duration::try_from_secs_f64(seconds).map_err(serde::de::Error::custom)
+        }
+    }
+}
+
+/// Deserializes the `[error_pages]` table, whose keys are status codes written as strings.
+fn error_pages<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<HashMap<u16, PathBuf>, D::Error> {
+    HashMap::<String, PathBuf>::deserialize(deserializer)?
+        .into_iter()
+        .map(|(code, path)| {
+            let code = code
+                .parse()
+                .map_err(|_| serde::de::Error::custom(format!("`{code}` is not a status code")))?;
+            Ok((code, path))
+        })
+        .collect()
+}
+
+/// Deserializes the `[mime_types]` table as overrides on top of the built-in [`MimeTypes`].
+fn mime_types<'de, D: Deserializer<'de>>(deserializer: D) -> Result<MimeTypes, D::Error> {
+    let mut mime_types = MimeTypes::new();
+    for (extension, mime) in HashMap::<String, String>::deserialize(deserializer)? {
+        if !mime.contains('/') {
+            return Err(serde::de::Error::custom(format!(
+                "`{mime}` for `{extension}` is not a MIME type"
+            )));
+        }
+        mime_types.insert(&extension, mime);
+    }
+    Ok(mime_types)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A config file in the temp directory, removed again when dropped.
+    struct ConfigFile(PathBuf);
+
+    impl ConfigFile {
+        fn new(name: &str, contents: &str) -> Self {
+            let path = std::env::temp_dir().join(format!(
+                "rust_server_config_{name}_{}.toml",
+                std::process::id()
+            ));
+            std::fs::write(&path, contents).unwrap();
+            Self(path)
+        }
+
+        fn path(&self) -> String {
+            self.0.display().to_string()
+        }
+    }
+
+    impl Drop for ConfigFile {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    fn load(args: &[&str], vars: &[(&str, &str)]) -> Result<ServerConfig, ConfigError> {
+        ServerConfig::load(
+            args.iter().map(|arg| arg.to_string()),
+            vars.iter()
+                .map(|(name, value)| (name.to_string(), value.to_string())),
+        )
+    }
+
+    #[test]
+    fn later_sources_win_defaults_file_env_flags() {
+        let file = ConfigFile::new("precedence", "workers = 3\nqueue_capacity = 8\n");
+        let config_flag = ["--config", &file.path()];
+
+        let config = load(&config_flag, &[]).unwrap();
+        assert_eq!(config.workers, 3);
+        assert_eq!(config.queue_capacity, 8);
+        assert_eq!(config.limits.max_body_size, MAX_BODY_SIZE);
+
+        let env = [
+            ("RUST_SERVER_WORKERS", "5"),
+            ("RUST_SERVER_KEEP_ALIVE_TIMEOUT", "1.5"),
+            ("PATH", "/usr/bin"),
+        ];
+        let config = load(&config_flag, &env).unwrap();
+        assert_eq!(config.workers, 5);
+        assert_eq!(config.queue_capacity, 8);
+        assert_eq!(config.timeouts.keep_alive, Duration::from_millis(1500));
+
+        let config = load(&[&config_flag[..], &["--workers", "7"]].concat(), &env).unwrap();
+        assert_eq!(config.workers, 7);
+        let config = load(&[&config_flag[..], &["--workers=9"]].concat(), &env).unwrap();
+        assert_eq!(config.workers, 9);
+    }
+
+    #[test]
+    fn the_config_file_can_come_from_the_environment_or_a_flag() {
+        let from_env = ConfigFile::new("from_env", "workers = 2\n");
+        let from_flag = ConfigFile::new("from_flag", "workers = 6\n");
+        let env = [("RUST_SERVER_CONFIG", from_env.path())];
+        let env = env
+            .iter()
+            .map(|(name, value)| (*name, value.as_str()))
+            .collect::<Vec<_>>();
+
+        assert_eq!(load(&[], &env).unwrap().workers, 2);
+        assert_eq!(
+            load(&["--config", &from_flag.path()], &env)
+                .unwrap()
+                .workers,
+            6
+        );
+    }
+
+    #[test]
+    fn unknown_keys_are_rejected_from_every_source() {
+        assert!(matches!(
+            load(&["--no-such-key", "1"], &[]),
+            Err(ConfigError::UnknownKey(key)) if key == "no-such-key"
+        ));
+        assert!(matches!(
+            load(&["workers"], &[]),
+            Err(ConfigError::UnknownKey(key)) if key == "workers"
+        ));
+        assert!(matches!(
+            load(&[], &[("RUST_SERVER_NO_SUCH_KEY", "1")]),
+            Err(ConfigError::UnknownKey(key)) if key == "no-such-key"
+        ));
+
+        let file = ConfigFile::new("unknown", "no_such_key = 1\n");
+        assert!(matches!(
+            load(&["--config", &file.path()], &[]),
+            Err(ConfigError::Parse { .. })
+        ));
+        assert!(matches!(
+            load(&["--config", "/nonexistent/server.toml"], &[]),
+            Err(ConfigError::Read { .. })
+        ));
+    }
+
+    #[test]
+    fn invalid_values_are_rejected() {
+        assert!(matches!(
+            load(&["--workers", "many"], &[]),
+            Err(ConfigError::InvalidValue { key, value, .. }) if key == "workers" && value == "many"
+        ));
+        assert!(matches!(
+            load(&["--keep-alive-timeout", "-1"], &[]),
+            Err(ConfigError::InvalidValue { key, .. }) if key == "keep-alive-timeout"
+        ));
+        assert!(matches!(
+            load(&["--workers"], &[]),
+            Err(ConfigError::MissingValue(key)) if key == "workers"
+        ));
+
+        let Err(ConfigError::Invalid(problems)) =
+            load(&["--workers", "0", "--compression-level", "10"], &[])
+        else {
+            panic!("zero workers and compression level 10 were accepted");
+        };
+        assert!(problems.contains(&"workers must be at least 1".to_string()));
+        assert!(problems.contains(&"compression.level must be between 0 and 9".to_string()));
+    }
+
+    #[test]
+    fn help_wins_over_everything_else() {
+        for args in [
+            &["--help"][..],
+            &["-h"],
+            &["--workers", "2", "--help"],
+            &["--help", "--no-such-key"],
+        ] {
+            assert!(
+                matches!(load(args, &[]), Err(ConfigError::Help)),
+                "{args:?}"
+            );
+        }
+        assert!(USAGE.contains("--config <file>"));
+    }
+
+    #[test]
+    fn validate_rejects_durations_too_long_to_add_to_the_clock() {
+        let mut config = ServerConfig::default();
+        config.set("keep-alive-timeout", "1e15").unwrap();
+        config.set("shutdown-timeout", "86400").unwrap();
+
+        let Err(ConfigError::Invalid(problems)) = config.validate() else {
+            panic!("a keep-alive timeout of 1e15 seconds was accepted");
+        };
+        assert!(
+            problems.contains(&"timeouts.keep_alive must be at most 86400 seconds".to_string())
+        );
+        assert!(
+            !problems
+                .iter()
+                .any(|problem| problem.starts_with("timeouts.shutdown"))
+        );
+    }
+}
@@ -0,0 +1,250 @@
+use crate::TimeoutConfig;
+
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::time::{Duration, Instant};
+
+/// A client connection that [`crate::handle_connection`] can serve: a byte stream plus the
+/// socket operations it needs.
+///
+/// Implemented by [`TcpStream`] and, with the `tls` feature, by [`crate::TlsStream`].
+pub trait Connection: Read + Write {
+    /// The address of the client.
+    fn peer_addr(&self) -> io::Result<SocketAddr>;
+
+    /// Sets how long a read may block; `None` blocks indefinitely. See [`TcpStream::set_read_timeout`].
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()>;
+
+    /// Sets how long a write may block; `None` blocks indefinitely. See [`TcpStream::set_write_timeout`].
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()>;
+}
+
+impl Connection for TcpStream {
+    fn peer_addr(&self) -> io::Result<SocketAddr> {
+        TcpStream::peer_addr(self)
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        TcpStream::set_read_timeout(self, timeout)
+    }
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        TcpStream::set_write_timeout(self, timeout)
+    }
+}
+
+/// Which part of a request a [`TimedConnection`] is receiving, worked out from the bytes read.
+///
+/// # Variants
+/// - `Idle`: Between requests. Reads are passed through untimed.
+/// - `RequestLine`: Until the first line ends.
+/// - `Headers`: Until the empty line that ends the head. `line_empty` is `true` while nothing but
+///   a `\r` has been read of the current line.
+/// - `Body`: Everything after the head, until the response starts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Phase {
+    Idle,
+    RequestLine,
+    Headers { line_empty: bool },
+    Body,
+}
+
+impl Phase {
+    /// The phase after `bytes` more of the request were received.
+    pub(crate) fn advance(mut self, bytes: &[u8]) -> Self {
+        for byte in bytes {
+            self = match (self, byte) {
+                (Phase::RequestLine, b'\n') => Phase::Headers { line_empty: true },
+                (Phase::Headers { line_empty: true }, b'\n') => return Phase::Body,
+                (Phase::Headers { .. }, b'\n') => Phase::Headers { line_empty: true },
+                (Phase::Headers { line_empty }, b'\r') => Phase::Headers { line_empty },
+                (Phase::Headers { .. }, _) => Phase::Headers { line_empty: false },
+                (phase, _) => phase,
+            };
+            if matches!(self, Phase::Idle | Phase::Body) {
+                break;
+            }
+        }
+        self
+    }
+}
+
+/// Bytes moved in one direction since a transfer started, to enforce [`TimeoutConfig::min_rate`].
+#[derive(Debug, Clone, Copy)]
+struct Transfer {
+    started: Instant,
+    bytes: u64,
+}
+
+impl Transfer {
+    fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            bytes: 0,
+        }
+    }
+
+    /// Counts `bytes` more and checks the average rate, once the grace period is over.
+    fn record(&mut self, bytes: usize, timeouts: &TimeoutConfig, what: &str) -> io::Result<()> {
+        self.bytes += bytes as u64;
+        let elapsed = self.started.elapsed();
+        if timeouts.min_rate == 0 || elapsed <= timeouts.min_rate_grace {
+            return Ok(());
+        }
+        if (self.bytes as f64) < timeouts.min_rate as f64 * elapsed.as_secs_f64() {
+            return Err(timed_out(&format!(
+                "{what} slower than {} bytes per second",
+                timeouts.min_rate
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Enforces [`TimeoutConfig`] on a client connection for [`crate::handle_connection`].
+///
+/// - The request line and the head must arrive before their deadlines, counted from the start
+///   of the request given to [`Self::start_request`].
+/// - Body reads may pause for at most `timeouts.body`, writes may block for at most
+///   `timeouts.write`, and both must keep up `timeouts.min_rate` on average after the grace period.
+///
+/// Any limit that is hit fails the read or write with [`ErrorKind::TimedOut`], which becomes
+/// [`crate::RequestError::Timeout`].
+pub(crate) struct TimedConnection<'a, C: Connection> {
+    inner: &'a mut C,
+    timeouts: &'a TimeoutConfig,
+    phase: Phase,
+    /// When the current request started, the base of the request line and head deadlines.
+    started: Instant,
+    received: Transfer,
+    sent: Transfer,
+}
+
+impl<'a, C: Connection> TimedConnection<'a, C> {
+    /// Wraps `inner`, idle until the first [`Self::start_request`].
+    ///
+    /// # Returns
+    /// - `Ok(TimedConnection)`: With the write timeout set on `inner`.
+    /// - `Err(io::Error)`: If the write timeout could not be set.
+    pub(crate) fn new(inner: &'a mut C, timeouts: &'a TimeoutConfig) -> io::Result<Self> {
+        inner.set_write_timeout(Some(timeouts.write))?;
+        Ok(Self {
+            inner,
+            timeouts,
+            phase: Phase::Idle,
+            started: Instant::now(),
+            received: Transfer::new(),
+            sent: Transfer::new(),
+        })
+    }
+
+    /// Starts timing a request that began at `started` in `phase`, which is
+    /// [`Phase::RequestLine`] advanced past the bytes already buffered.
+    pub(crate) fn start_request(&mut self, started: Instant, phase: Phase) {
+        self.started = started;
+        self.phase = phase;
+        self.received = Transfer::new();
+    }
+
+    /// Stops timing reads and starts timing the response.
+    pub(crate) fn start_response(&mut self) {
+        self.phase = Phase::Idle;
+        self.sent = Transfer::new();
+    }
+
+    /// This function is a private helper function for the [`Read`] impl of [`TimedConnection`].
+    /// - It returns how long the next read may block in the current phase.
+    /// - A head deadline that has already passed is a timeout.
+    fn read_timeout(&self) -> io::Result<Duration> {
+        let (limit, what) = match self.phase {
+            Phase::Idle => unreachable!("idle reads are not timed"),
+            Phase::Body => return Ok(self.timeouts.body),
+            Phase::RequestLine => (
+                self.timeouts.request_line.min(self.timeouts.headers),
+                "request line",
+            ),
+            Phase::Headers { .. } => (self.timeouts.headers, "request head"),
+        };
+        let remaining = (self.started + limit).saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Err(timed_out(&format!("{what} not received in time")));
+        }
+        Ok(remaining)
+    }
+}
+
+impl<C: Connection> Read for TimedConnection<'_, C> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.phase == Phase::Idle {
+            return self.inner.read(buf);
+        }
+
+        self.inner.set_read_timeout(Some(self.read_timeout()?))?;
+        let read = match self.inner.read(buf) {
+            Ok(read) => read,
+            Err(error) if is_timeout(&error) => {
+                return Err(match self.phase {
+                    Phase::Body => timed_out("request body stalled"),
+                    _ => timed_out("request head not received in time"),
+                });
+            }
+            Err(error) => return Err(error),
+        };
+
+        if self.phase == Phase::Body {
+            self.received.record(read, self.timeouts, "request body")?;
+        } else {
+            self.phase = self.phase.advance(&buf[..read]);
+            if self.phase == Phase::Body {
+                self.received = Transfer::new();
+            }
+        }
+        Ok(read)
+    }
+}
+
+impl<C: Connection> Write for TimedConnection<'_, C> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        /* Checked before writing: an error for bytes already sent would make the caller send them again. */
+        self.sent.record(0, self.timeouts, "response")?;
+        let written = match self.inner.write(buf) {
+            Ok(written) => written,
+            Err(error) if is_timeout(&error) => return Err(timed_out("response write stalled")),
+            Err(error) => return Err(error),
+        };
+        self.sent.bytes += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.inner.flush() {
+            Err(error) if is_timeout(&error) => Err(timed_out("response write stalled")),
+            result => result,
+        }
+    }
+}
+
+impl<C: Connection> Connection for TimedConnection<'_, C> {
+    fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.peer_addr()
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.inner.set_read_timeout(timeout)
+    }
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.inner.set_write_timeout(timeout)
+    }
+}
+
+/// Returns `true` for the errors a socket read or write reports when its timeout passes
+/// (`WouldBlock` on Unix, `TimedOut` on Windows).
+fn is_timeout(error: &io::Error) -> bool {
+    matches!(error.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut)
+}
+
+/// The error every limit of a [`TimedConnection`] fails with.
+fn timed_out(message: &str) -> io::Error {
+    io::Error::new(ErrorKind::TimedOut, message.to_string())
+}
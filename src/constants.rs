@@ -1,5 +1,114 @@
+use crate::QueuePolicy;
+use std::time::Duration;
+
 /// The address that the server will bind to.
 pub static ADDRESS: &str = "127.0.0.1:7878";
 
 ///The base directory where the HTML files live.
-pub static BASE_DIR: &str = "pages";
\ No newline at end of file
+pub static BASE_DIR: &str = "pages";
+
+/// Number of worker threads that handle connections.
+pub static WORKER_COUNT: usize = 4;
+
+/// Maximum number of accepted connections waiting for a free worker.
+pub static QUEUE_CAPACITY: usize = 64;
+
+/// What happens to a new connection when the queue is full.
+pub static QUEUE_POLICY: QueuePolicy = QueuePolicy::Reject;
+
+/// Worker threads serving each side listener (the metrics admin listener and the HTTPS redirect).
+pub static SIDE_LISTENER_WORKERS: usize = 2;
+
+/// Connections each side listener queues for a free worker; more get `503 Service Unavailable`.
+pub static SIDE_LISTENER_QUEUE_CAPACITY: usize = 16;
+
+/// The longest any timeout or interval may be configured to. Longer values are rejected by
+/// [`crate::ServerConfig::validate`].
+pub static MAX_DURATION: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// How long a shutdown waits for in-flight connections before giving up on them.
+pub static SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Maximum number of header fields accepted in a single request.
+pub static MAX_HEADER_COUNT: usize = 100;
+
+/// Maximum length in bytes of a single header line, not counting the line terminator.
+pub static MAX_HEADER_LINE_LENGTH: usize = 8 * 1024;
+
+/// Maximum size in bytes of a decoded request body. Larger bodies are rejected with `413 Payload Too Large`.
+pub static MAX_BODY_SIZE: usize = 1024 * 1024;
+
+/// How long a connection may sit idle waiting for its next request before it is closed.
+pub static KEEP_ALIVE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long a client has to send the request line, counted from the connection (or, on a persistent
+/// connection, from the first byte of the request). Slower clients get `408 Request Timeout`.
+pub static REQUEST_LINE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long a client has to send the whole request head, counted like [`REQUEST_LINE_TIMEOUT`].
+pub static HEADERS_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// The longest pause allowed between two reads of a request body.
+pub static BODY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The longest a single write of a response may block on a client that doesn't read.
+pub static WRITE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Minimum average transfer rate in bytes per second for request bodies and responses. `0` disables it.
+pub static MIN_TRANSFER_RATE: u64 = 240;
+
+/// How long a transfer may run before [`MIN_TRANSFER_RATE`] is enforced, so slow starts are tolerated.
+pub static MIN_RATE_GRACE_PERIOD: Duration = Duration::from_secs(5);
+
+/// Maximum number of requests served on one persistent connection.
+pub static MAX_KEEP_ALIVE_REQUESTS: usize = 100;
+
+/// Maximum length in bytes of the request line. Longer ones get `414 URI Too Long`.
+pub static MAX_REQUEST_LINE_LENGTH: usize = 8 * 1024;
+
+/// Directory holding the error pages, named after their status code (`error404.html`, `error400.html`, ...).
+/// Codes without a page get a small built-in HTML body instead.
+pub static ERROR_PAGE_DIR: &str = "pages";
+
+/// Responses smaller than this many bytes are sent uncompressed; compressing them gains little.
+pub static COMPRESSION_MIN_SIZE: u64 = 1024;
+
+/// gzip/deflate compression level, from `0` (store only) to `9` (smallest output).
+pub static COMPRESSION_LEVEL: u32 = 6;
+
+/// Size in bytes after which the access log file is rotated.
+pub static ACCESS_LOG_MAX_SIZE: u64 = 10 * 1024 * 1024;
+
+/// Number of rotated access log files kept (`access.log.1` ... `access.log.5`).
+pub static ACCESS_LOG_MAX_FILES: usize = 5;
+
+/// Path the Prometheus metrics are served on, when enabled.
+pub static METRICS_PATH: &str = "/metrics";
+
+/// Path of the liveness probe.
+pub static LIVENESS_PATH: &str = "/healthz";
+
+/// Path of the readiness probe.
+pub static READINESS_PATH: &str = "/readyz";
+
+/// How often the TLS certificate and key files are checked for changes. `0` disables the check.
+pub static TLS_RELOAD_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Requests per second each client IP may make on average once rate limiting is enabled.
+pub static RATE_LIMIT_REQUESTS_PER_SECOND: f64 = 10.0;
+
+/// Requests a client IP may make in a burst on top of the average rate.
+pub static RATE_LIMIT_BURST: u32 = 20;
+
+/// Connections one client IP may hold open at once when rate limiting is enabled. `0` means no cap.
+pub static MAX_CONNECTIONS_PER_IP: usize = 16;
+
+/// Number of client IPs the rate limiter keeps state for. New IPs beyond it are refused until
+/// tracked ones go idle.
+pub static RATE_LIMIT_MAX_CLIENTS: usize = 10_000;
+
+/// How often at most a full rate limiter table is swept for idle clients.
+pub static RATE_LIMIT_SWEEP_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Header a trusted proxy puts the client address in.
+pub static CLIENT_IP_HEADER: &str = "X-Forwarded-For";
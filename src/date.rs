@@ -0,0 +1,258 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+static WEEKDAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+static MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// A point in time broken down into UTC calendar fields.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DateTime {
+    pub year: i64,
+    /// `1..=12`
+    pub month: u32,
+    /// `1..=31`
+    pub day: u32,
+    pub hour: u32,
+    pub minute: u32,
+    pub second: u32,
+    /// `0` is Thursday (1970-01-01 was a Thursday), see [`DateTime::weekday_name`].
+    weekday: usize,
+}
+
+impl DateTime {
+    /// Breaks `time` down into UTC calendar fields. Times before 1970 are clamped to the epoch.
+    pub fn from_system_time(time: SystemTime) -> Self {
+        let seconds = time
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or(Duration::ZERO)
+            .as_secs();
+        let days = (seconds / 86_400) as i64;
+        let second_of_day = seconds % 86_400;
+        let (year, month, day) = civil_from_days(days);
+
+        Self {
+            year,
+            month,
+            day,
+            hour: (second_of_day / 3_600) as u32,
+            minute: (second_of_day % 3_600 / 60) as u32,
+            second: (second_of_day % 60) as u32,
+            weekday: (days % 7) as usize,
+        }
+    }
+
+    /// Three-letter English weekday name (`Mon`, `Tue`, ...).
+    pub fn weekday_name(&self) -> &'static str {
+        WEEKDAYS[self.weekday]
+    }
+
+    /// Three-letter English month name (`Jan`, `Feb`, ...).
+    pub fn month_name(&self) -> &'static str {
+        MONTHS[self.month as usize - 1]
+    }
+}
+
+/// Formats `time` as an HTTP date (IMF-fixdate, RFC 9110 section 5.6.7).
+///
+/// Example: `Sun, 06 Nov 1994 08:49:37 GMT`.
+pub fn http_date(time: SystemTime) -> String {
+    let date = DateTime::from_system_time(time);
+    format!(
+        "{}, {:02} {} {} {:02}:{:02}:{:02} GMT",
+        date.weekday_name(),
+        date.day,
+        date.month_name(),
+        date.year,
+        date.hour,
+        date.minute,
+        date.second
+    )
+}
+
+/// Parses an HTTP date, as sent in `If-Modified-Since` and similar header fields.
+///
+/// All three formats of RFC 9110 section 5.6.7 are accepted:
+/// - IMF-fixdate: `Sun, 06 Nov 1994 08:49:37 GMT`
+/// - RFC 850: `Sunday, 06-Nov-94 08:49:37 GMT` (two-digit years below 70 are taken as `20xx`)
+/// - asctime: `Sun Nov  6 08:49:37 1994`
+///
+/// The weekday name is not checked against the date.
+///
+/// # Returns
+/// - `Some(SystemTime)`: If `value` is a valid date in one of the formats, not before 1970.
+/// - `None`: Otherwise.
+pub fn parse_http_date(value: &str) -> Option<SystemTime> {
+    let value = value.trim();
+    let (day, month, year, time) = if let Some((_, rest)) = value.split_once(", ") {
+        if rest.contains('-') {
+            /* RFC 850: "06-Nov-94 08:49:37 GMT" */
+            let (date, rest) = rest.split_once(' ')?;
+            let mut date = date.splitn(3, '-');
+            let (day, month, year) = (date.next()?, date.next()?, date.next()?);
+            let time = rest.strip_suffix(" GMT")?;
+            let year = match year.len() {
+                2 => {
+                    let year: i64 = year.parse().ok()?;
+                    if year < 70 { 2000 + year } else { 1900 + year }
+                }
+                _ => return None,
+            };
+            (day, month, year, time)
+        } else {
+            /* IMF-fixdate: "06 Nov 1994 08:49:37 GMT" */
+            let mut parts = rest.split(' ');
+            let (day, month, year, time) =
+                (parts.next()?, parts.next()?, parts.next()?, parts.next()?);
+            if parts.next()? != "GMT" || parts.next().is_some() || year.len() != 4 {
+                return None;
+            }
+            (day, month, year.parse().ok()?, time)
+        }
+    } else {
+        /* asctime: "Sun Nov  6 08:49:37 1994" */
+        let mut parts = value.split_whitespace();
+        let (_, month, day, time, year) = (
+            parts.next()?,
+            parts.next()?,
+            parts.next()?,
+            parts.next()?,
+            parts.next()?,
+        );
+        if parts.next().is_some() || year.len() != 4 {
+            return None;
+        }
+        (day, month, year.parse().ok()?, time)
+    };
+
+    if day.is_empty() || day.len() > 2 || !day.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+    let day: u32 = day.parse().ok()?;
+    let month = MONTHS.iter().position(|name| *name == month)? as u32 + 1;
+    if !(1..=days_in_month(year, month)).contains(&day) || year < 1970 {
+        return None;
+    }
+
+    let mut time = time.split(':');
+    let mut field = |max: u64| -> Option<u64> {
+        let field = time.next()?;
+        if field.len() != 2 || !field.bytes().all(|byte| byte.is_ascii_digit()) {
+            return None;
+        }
+        field.parse().ok().filter(|value| *value <= max)
+    };
+    /* 60 allows for a leap second. */
+    let (hour, minute, second) = (field(23)?, field(59)?, field(60)?);
+    if time.next().is_some() {
+        return None;
+    }
+
+    let days = days_from_civil(year, month, day) as u64;
+    let seconds = days * 86_400 + hour * 3_600 + minute * 60 + second;
+    Some(UNIX_EPOCH + Duration::from_secs(seconds))
+}
+
+/// Converts a day count since 1970-01-01 into a `(year, month, day)` civil date.
+///
+/// This is Howard Hinnant's `civil_from_days` algorithm for the proleptic Gregorian calendar.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u32;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    (year, month, day)
+}
+
+/// Converts a `(year, month, day)` civil date into a day count since 1970-01-01.
+///
+/// The inverse of [`civil_from_days`] (Howard Hinnant's `days_from_civil`).
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let shifted_month = if month > 2 { month - 3 } else { month + 9 } as i64;
+    let day_of_year = (153 * shifted_month + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// The number of days in `month` (`1..=12`) of `year`.
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `Sun, 06 Nov 1994 08:49:37 GMT`, the example date of RFC 9110.
+    fn example() -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(784_111_777)
+    }
+
+    #[test]
+    fn all_three_formats_parse_to_the_same_time() {
+        for value in [
+            "Sun, 06 Nov 1994 08:49:37 GMT",
+            "Sunday, 06-Nov-94 08:49:37 GMT",
+            "Sun Nov  6 08:49:37 1994",
+            "  Sun, 06 Nov 1994 08:49:37 GMT ",
+        ] {
+            assert_eq!(parse_http_date(value), Some(example()), "{value:?}");
+        }
+    }
+
+    #[test]
+    fn rfc_850_years_below_70_are_in_this_century() {
+        assert_eq!(
+            parse_http_date("Thursday, 15-Oct-26 09:31:26 GMT"),
+            Some(UNIX_EPOCH + Duration::from_secs(1_792_056_686))
+        );
+        assert_eq!(
+            parse_http_date("Thursday, 01-Jan-70 00:00:00 GMT"),
+            Some(UNIX_EPOCH)
+        );
+    }
+
+    #[test]
+    fn invalid_dates_are_rejected() {
+        for value in [
+            "",
+            "yesterday",
+            "Sun, 06 Nov 1994 08:49:37 UTC",
+            "Sun, 06 Nov 94 08:49:37 GMT",
+            "Sun, 31 Feb 1994 08:49:37 GMT",
+            "Sun, 06 Foo 1994 08:49:37 GMT",
+            "Sun, 06 Nov 1994 25:49:37 GMT",
+            "Sunday, 06-Nov-1994 08:49:37 GMT",
+            "Sun Nov  6 08:49:37 94",
+            "Wed, 31 Dec 1969 23:59:59 GMT",
+        ] {
+            assert_eq!(parse_http_date(value), None, "{value:?}");
+        }
+    }
+
+    #[test]
+    fn http_date_round_trips() {
+        assert_eq!(http_date(example()), "Sun, 06 Nov 1994 08:49:37 GMT");
+        assert_eq!(parse_http_date(&http_date(example())), Some(example()));
+    }
+}
@@ -0,0 +1,130 @@
+use std::fmt::{Display, Formatter};
+
+/// An ordered, case-insensitive multi-map of HTTP header fields.
+///
+/// Header names are compared with ASCII case folding (`Host` == `host`), the original spelling
+/// and the order fields were added in are kept, and a name can appear more than once
+/// (for example several `Accept` or `Set-Cookie` lines).
+///
+/// HTTP messages rarely carry more than a few dozen fields, so a plain `Vec` scan beats hashing here.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Headers {
+    entries: Vec<(String, String)>,
+}
+
+impl Headers {
+    /// Creates an empty header map.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the value of the first field called `name`, if any.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Returns the values of every field called `name`, in the order they were added.
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.entries
+            .iter()
+            .filter(move |(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Returns `true` if at least one field is called `name`.
+    pub fn contains(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    /// Adds a field, keeping any existing fields with the same name.
+    pub fn append(&mut self, name: impl Into<String>, value: impl Into<String>) {
+        self.entries.push((name.into(), value.into()));
+    }
+
+    /// Sets a field, replacing every existing field with the same name.
+    pub fn insert(&mut self, name: impl Into<String>, value: impl Into<String>) {
+        let name = name.into();
+        self.remove(&name);
+        self.entries.push((name, value.into()));
+    }
+
+    /// Removes every field called `name`.
+    pub fn remove(&mut self, name: &str) {
+        self.entries
+            .retain(|(key, _)| !key.eq_ignore_ascii_case(name));
+    }
+
+    /// Iterates over every `(name, value)` pair in insertion order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.entries
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+
+    /// Number of fields, counting repeated names separately.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns `true` if there are no fields.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl Display for Headers {
+    /// Formats the fields the way they appear on the wire: one `Name: value\r\n` line each.
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (name, value) in self.iter() {
+            write!(f, "{name}: {value}\r\n")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lookups_ignore_case_and_keep_the_original_spelling() {
+        let mut headers = Headers::new();
+        headers.append("Content-Type", "text/html");
+
+        assert_eq!(headers.get("content-type"), Some("text/html"));
+        assert_eq!(headers.get("CONTENT-TYPE"), Some("text/html"));
+        assert!(headers.contains("cOnTeNt-TyPe"));
+        assert_eq!(headers.get("Content-Length"), None);
+        assert_eq!(headers.to_string(), "Content-Type: text/html\r\n");
+    }
+
+    #[test]
+    fn repeated_names_are_all_kept_in_order() {
+        let mut headers = Headers::new();
+        headers.append("Accept", "text/html");
+        headers.append("Host", "localhost");
+        headers.append("accept", "application/json");
+
+        assert_eq!(headers.len(), 3);
+        assert_eq!(headers.get("Accept"), Some("text/html"));
+        assert_eq!(
+            headers.get_all("ACCEPT").collect::<Vec<_>>(),
+            ["text/html", "application/json"]
+        );
+    }
+
+    #[test]
+    fn insert_replaces_and_remove_drops_every_spelling() {
+        let mut headers = Headers::new();
+        headers.append("Vary", "Accept");
+        headers.append("vary", "Accept-Encoding");
+        headers.insert("VARY", "Origin");
+        assert_eq!(headers.get_all("Vary").collect::<Vec<_>>(), ["Origin"]);
+
+        headers.remove("vary");
+        assert!(headers.is_empty());
+    }
+}
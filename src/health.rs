@@ -0,0 +1,262 @@
+use crate::{
+    Method, Middleware, Next, Request, RequestError, Response, ServerConfig, ShutdownHandle,
+    StatusCode,
+};
+
+use std::fmt::Write;
+
+/// A readiness check: `Ok(())` if the server may take traffic, otherwise the reason it can't.
+pub type ReadinessCheck = Box<dyn Fn() -> Result<(), String> + Send + Sync>;
+
+/// Liveness and readiness probes for orchestrators, on [`crate::HealthConfig`]'s paths.
+///
+/// - Liveness (`/healthz`) answers `200 OK` whenever a worker gets to the request.
+/// - Readiness (`/readyz`) runs every check and answers `200 OK` if all pass, otherwise
+///   `503 Service Unavailable`. The built-in checks fail when the document root can't be
+///   canonicalized, when an error page is missing (the `404` page and every `error_pages` entry),
+///   and once a graceful shutdown has started. More checks are added with
+///   [`crate::Server::with_readiness_check`].
+///
+/// Both answer `GET` and `HEAD` with a `text/plain` body listing each check as `ok <name>` or
+/// `failed <name>: <reason>`. As a [`Middleware`], everything else is passed on.
+pub struct Health {
+    liveness_path: String,
+    readiness_path: String,
+    shutdown: ShutdownHandle,
+    checks: Vec<(String, ReadinessCheck)>,
+}
+
+impl Health {
+    /// Probes on the paths in `config`, failing readiness once `shutdown` is requested.
+    pub fn new(config: &ServerConfig, shutdown: ShutdownHandle) -> Self {
+        Self {
+            liveness_path: config.health.liveness_path.clone(),
+            readiness_path: config.health.readiness_path.clone(),
+            shutdown,
+            checks: Vec::new(),
+        }
+    }
+
+    /// Adds a readiness check, reported under `name`.
+    pub fn with_check(
+        mut self,
+        name: impl Into<String>,
+        check: impl Fn() -> Result<(), String> + Send + Sync + 'static,
+    ) -> Self {
+        self.checks.push((name.into(), Box::new(check)));
+        self
+    }
+
+    /// Adds checks built elsewhere, see [`crate::Server::with_readiness_check`].
+    pub(crate) fn with_checks(mut self, checks: Vec<(String, ReadinessCheck)>) -> Self {
+        self.checks.extend(checks);
+        self
+    }
+
+    /// Runs the built-in checks, then the added ones, in order.
+    ///
+    /// # Returns
+    /// Each check's name with its outcome.
+    pub fn readiness(&self, config: &ServerConfig) -> Vec<(String, Result<(), String>)> {
+        let mut results = vec![
+            ("shutdown".to_string(), self.check_shutdown()),
+            ("document_root".to_string(), check_document_root(config)),
+            ("error_pages".to_string(), check_error_pages(config)),
+        ];
+        for (name, check) in &self.checks {
+            results.push((name.clone(), check()));
+        }
+        results
+    }
+
+    /// This function is a private helper function for [`Health::readiness`].
+    /// - It fails once a graceful shutdown has been requested.
+    fn check_shutdown(&self) -> Result<(), String> {
+        if self.shutdown.is_shutting_down() {
+            Err("the server is shutting down".to_string())
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl Middleware for Health {
+    fn handle(
+        &self,
+        request: &mut Request,
+        config: &ServerConfig,
+        next: Next<'_>,
+    ) -> Result<Response, RequestError> {
+        if !matches!(request.method(), Method::Get | Method::Head) {
+            return next.run(request, config);
+        }
+
+        let (status, body) = if request.path() == self.liveness_path {
+            (StatusCode::Ok, "ok\n".to_string())
+        } else if request.path() == self.readiness_path {
+            let results = self.readiness(config);
+            let mut body = String::new();
+            for (name, result) in &results {
+                let _ = match result {
+                    Ok(()) => writeln!(body, "ok {name}"),
+                    Err(reason) => writeln!(body, "failed {name}: {reason}"),
+                };
+            }
+            let status = if results.iter().all(|(_, result)| result.is_ok()) {
+                StatusCode::Ok
+            } else {
+                StatusCode::ServiceUnavailable
+            };
+            (status, body)
+        } else {
+            return next.run(request, config);
+        };
+
+        Ok(Response::new(status)
+            .with_header("Content-Type", "text/plain; charset=utf-8")
+            .with_header("Cache-Control", "no-store")
+            .with_bytes(body))
+    }
+}
+
+/// This function is a private helper function for [`Health::readiness`].
+/// - It fails if the document root can't be canonicalized, which every file lookup needs.
+fn check_document_root(config: &ServerConfig) -> Result<(), String> {
+    let root = if config.document_root.as_os_str().is_empty() {
+        std::path::Path::new(".")
+    } else {
+        &config.document_root
+    };
+    root.canonicalize().map(|_| ()).map_err(|error| {
+        format!(
+            "`{}` cannot be canonicalized: {error}",
+            config.document_root.display()
+        )
+    })
+}
+
+/// This function is a private helper function for [`Health::readiness`].
+/// - It fails if the `404` page or a page listed in `error_pages` is not a file.
+fn check_error_pages(config: &ServerConfig) -> Result<(), String> {
+    let mut codes: Vec<u16> = config.error_pages.keys().copied().collect();
+    codes.push(StatusCode::NotFound.code());
+    codes.sort_unstable();
+    codes.dedup();
+
+    let missing: Vec<String> = codes
+        .into_iter()
+        .map(|code| config.error_page_path(code))
+        .filter(|path| !path.is_file())
+        .map(|path| format!("`{}`", path.display()))
+        .collect();
+    if missing.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("missing {}", missing.join(", ")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Body;
+
+    use std::io::BufReader;
+    use std::net::{Ipv4Addr, SocketAddr};
+
+    /// A handle for a server that isn't listening: shutting it down doesn't reach anything.
+    fn shutdown_handle() -> ShutdownHandle {
+        ShutdownHandle::new(SocketAddr::from((Ipv4Addr::LOCALHOST, 9)))
+    }
+
+    /// Runs `method path` through `health`, in front of an endpoint answering `404`.
+    ///
+    /// # Returns
+    /// The status and the body.
+    fn probe(health: Health, method: &str, path: &str) -> (StatusCode, String) {
+        let raw = format!("{method} {path} HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        let mut request = Request::new(&mut BufReader::new(raw.as_bytes())).unwrap();
+        let chain: [Box<dyn Middleware>; 1] = [Box::new(health)];
+        let fallback = |_: &mut Request, _: &ServerConfig| Ok(Response::new(StatusCode::NotFound));
+        let response = Next::new(&chain, &fallback)
+            .run(&mut request, &ServerConfig::default())
+            .unwrap();
+        let body = match response.body() {
+            Body::Bytes(bytes) => String::from_utf8_lossy(bytes).into_owned(),
+            _ => String::new(),
+        };
+        (response.status(), body)
+    }
+
+    #[test]
+    fn liveness_answers_ok() {
+        let health = Health::new(&ServerConfig::default(), shutdown_handle());
+        assert_eq!(
+            probe(health, "GET", "/healthz"),
+            (StatusCode::Ok, "ok\n".to_string())
+        );
+    }
+
+    #[test]
+    fn readiness_lists_every_check() {
+        let health = Health::new(&ServerConfig::default(), shutdown_handle())
+            .with_check("database", || Ok(()));
+        assert_eq!(
+            probe(health, "GET", "/readyz"),
+            (
+                StatusCode::Ok,
+                "ok shutdown\nok document_root\nok error_pages\nok database\n".to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn readiness_is_503_while_shutting_down() {
+        let shutdown = shutdown_handle();
+        let health = Health::new(&ServerConfig::default(), shutdown.clone());
+        shutdown.shutdown();
+
+        let (status, body) = probe(health, "GET", "/readyz");
+        assert_eq!(status, StatusCode::ServiceUnavailable);
+        assert!(
+            body.starts_with("failed shutdown: the server is shutting down\n"),
+            "{body}"
+        );
+        /* Liveness doesn't care. */
+        let health = Health::new(&ServerConfig::default(), shutdown);
+        assert_eq!(probe(health, "GET", "/healthz").0, StatusCode::Ok);
+    }
+
+    #[test]
+    fn a_failing_check_or_missing_files_make_readiness_503() {
+        let health = Health::new(&ServerConfig::default(), shutdown_handle())
+            .with_check("database", || Err("connection refused".to_string()));
+        let (status, body) = probe(health, "GET", "/readyz");
+        assert_eq!(status, StatusCode::ServiceUnavailable);
+        assert!(
+            body.ends_with("failed database: connection refused\n"),
+            "{body}"
+        );
+
+        let config = ServerConfig {
+            document_root: "/nonexistent/root".into(),
+            error_page_dir: "/nonexistent/pages".into(),
+            ..ServerConfig::default()
+        };
+        let readiness = Health::new(&config, shutdown_handle()).readiness(&config);
+        assert!(readiness[1].1.is_err());
+        assert!(readiness[2].1.is_err());
+    }
+
+    #[test]
+    fn other_paths_and_methods_are_passed_on() {
+        for (method, path) in [("GET", "/"), ("POST", "/readyz"), ("DELETE", "/healthz")] {
+            let health = Health::new(&ServerConfig::default(), shutdown_handle());
+            assert_eq!(
+                probe(health, method, path).0,
+                StatusCode::NotFound,
+                "{method} {path}"
+            );
+        }
+    }
+}
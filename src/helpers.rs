@@ -1,50 +1,316 @@
-use std::fs::File;
-use std::io::{BufReader, BufWriter, Write};
-use std::net::TcpStream;
-use std::path::{Path, PathBuf};
-use crate::{Request, RequestError, BASE_DIR};
+use crate::connection::{Phase, TimedConnection};
+use crate::{
+    AccessLog, AccessLogEntry, Connection, Method, Metrics, Request, RequestError, Response,
+    Router, ServerConfig, ShutdownHandle, StatusCode, compress_response,
+};
+use std::io::{BufRead, BufReader, ErrorKind, Read};
+use std::net::{Shutdown, TcpStream};
+use std::time::{Duration, Instant, SystemTime};
 
+/// How often an idle persistent connection checks whether the server is shutting down.
+static IDLE_POLL_INTERVAL: Duration = Duration::from_millis(250);
 
-/// Parses an HTTP request from the given TCP stream and sends an HTTP response.
+/// How long [`reject_connection`] waits for the client to send its request or close, at most.
+static REJECT_DRAIN_TIMEOUT: Duration = Duration::from_millis(10);
+
+/// How many request bytes [`reject_connection`] reads and throws away, at most.
+static REJECT_DRAIN_LIMIT: usize = 16 * 1024;
+
+/// Serves HTTP requests from the given connection until it should close.
+///
+/// The connection is persistent (keep-alive): after each response the next request is read from
+/// the same stream. The connection is closed when
+/// - the client asks for it (`Connection: close`, or an `HTTP/1.0` request without `Connection: keep-alive`),
+/// - `limits.max_keep_alive_requests` requests have been served,
+/// - no new request starts within `timeouts.keep_alive`,
+/// - an `HTTP/1.0` response has a body of unknown length, which only the close can end,
+/// - or the server is shutting down (checked between requests).
 ///
-/// If the requested HTML file exists, a `200 OK` response is sent with the file
-/// contents. Otherwise, a `404 NOT FOUND` response is sent using `{`[`BASE_DIR`]`}/error404.html`.
+/// Every request is held to `config.timeouts` (see [`crate::TimeoutConfig`]): a client that is
+/// too slow sending it gets `408 Request Timeout`, one too slow reading the response is dropped.
 ///
 /// # Arguments
-/// - `tcp_stream`: Mutable reference to the client [`TcpStream`].
+/// - `connection`: The client connection, a [`TcpStream`] or (with the `tls` feature) a
+///   [`crate::TlsStream`].
+/// - `router`: The [`Router`] that picks the handler for each request.
+/// - `config`: The [`ServerConfig`] with the document root, limits, timeouts and error pages.
+/// - `shutdown`: The server's [`ShutdownHandle`], so idle connections close promptly on shutdown.
+/// - `access_log`: Where each answered request is logged, if anywhere.
+/// - `metrics`: The registry counting requests and open connections, if metrics are enabled.
 ///
 /// # Returns
-/// - `Ok(())`: if the request is handled successfully.
-/// - `Err(RequestError)`: if request parsing, file access, or response writing fails.
-pub fn handle_connection(tcp_stream: &mut TcpStream) -> Result<(), RequestError> {
-    let request = Request::new(tcp_stream)?;
-
-    let file: File;
-    let mut status = String::from("HTTP/1.1 ");
-
-    if let Some(url) = request.path_exists() {
-        file = File::open(url)?;
-        status.push_str("200 Ok");
-    } else {
-        let url = "error404";
-        let mut full_url = if BASE_DIR.is_empty() {
-            PathBuf::from(url)
+/// - `Ok(())`: if every request is handled and the connection ends cleanly. A request that fails
+///   to parse is answered with the matching error status (see [`RequestError::status`]) and an
+///   error page, and also counts as handled. So is a request whose handler fails: it gets the
+///   error's status, or `500 Internal Server Error` for an I/O error, and the connection closes.
+/// - `Err(RequestError)`: if the stream breaks, a file can't be read, or response writing fails
+///   or times out.
+pub fn handle_connection(
+    connection: &mut impl Connection,
+    router: &Router,
+    config: &ServerConfig,
+    shutdown: &ShutdownHandle,
+    access_log: Option<&AccessLog>,
+    metrics: Option<&Metrics>,
+) -> Result<(), RequestError> {
+    let remote_addr = connection.peer_addr().ok();
+    let connected = Instant::now();
+    let mut reader = BufReader::new(TimedConnection::new(connection, &config.timeouts)?);
+    let _active = metrics.map(Metrics::track_connection);
+
+    let max_requests = config.limits.max_keep_alive_requests;
+
+    for served in 0..max_requests {
+        /* The first request counts from the connection, later ones from their first byte. */
+        let idle = served > 0;
+        let (wait, request_started) = if idle {
+            (config.timeouts.keep_alive, None)
         } else {
-            Path::new(BASE_DIR).join(url)
+            let timeouts = &config.timeouts;
+            (timeouts.request_line.min(timeouts.headers), Some(connected))
         };
-        full_url.set_extension("html");
-        file = File::open(full_url)?;
-        status.push_str("404 NOT FOUND");
+        match wait_for_request(&mut reader, wait, idle, shutdown)? {
+            Wait::Ready => {}
+            Wait::Closed => return Ok(()),
+            /* An idle connection just closes; a new one that sent nothing gets its 408 below. */
+            Wait::TimedOut if idle => return Ok(()),
+            Wait::TimedOut => {}
+        }
+        let phase = Phase::RequestLine.advance(reader.buffer());
+        reader
+            .get_mut()
+            .start_request(request_started.unwrap_or_else(Instant::now), phase);
+        let started = Instant::now();
+        let mut entry = AccessLogEntry {
+            remote_addr,
+            time: SystemTime::now(),
+            request_line: None,
+            status: 0,
+            bytes_sent: 0,
+            duration: Duration::ZERO,
+            referer: None,
+            user_agent: None,
+        };
+
+        let mut request = match Request::with_limits(&mut reader, &config.limits) {
+            Ok(request) => request,
+            /* The rest of the stream can't be trusted after a bad request, so always close. */
+            Err(error) => {
+                let Some(status) = error.status() else {
+                    return Err(error);
+                };
+                reader.get_mut().start_response();
+                entry.status = status.code();
+                entry.bytes_sent = error_response(status, config)
+                    .with_header("Connection", "close")
+                    .write_to(reader.get_mut())?;
+                entry.duration = started.elapsed();
+                record(&entry, None, access_log, metrics);
+                return Ok(());
+            }
+        };
+
+        request.set_remote_addr(remote_addr);
+        let mut keep_alive =
+            request.keep_alive() && served + 1 < max_requests && !shutdown.is_shutting_down();
+        let response = match router.handle(&mut request, config) {
+            Ok(response) => response,
+            /* A failed handler still owes the client an answer, just not a persistent connection. */
+            Err(error) => {
+                eprintln!("Failed to handle `{request}`: {error}");
+                keep_alive = false;
+                let status = error.status().unwrap_or(StatusCode::InternalServerError);
+                error_response(status, config)
+            }
+        };
+        let mut response = compress_response(response, &request, &config.compression)?;
+
+        /* HTTP/1.0 has no chunked framing: a body of unknown length ends when the connection does. */
+        if request.version() == "HTTP/1.0"
+            && !response.status().forbids_body()
+            && response.body().len()?.is_none()
+        {
+            response = response.close_delimited();
+            keep_alive = false;
+        }
+
+        /* HTTP/1.1 connections persist by default, HTTP/1.0 ones have to be told. */
+        match (keep_alive, request.version()) {
+            (false, _) => response.headers_mut().insert("Connection", "close"),
+            (true, "HTTP/1.0") => response.headers_mut().insert("Connection", "keep-alive"),
+            (true, _) => {}
+        }
+        if request.method() == Method::Head {
+            response = response.omit_body();
+        }
+        entry.status = response.status().code();
+        /* Only now, so the time the handler takes doesn't count against the client's rate. */
+        reader.get_mut().start_response();
+        entry.bytes_sent = response.write_to(reader.get_mut())?;
+        entry.duration = started.elapsed();
+
+        if access_log.is_some() {
+            entry.request_line = Some(request.to_string());
+            entry.referer = request.header("Referer").map(str::to_string);
+            entry.user_agent = request.header("User-Agent").map(str::to_string);
+        }
+        record(&entry, Some(request.method()), access_log, metrics);
+
+        if !keep_alive {
+            return Ok(());
+        }
+    }
+
+    Ok(())
+}
+
+/// This function is a private helper function for [`handle_connection`].
+/// - It writes the access log line for an answered request and counts it in the metrics.
+/// - `method` is `None` for a request that couldn't be parsed.
+fn record(
+    entry: &AccessLogEntry,
+    method: Option<Method>,
+    access_log: Option<&AccessLog>,
+    metrics: Option<&Metrics>,
+) {
+    if let Some(access_log) = access_log {
+        access_log.log(entry);
+    }
+    if let Some(metrics) = metrics {
+        metrics.record_request(method, entry.status, entry.duration, entry.bytes_sent);
+    }
+}
+
+/// What [`wait_for_request`] saw.
+///
+/// # Variants
+/// - `Ready`: A request is waiting to be read.
+/// - `Closed`: The client closed the connection, or it was idle and the server is shutting down.
+/// - `TimedOut`: Nothing arrived in time.
+enum Wait {
+    Ready,
+    Closed,
+    TimedOut,
+}
+
+/// This function is a private helper function for [`handle_connection`].
+/// - It blocks until the first byte of the next request is available, for at most `timeout`.
+/// - Between requests (`idle` is `true`) it also gives up as soon as a shutdown is requested.
+/// - Once data arrives the read timeout is cleared again for the rest of the request.
+///
+/// # Returns
+/// - `Ok(Wait)`: See [`Wait`].
+/// - `Err(RequestError::Io(_))`: If reading from the stream fails.
+fn wait_for_request(
+    reader: &mut BufReader<impl Connection>,
+    timeout: Duration,
+    idle: bool,
+    shutdown: &ShutdownHandle,
+) -> Result<Wait, RequestError> {
+    /* A timeout too long to add to the clock never runs out. */
+    let deadline = Instant::now().checked_add(timeout);
+
+    loop {
+        if idle && shutdown.is_shutting_down() {
+            return Ok(Wait::Closed);
+        }
+        let remaining = deadline.map_or(timeout, |deadline| {
+            deadline.saturating_duration_since(Instant::now())
+        });
+        if remaining.is_zero() {
+            return Ok(Wait::TimedOut);
+        }
+
+        reader
+            .get_ref()
+            .set_read_timeout(Some(remaining.min(IDLE_POLL_INTERVAL)))?;
+        match reader.fill_buf() {
+            Ok(buf) => {
+                let wait = if buf.is_empty() {
+                    Wait::Closed
+                } else {
+                    Wait::Ready
+                };
+                reader.get_ref().set_read_timeout(None)?;
+                return Ok(wait);
+            }
+            Err(error)
+                if matches!(
+                    error.kind(),
+                    ErrorKind::WouldBlock | ErrorKind::TimedOut | ErrorKind::Interrupted
+                ) =>
+            {
+                continue;
+            }
+            Err(error) => return Err(error.into()),
+        }
     }
-    let mut buf_writer = BufWriter::new(tcp_stream);
-    write!(buf_writer, "{status}\r\n")?;
-    write!(buf_writer, "Content-Length: {}\r\n", file.metadata()?.len())?;
-    write!(buf_writer, "Content-Type: text/html; charset=utf-8\r\n")?;
-    write!(buf_writer, "\r\n")?;
+}
 
-    let mut buf_reader = BufReader::new(&file);
-    std::io::copy(&mut buf_reader, &mut buf_writer)?;
-    buf_writer.flush()?;
+/// Answers a connection that is turned away with `status` and an empty body.
+///
+/// Used with `503 Service Unavailable` when the [`crate::ThreadPool`] queue is full under
+/// [`crate::QueuePolicy::Reject`], and with `429 Too Many Requests` when the client IP holds too
+/// many connections (see [`crate::RateLimiter::open_connection`]).
+/// The request itself is never parsed. After the response the write side is shut down and
+/// whatever the client sent is read and dropped, for at most [`REJECT_DRAIN_TIMEOUT`] and
+/// [`REJECT_DRAIN_LIMIT`] bytes: closing a socket with unread data resets the connection, and
+/// the client would lose the response (and its `Retry-After`) before reading it.
+///
+/// # Arguments
+/// - `tcp_stream`: Mutable reference to the client [`TcpStream`].
+/// - `status`: The status to answer with.
+///
+/// # Returns
+/// - `Ok(())`: if the response is written.
+/// - `Err(RequestError::Io(_))`: if writing the response fails.
+pub fn reject_connection(
+    tcp_stream: &mut TcpStream,
+    status: StatusCode,
+) -> Result<(), RequestError> {
+    Response::new(status)
+        .with_header("Retry-After", "1")
+        .with_header("Connection", "close")
+        .write_to(&mut *tcp_stream)?;
+    tcp_stream.shutdown(Shutdown::Write)?;
+
+    /* Runs on the accept loop, so it must stay short whatever the client does. */
+    let deadline = Instant::now() + REJECT_DRAIN_TIMEOUT;
+    let mut buf = [0u8; 4096];
+    let mut drained = 0;
+    while drained < REJECT_DRAIN_LIMIT {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            break;
+        }
+        tcp_stream.set_read_timeout(Some(remaining))?;
+        match tcp_stream.read(&mut buf) {
+            Ok(0) | Err(_) => break,
+            Ok(read) => drained += read,
+        }
+    }
 
     Ok(())
 }
+
+/// Builds an HTML error response for `status`, with the matching error page (see [`error_page`]) as body.
+pub fn error_response(status: StatusCode, config: &ServerConfig) -> Response {
+    Response::new(status)
+        .with_header("Content-Type", "text/html; charset=utf-8")
+        .with_bytes(error_page(status, config))
+}
+
+/// Returns the HTML body for an error response with the given status.
+///
+/// The page is read from [`ServerConfig::error_page_path`]. If that file is missing or
+/// unreadable, a minimal built-in page showing the code and reason phrase is returned instead,
+/// so an error response never fails because of a missing page.
+pub fn error_page(status: StatusCode, config: &ServerConfig) -> Vec<u8> {
+    match std::fs::read(config.error_page_path(status.code())) {
+        Ok(page) => page,
+        Err(_) => format!(
+            "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n    <meta charset=\"utf-8\">\n    <title>{status}</title>\n</head>\n<body>\n<h1>{status}</h1>\n</body>\n</html>\n"
+        )
+        .into_bytes(),
+    }
+}
@@ -1,8 +1,88 @@
 pub mod request;
-pub use request::{Request, RequestError};
+pub use request::{Request, RequestError, resolve_directory, resolve_file};
+
+pub mod url;
+pub use url::{decode_target, parse_query, percent_decode, percent_encode, remove_dot_segments};
+
+pub mod method;
+pub use method::Method;
+
+pub mod headers;
+pub use headers::Headers;
+
+mod body;
+
+pub mod response;
+pub use response::{Body, ChunkedWriter, Response, SERVER_NAME, StatusCode};
+
+pub mod date;
+pub use date::{DateTime, http_date, parse_http_date};
+
+pub mod conditional;
+pub use conditional::{Validators, not_modified};
+
+pub mod range;
+pub use range::{ByteRange, MAX_RANGES, RangeRequest, parse_range, partial_response};
+
+pub mod compression;
+pub use compression::{
+    Encoding, PRECOMPRESSED_ENCODINGS, SUPPORTED_ENCODINGS, add_vary_accept_encoding,
+    compress_response, is_compressible, negotiate_encoding, precompressed_variants,
+};
+
+pub mod listing;
+pub use listing::{DirectoryListing, ListingEntry, SortKey, listing_response};
+
+pub mod access_log;
+pub use access_log::{AccessLog, AccessLogEntry, LogFormat};
+
+pub mod health;
+pub use health::{Health, ReadinessCheck};
+
+pub mod metrics;
+pub use metrics::{ActiveConnection, Metrics, MetricsEndpoint};
+
+pub mod rate_limit;
+pub use rate_limit::{ConnectionPermit, RateLimiter};
+
+pub mod mime;
+pub use mime::{DEFAULT_MIME_TYPE, MimeTypes};
+
+pub mod middleware;
+pub use middleware::{Middleware, Next};
+
+pub mod router;
+pub use router::{Handler, Pattern, Router};
+
+pub mod static_files;
+pub use static_files::{ALLOWED_METHODS, StaticFiles};
+
+pub mod connection;
+pub use connection::Connection;
+
+#[cfg(feature = "tls")]
+pub mod tls;
+#[cfg(feature = "tls")]
+pub use tls::{CertificateStore, HttpsRedirect, TlsError, TlsStream};
 
 pub mod helpers;
 pub use helpers::*;
 
 pub mod constants;
 pub use constants::*;
+
+pub mod thread_pool;
+pub use thread_pool::{PoolError, QueuePolicy, Rejected, ThreadPool};
+
+pub mod shutdown;
+pub use shutdown::ShutdownHandle;
+
+pub mod server;
+pub use server::{Server, ServerError};
+
+pub mod config;
+pub use config::{
+    CompressionConfig, ConfigError, DEFAULT_CONFIG_FILE, ENV_PREFIX, HealthConfig, Limits,
+    ListingConfig, LoggingConfig, MetricsConfig, RateLimitConfig, ServerConfig, SniCertificate,
+    TimeoutConfig, TlsConfig, USAGE,
+};
@@ -0,0 +1,426 @@
+use crate::{DateTime, Request, Response, StatusCode, http_date, percent_encode};
+
+use serde::Serialize;
+use std::cmp::Ordering;
+use std::fmt::Write;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::SystemTime;
+
+/// One file or subdirectory shown in a [`DirectoryListing`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListingEntry {
+    pub name: String,
+    pub is_dir: bool,
+    /// The file size in bytes. `None` for directories.
+    pub size: Option<u64>,
+    /// The modification time, if the platform reports one.
+    pub modified: Option<SystemTime>,
+}
+
+/// The column a listing is sorted by, chosen with the `sort` query parameter
+/// (`name`, `size` or `modified`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SortKey {
+    #[default]
+    Name,
+    Size,
+    Modified,
+}
+
+impl SortKey {
+    /// Returns the name used in the `sort` query parameter.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SortKey::Name => "name",
+            SortKey::Size => "size",
+            SortKey::Modified => "modified",
+        }
+    }
+}
+
+impl FromStr for SortKey {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "name" => Ok(SortKey::Name),
+            "size" => Ok(SortKey::Size),
+            "modified" => Ok(SortKey::Modified),
+            other => Err(format!("unknown sort key `{other}`")),
+        }
+    }
+}
+
+/// The contents of a directory, ready to be rendered as HTML or JSON.
+///
+/// Directories always come before files; within each group the entries follow the [`SortKey`].
+#[derive(Debug, Clone)]
+pub struct DirectoryListing {
+    path: String,
+    entries: Vec<ListingEntry>,
+    sort: SortKey,
+    descending: bool,
+}
+
+impl DirectoryListing {
+    /// Reads the entries of `directory`, sorted by name.
+    ///
+    /// - Dotfiles (names starting with `.`) are hidden.
+    /// - Every entry is canonicalized and must stay inside `base_dir`, like [`Request::path_exists`],
+    ///   so symlinks pointing out of the document root (or nowhere) are not shown.
+    ///
+    /// # Arguments
+    /// - `directory`: The canonical directory path, see [`Request::directory_exists`].
+    /// - `base_dir`: The canonical document root.
+    /// - `url_path`: The URL path of the directory, used as the page title and for links.
+    ///
+    /// # Returns
+    /// - `Ok(DirectoryListing)`: The listing.
+    /// - `Err(io::Error)`: If the directory can't be read.
+    pub fn read(directory: &Path, base_dir: &Path, url_path: &str) -> io::Result<Self> {
+        let mut entries = Vec::new();
+        for entry in std::fs::read_dir(directory)? {
+            let entry = entry?;
+            let Some(name) = entry.file_name().to_str().map(str::to_string) else {
+                continue;
+            };
+            if name.starts_with('.') {
+                continue;
+            }
+            let Ok(target) = entry.path().canonicalize() else {
+                continue;
+            };
+            if !target.starts_with(base_dir) {
+                continue;
+            }
+            let Ok(metadata) = target.metadata() else {
+                continue;
+            };
+
+            entries.push(ListingEntry {
+                name,
+                is_dir: metadata.is_dir(),
+                size: metadata.is_file().then_some(metadata.len()),
+                modified: metadata.modified().ok(),
+            });
+        }
+
+        let mut listing = Self {
+            path: url_path.to_string(),
+            entries,
+            sort: SortKey::Name,
+            descending: false,
+        };
+        listing.sort(SortKey::Name, false);
+        Ok(listing)
+    }
+
+    /// Sorts the entries by `key`, keeping directories first. Ties are broken by name.
+    pub fn sort(&mut self, key: SortKey, descending: bool) {
+        self.sort = key;
+        self.descending = descending;
+        self.entries.sort_by(|a, b| {
+            let order = match key {
+                SortKey::Name => Ordering::Equal,
+                SortKey::Size => a.size.cmp(&b.size),
+                SortKey::Modified => a.modified.cmp(&b.modified),
+            }
+            .then_with(|| a.name.cmp(&b.name));
+            let order = if descending { order.reverse() } else { order };
+            b.is_dir.cmp(&a.is_dir).then(order)
+        });
+    }
+
+    /// The entries in their current order.
+    pub fn entries(&self) -> &[ListingEntry] {
+        &self.entries
+    }
+
+    /// Renders the listing as an HTML page with name, size and modification time columns.
+    /// The column headers link to the listing sorted by that column.
+    pub fn to_html(&self) -> String {
+        let title = escape_html(&self.path);
+        let mut html = format!(
+            "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n    <meta charset=\"utf-8\">\n    <title>Index of {title}</title>\n</head>\n<body>\n<h1>Index of {title}</h1>\n<table>\n<thead>\n<tr>"
+        );
+        for (key, label) in [
+            (SortKey::Name, "Name"),
+            (SortKey::Size, "Size"),
+            (SortKey::Modified, "Last modified"),
+        ] {
+            /* Clicking the current column again flips the order. */
+            let order = if key == self.sort && !self.descending {
+                "desc"
+            } else {
+                "asc"
+            };
+            let _ = write!(
+                html,
+                "<th><a href=\"?sort={}&amp;order={order}\">{label}</a></th>",
+                key.as_str()
+            );
+        }
+        html.push_str("</tr>\n</thead>\n<tbody>\n");
+
+        if self.path != "/" {
+            html.push_str("<tr><td><a href=\"../\">../</a></td><td></td><td></td></tr>\n");
+        }
+        for entry in &self.entries {
+            let suffix = if entry.is_dir { "/" } else { "" };
+            let size = entry.size.map(format_size).unwrap_or_default();
+            let modified = entry.modified.map(format_time).unwrap_or_default();
+            let _ = writeln!(
+                html,
+                "<tr><td><a href=\"{}{suffix}\">{}{suffix}</a></td><td>{size}</td><td>{modified}</td></tr>",
+                escape_html(&percent_encode(&entry.name)),
+                escape_html(&entry.name),
+            );
+        }
+
+        html.push_str("</tbody>\n</table>\n</body>\n</html>\n");
+        html
+    }
+
+    /// Renders the listing as JSON:
+    ///
+    /// ```json
+    /// {"path": "/docs/", "entries": [{"name": "a.txt", "type": "file", "size": 12, "modified": "Thu, 15 Oct 2026 09:21:28 GMT"}]}
+    /// ```
+    ///
+    /// `size` is `null` for directories, `modified` is `null` if unknown.
+    pub fn to_json(&self) -> String {
+        #[derive(Serialize)]
+        struct Listing<'a> {
+            path: &'a str,
+            entries: Vec<Entry<'a>>,
+        }
+        #[derive(Serialize)]
+        struct Entry<'a> {
+            name: &'a str,
+            #[serde(rename = "type")]
+            kind: &'static str,
+            size: Option<u64>,
+            modified: Option<String>,
+        }
+
+        let listing = Listing {
+            path: &self.path,
+            entries: self
+                .entries
+                .iter()
+                .map(|entry| Entry {
+                    name: &entry.name,
+                    kind: if entry.is_dir { "directory" } else { "file" },
+                    size: entry.size,
+                    modified: entry.modified.map(http_date),
+                })
+                .collect(),
+        };
+        serde_json::to_string(&listing).unwrap_or_default()
+    }
+}
+
+/// Builds the response listing `directory`.
+///
+/// - A URL without a trailing `/` is redirected (`301`) to the same path with one, so the relative
+///   links in the page resolve inside the directory (see [`directory_location`]).
+/// - `?sort=name|size|modified` and `&order=asc|desc` pick the order; unknown values are ignored.
+/// - JSON is sent when the `Accept` header prefers `application/json` over `text/html`.
+///
+/// # Arguments
+/// - `request`: The request naming the directory.
+/// - `directory`: The canonical directory path, see [`Request::directory_exists`].
+/// - `base_dir`: The document root.
+///
+/// # Returns
+/// - `Ok(Response)`: The listing or the redirect.
+/// - `Err(io::Error)`: If the document root can't be canonicalized or the directory can't be read.
+pub fn listing_response(
+    request: &Request,
+    directory: &Path,
+    base_dir: &Path,
+) -> io::Result<Response> {
+    let path = request.path();
+    if !path.ends_with('/') {
+        let location = directory_location(path, request.query_string());
+        return Ok(Response::new(StatusCode::MovedPermanently).with_header("Location", location));
+    }
+
+    let base_dir = if base_dir.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        base_dir
+    };
+    let mut listing = DirectoryListing::read(directory, &base_dir.canonicalize()?, path)?;
+
+    let sort = request
+        .query_as::<SortKey>("sort")
+        .and_then(Result::ok)
+        .unwrap_or_default();
+    let descending = request.query("order") == Some("desc");
+    listing.sort(sort, descending);
+
+    let response = if prefers_json(request.header("Accept")) {
+        Response::new(StatusCode::Ok)
+            .with_header("Content-Type", "application/json")
+            .with_bytes(listing.to_json())
+    } else {
+        Response::new(StatusCode::Ok)
+            .with_header("Content-Type", "text/html; charset=utf-8")
+            .with_bytes(listing.to_html())
+    };
+    Ok(response.with_header("Vary", "Accept"))
+}
+
+/// This function is a private helper function for [`listing_response`].
+/// - It builds the `Location` of the trailing-slash redirect from the decoded `path`, never from
+///   the raw request target, so `//evil.example/dir` or `http://evil.example/dir` can't send the
+///   client to another host.
+/// - Empty and `.` segments are dropped and `..` removes the segment before it; the rest are
+///   percent-encoded again and joined under a single leading `/`.
+/// - The query string is kept as the client sent it.
+fn directory_location(path: &str, query: Option<&str>) -> String {
+    let mut segments = Vec::new();
+    for segment in path.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            segment => segments.push(percent_encode(segment)),
+        }
+    }
+
+    let mut location = String::from("/");
+    for segment in segments {
+        location.push_str(&segment);
+        location.push('/');
+    }
+    if let Some(query) = query {
+        location.push('?');
+        location.push_str(query);
+    }
+    location
+}
+
+/// Returns `true` if the `Accept` value weighs `application/json` higher than `text/html`.
+///
+/// Media ranges are matched most specific first (`application/json`, then `application/*`, then
+/// `*/*`), and each may carry a `q` weight.
+fn prefers_json(accept: Option<&str>) -> bool {
+    let Some(accept) = accept else {
+        return false;
+    };
+
+    let ranges: Vec<(&str, f32)> = accept
+        .split(',')
+        .filter_map(|item| {
+            let mut parameters = item.split(';');
+            let range = parameters.next()?.trim();
+            let weight = parameters
+                .filter_map(|parameter| parameter.trim().split_once('='))
+                .find(|(name, _)| name.trim().eq_ignore_ascii_case("q"))
+                .map_or(Some(1.0), |(_, value)| value.trim().parse::<f32>().ok())?;
+            Some((range, weight))
+        })
+        .collect();
+    let weight_of = |mime: &str| {
+        let (kind, _) = mime.split_once('/').unwrap_or((mime, ""));
+        let wildcard = format!("{kind}/*");
+        [mime, wildcard.as_str(), "*/*"]
+            .iter()
+            .find_map(|candidate| {
+                ranges
+                    .iter()
+                    .find(|(range, _)| range.eq_ignore_ascii_case(candidate))
+                    .map(|(_, weight)| *weight)
+            })
+            .unwrap_or(0.0)
+    };
+
+    let json = weight_of("application/json");
+    json > 0.0 && json > weight_of("text/html")
+}
+
+/// Formats a byte count for humans: `512 B`, `1.5 KiB`, `3.2 MiB`, ...
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["KiB", "MiB", "GiB", "TiB", "PiB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{size:.1} {}", UNITS[unit])
+}
+
+/// Formats a time as `YYYY-MM-DD HH:MM` in UTC.
+fn format_time(time: SystemTime) -> String {
+    let date = DateTime::from_system_time(time);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}",
+        date.year, date.month, date.day, date.hour, date.minute
+    )
+}
+
+/// Escapes the characters that are special in HTML text and attribute values.
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for char in text.chars() {
+        match char {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(char),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn directory_location_adds_the_slash_and_keeps_the_query() {
+        assert_eq!(directory_location("/docs", None), "/docs/");
+        assert_eq!(
+            directory_location("/my docs/api", Some("sort=size")),
+            "/my%20docs/api/?sort=size"
+        );
+    }
+
+    #[test]
+    fn directory_location_never_leaves_the_host() {
+        assert_eq!(
+            directory_location("//evil.example/dir", None),
+            "/evil.example/dir/"
+        );
+        assert_eq!(directory_location("/./../dir", None), "/dir/");
+        assert_eq!(
+            directory_location("/\\evil.example", None),
+            "/%5Cevil.example/"
+        );
+    }
+
+    #[test]
+    fn redirect_location_is_built_from_the_path_not_the_raw_target() {
+        let raw =
+            "GET http://evil.example//evil.example/docs?x=1 HTTP/1.1\r\nHost: localhost\r\n\r\n";
+        let request = Request::new(&mut std::io::BufReader::new(raw.as_bytes())).unwrap();
+        let response = listing_response(&request, Path::new("."), Path::new(".")).unwrap();
+
+        assert_eq!(response.status(), StatusCode::MovedPermanently);
+        assert_eq!(
+            response.headers().get("Location"),
+            Some("/evil.example/docs/?x=1")
+        );
+    }
+}
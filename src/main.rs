@@ -1,13 +1,37 @@
-use rust_server::{ADDRESS, handle_connection};
-use std::net::TcpListener;
+use rust_server::{ConfigError, Server, ServerConfig, USAGE};
+use std::process::ExitCode;
 
-fn main() {
-    let tcp_listener = TcpListener::bind(ADDRESS).unwrap();
-    println!("Attempting to bind a listener at: {ADDRESS}");
-    let connection_attempts = tcp_listener.incoming();
+fn main() -> ExitCode {
+    let config = match ServerConfig::load(std::env::args().skip(1), std::env::vars()) {
+        Ok(config) => config,
+        Err(ConfigError::Help) => {
+            print!("{USAGE}");
+            return ExitCode::SUCCESS;
+        }
+        Err(error) => {
+            eprintln!("{error}");
+            return ExitCode::from(2);
+        }
+    };
 
-    for connection_attempt in connection_attempts {
-        let mut connection = connection_attempt.unwrap();
-        let _ = handle_connection(&mut connection).unwrap();
+    let address = config.address.clone();
+    let server = match Server::from_config(config) {
+        Ok(server) => server,
+        Err(error) => {
+            eprintln!("Failed to start the server: {error}");
+            return ExitCode::FAILURE;
+        }
+    };
+    println!("Attempting to bind a listener at: {address}");
+    if let Err(error) = server.shutdown_handle().shutdown_on_signals() {
+        eprintln!("Failed to install the signal handler: {error}");
+        return ExitCode::FAILURE;
     }
+
+    if let Err(error) = server.run() {
+        eprintln!("Server failed: {error}");
+        return ExitCode::FAILURE;
+    }
+    println!("Server stopped.");
+    ExitCode::SUCCESS
 }
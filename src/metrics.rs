@@ -0,0 +1,405 @@
+use crate::{
+    Handler, Method, Middleware, Next, Request, RequestError, Response, ServerConfig, StatusCode,
+};
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Upper bounds, in seconds, of the response latency histogram buckets.
+pub static LATENCY_BUCKETS: &[f64] = &[
+    0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Upper bounds, in bytes, of the response size histogram buckets.
+pub static SIZE_BUCKETS: &[f64] = &[
+    100.0,
+    1_000.0,
+    10_000.0,
+    100_000.0,
+    1_000_000.0,
+    10_000_000.0,
+    100_000_000.0,
+];
+
+/// The `Content-Type` of the Prometheus text exposition format.
+pub static METRICS_CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// A Prometheus histogram: a count per bucket plus the sum and count of every observation.
+#[derive(Debug, Clone)]
+struct Histogram {
+    bounds: &'static [f64],
+    /// Observations per bucket, not cumulative. The last entry is the `+Inf` bucket.
+    counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn new(bounds: &'static [f64]) -> Self {
+        Self {
+            bounds,
+            counts: vec![0; bounds.len() + 1],
+            sum: 0.0,
+            count: 0,
+        }
+    }
+
+    fn observe(&mut self, value: f64) {
+        let bucket = self
+            .bounds
+            .iter()
+            .position(|bound| value <= *bound)
+            .unwrap_or(self.bounds.len());
+        self.counts[bucket] += 1;
+        self.sum += value;
+        self.count += 1;
+    }
+
+    /// Appends the `_bucket`, `_sum` and `_count` series of the histogram called `name`.
+    fn render(&self, name: &str, help: &str, out: &mut String) {
+        let _ = writeln!(out, "# HELP {name} {help}");
+        let _ = writeln!(out, "# TYPE {name} histogram");
+        let mut cumulative = 0;
+        for (bound, count) in self.bounds.iter().zip(&self.counts) {
+            cumulative += count;
+            let _ = writeln!(out, "{name}_bucket{{le=\"{bound}\"}} {cumulative}");
+        }
+        let _ = writeln!(out, "{name}_bucket{{le=\"+Inf\"}} {}", self.count);
+        let _ = writeln!(out, "{name}_sum {}", self.sum);
+        let _ = writeln!(out, "{name}_count {}", self.count);
+    }
+}
+
+/// The counters and histograms, updated together under one lock.
+#[derive(Debug)]
+struct Recorded {
+    /// Requests by `(method, status)`.
+    requests: BTreeMap<(String, u16), u64>,
+    latency: Histogram,
+    size: Histogram,
+}
+
+/// The server's metrics registry, exposed in the Prometheus text format by [`Metrics::render`].
+///
+/// | Metric                          | Type      | Labels             |
+/// |---------------------------------|-----------|--------------------|
+/// | `http_requests_total`           | counter   | `method`, `status` |
+/// | `http_request_duration_seconds` | histogram |                    |
+/// | `http_response_size_bytes`      | histogram |                    |
+/// | `http_active_connections`       | gauge     |                    |
+/// | `http_queue_depth`              | gauge     |                    |
+///
+/// [`crate::handle_connection`] records every answered request and the open connections, and the
+/// accept loop of [`crate::Server::run`] tracks the connections waiting for a worker.
+#[derive(Debug)]
+pub struct Metrics {
+    recorded: Mutex<Recorded>,
+    active_connections: AtomicI64,
+    queue_depth: AtomicI64,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Metrics {
+    /// Creates an empty registry.
+    pub fn new() -> Self {
+        Self {
+            recorded: Mutex::new(Recorded {
+                requests: BTreeMap::new(),
+                latency: Histogram::new(LATENCY_BUCKETS),
+                size: Histogram::new(SIZE_BUCKETS),
+            }),
+            active_connections: AtomicI64::new(0),
+            queue_depth: AtomicI64::new(0),
+        }
+    }
+
+    /// Records one answered request.
+    ///
+    /// # Arguments
+    /// - `method`: The request method, or `None` if the request couldn't be parsed.
+    /// - `status`: The status code sent.
+    /// - `duration`: From the start of the request until the response was written.
+    /// - `bytes_sent`: The body bytes sent.
+    pub fn record_request(
+        &self,
+        method: Option<Method>,
+        status: u16,
+        duration: Duration,
+        bytes_sent: u64,
+    ) {
+        let method = method.map_or("unknown", |method| method.as_str());
+        let mut recorded = self
+            .recorded
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        *recorded
+            .requests
+            .entry((method.to_string(), status))
+            .or_insert(0) += 1;
+        recorded.latency.observe(duration.as_secs_f64());
+        recorded.size.observe(bytes_sent as f64);
+    }
+
+    /// Counts a connection as active until the returned guard is dropped.
+    pub fn track_connection(&self) -> ActiveConnection<'_> {
+        self.active_connections.fetch_add(1, Ordering::Relaxed);
+        ActiveConnection { metrics: self }
+    }
+
+    /// Counts a connection handed to the worker queue.
+    pub fn connection_queued(&self) {
+        self.queue_depth.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts a connection taken off the worker queue, by a worker or because it was rejected.
+    pub fn connection_dequeued(&self) {
+        self.queue_depth.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    /// Number of connections currently being served.
+    pub fn active_connections(&self) -> i64 {
+        self.active_connections.load(Ordering::Relaxed)
+    }
+
+    /// Number of connections waiting for a free worker.
+    pub fn queue_depth(&self) -> i64 {
+        self.queue_depth.load(Ordering::Relaxed)
+    }
+
+    /// Renders every metric in the Prometheus text exposition format (version 0.0.4).
+    pub fn render(&self) -> String {
+        let recorded = self
+            .recorded
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let mut out = String::new();
+
+        out.push_str("# HELP http_requests_total Requests answered, by method and status.\n");
+        out.push_str("# TYPE http_requests_total counter\n");
+        for ((method, status), count) in &recorded.requests {
+            let _ = writeln!(
+                out,
+                "http_requests_total{{method=\"{method}\",status=\"{status}\"}} {count}"
+            );
+        }
+        recorded.latency.render(
+            "http_request_duration_seconds",
+            "Time from the start of a request until its response was written.",
+            &mut out,
+        );
+        recorded.size.render(
+            "http_response_size_bytes",
+            "Response body bytes sent.",
+            &mut out,
+        );
+
+        let gauges = [
+            (
+                "http_active_connections",
+                "Connections currently being served.",
+                self.active_connections(),
+            ),
+            (
+                "http_queue_depth",
+                "Connections waiting for a free worker.",
+                self.queue_depth(),
+            ),
+        ];
+        for (name, help, value) in gauges {
+            let _ = writeln!(out, "# HELP {name} {help}");
+            let _ = writeln!(out, "# TYPE {name} gauge");
+            let _ = writeln!(out, "{name} {value}");
+        }
+        out
+    }
+}
+
+/// Keeps a connection counted in `http_active_connections`, see [`Metrics::track_connection`].
+pub struct ActiveConnection<'a> {
+    metrics: &'a Metrics,
+}
+
+impl Drop for ActiveConnection<'_> {
+    fn drop(&mut self) {
+        self.metrics
+            .active_connections
+            .fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Serves [`Metrics::render`] on [`crate::MetricsConfig::path`].
+///
+/// As a [`Handler`] it answers every request it gets; [`crate::Server`] routes the metrics path
+/// of the admin listener to it. As a [`Middleware`] it answers `GET` and `HEAD` on the metrics
+/// path and passes everything else on, which is how the metrics share the main listener.
+#[derive(Debug, Clone)]
+pub struct MetricsEndpoint {
+    metrics: Arc<Metrics>,
+    path: String,
+}
+
+impl MetricsEndpoint {
+    /// Serves `metrics` on `path`.
+    pub fn new(metrics: Arc<Metrics>, path: impl Into<String>) -> Self {
+        Self {
+            metrics,
+            path: path.into(),
+        }
+    }
+}
+
+impl Handler for MetricsEndpoint {
+    fn handle(&self, _: &Request, _: &ServerConfig) -> Result<Response, RequestError> {
+        Ok(Response::new(StatusCode::Ok)
+            .with_header("Content-Type", METRICS_CONTENT_TYPE)
+            .with_header("Cache-Control", "no-store")
+            .with_bytes(self.metrics.render()))
+    }
+}
+
+impl Middleware for MetricsEndpoint {
+    fn handle(
+        &self,
+        request: &mut Request,
+        config: &ServerConfig,
+        next: Next<'_>,
+    ) -> Result<Response, RequestError> {
+        let is_read = matches!(request.method(), Method::Get | Method::Head);
+        if is_read && request.path() == self.path {
+            return Handler::handle(self, request, config);
+        }
+        next.run(request, config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Body;
+
+    use std::io::BufReader;
+
+    /// Runs `method path` through `endpoint` as middleware, in front of an endpoint answering `404`.
+    fn run(endpoint: MetricsEndpoint, method: &str, path: &str) -> Response {
+        let raw = format!("{method} {path} HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        let mut request = Request::new(&mut BufReader::new(raw.as_bytes())).unwrap();
+        let chain: [Box<dyn Middleware>; 1] = [Box::new(endpoint)];
+        let fallback = |_: &mut Request, _: &ServerConfig| Ok(Response::new(StatusCode::NotFound));
+        Next::new(&chain, &fallback)
+            .run(&mut request, &ServerConfig::default())
+            .unwrap()
+    }
+
+    fn lines(metrics: &Metrics) -> Vec<String> {
+        metrics.render().lines().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn requests_are_counted_by_method_and_status() {
+        let metrics = Metrics::new();
+        metrics.record_request(Some(Method::Get), 200, Duration::ZERO, 0);
+        metrics.record_request(Some(Method::Get), 200, Duration::ZERO, 0);
+        metrics.record_request(Some(Method::Post), 404, Duration::ZERO, 0);
+        metrics.record_request(None, 400, Duration::ZERO, 0);
+
+        let lines = lines(&metrics);
+        for expected in [
+            "# HELP http_requests_total Requests answered, by method and status.",
+            "# TYPE http_requests_total counter",
+            r#"http_requests_total{method="GET",status="200"} 2"#,
+            r#"http_requests_total{method="POST",status="404"} 1"#,
+            r#"http_requests_total{method="unknown",status="400"} 1"#,
+        ] {
+            assert!(
+                lines.contains(&expected.to_string()),
+                "missing `{expected}`"
+            );
+        }
+    }
+
+    #[test]
+    fn histograms_have_cumulative_buckets_a_sum_and_a_count() {
+        let metrics = Metrics::new();
+        metrics.record_request(Some(Method::Get), 200, Duration::from_millis(3), 150);
+        metrics.record_request(Some(Method::Get), 200, Duration::from_secs(20), 50);
+
+        let lines = lines(&metrics);
+        for expected in [
+            "# TYPE http_request_duration_seconds histogram",
+            r#"http_request_duration_seconds_bucket{le="0.0025"} 0"#,
+            r#"http_request_duration_seconds_bucket{le="0.005"} 1"#,
+            r#"http_request_duration_seconds_bucket{le="10"} 1"#,
+            r#"http_request_duration_seconds_bucket{le="+Inf"} 2"#,
+            "http_request_duration_seconds_sum 20.003",
+            "http_request_duration_seconds_count 2",
+            "# TYPE http_response_size_bytes histogram",
+            r#"http_response_size_bytes_bucket{le="100"} 1"#,
+            r#"http_response_size_bytes_bucket{le="1000"} 2"#,
+            r#"http_response_size_bytes_bucket{le="+Inf"} 2"#,
+            "http_response_size_bytes_sum 200",
+            "http_response_size_bytes_count 2",
+        ] {
+            assert!(
+                lines.contains(&expected.to_string()),
+                "missing `{expected}`"
+            );
+        }
+    }
+
+    #[test]
+    fn gauges_follow_connections_and_the_queue() {
+        let metrics = Metrics::new();
+        let connection = metrics.track_connection();
+        metrics.connection_queued();
+        metrics.connection_queued();
+        metrics.connection_dequeued();
+
+        let rendered = lines(&metrics);
+        assert!(rendered.contains(&"# TYPE http_active_connections gauge".to_string()));
+        assert!(rendered.contains(&"http_active_connections 1".to_string()));
+        assert!(rendered.contains(&"http_queue_depth 1".to_string()));
+
+        drop(connection);
+        assert_eq!(metrics.active_connections(), 0);
+    }
+
+    #[test]
+    fn the_endpoint_answers_reads_of_its_path_and_passes_the_rest_on() {
+        let metrics = Arc::new(Metrics::new());
+        metrics.record_request(Some(Method::Get), 200, Duration::ZERO, 0);
+        let endpoint = MetricsEndpoint::new(Arc::clone(&metrics), "/metrics");
+
+        let response = run(endpoint.clone(), "GET", "/metrics");
+        assert_eq!(response.status(), StatusCode::Ok);
+        assert_eq!(
+            response.headers().get("Content-Type"),
+            Some(METRICS_CONTENT_TYPE)
+        );
+        assert_eq!(response.headers().get("Cache-Control"), Some("no-store"));
+        let Body::Bytes(body) = response.body() else {
+            panic!("the metrics are not a byte body");
+        };
+        assert_eq!(String::from_utf8_lossy(body), metrics.render());
+
+        assert_eq!(
+            run(endpoint.clone(), "HEAD", "/metrics").status(),
+            StatusCode::Ok
+        );
+        assert_eq!(
+            run(endpoint.clone(), "POST", "/metrics").status(),
+            StatusCode::NotFound
+        );
+        assert_eq!(
+            run(endpoint, "GET", "/metrics/other").status(),
+            StatusCode::NotFound
+        );
+    }
+}
@@ -0,0 +1,197 @@
+use crate::{Request, RequestError, Response, ServerConfig};
+
+/// Code that runs around request handling: logging, extra headers, authentication, timing, ...
+///
+/// A middleware gets the request before the handler does and decides what happens next:
+/// - inspect or modify the [`Request`] (`&mut`), then call [`Next::run`] to continue,
+/// - short-circuit by returning a [`Response`] of its own without calling [`Next::run`],
+/// - or post-process the [`Response`] that [`Next::run`] returns.
+///
+/// Middleware is added to a [`crate::Server`] (runs for every request) or to a [`crate::Router`]
+/// (every request the router sees, or a single route, see [`crate::Router::layer`]) and runs in
+/// the order it was added: the first one added is the outermost.
+///
+/// Implemented by every closure `Fn(&mut Request, &ServerConfig, Next) -> Result<Response, RequestError>`:
+///
+/// ```no_run
+/// use rust_server::{Next, Request, Response, Router, ServerConfig, StatusCode};
+///
+/// let router = Router::default().with_middleware(
+///     |request: &mut Request, config: &ServerConfig, next: Next| {
+///         if request.header("Authorization").is_none() {
+///             return Ok(Response::new(StatusCode::Unauthorized));
+///         }
+///         Ok(next.run(request, config)?.with_header("Cache-Control", "no-store"))
+///     },
+/// );
+/// ```
+///
+/// Middleware is shared by every worker thread, hence `Send + Sync`.
+pub trait Middleware: Send + Sync {
+    /// Handles `request`, usually by passing it on with `next.run(request, config)`.
+    ///
+    /// # Returns
+    /// - `Ok(Response)`: The response to send.
+    /// - `Err(RequestError)`: If the request can't be answered. The connection is closed.
+    fn handle(
+        &self,
+        request: &mut Request,
+        config: &ServerConfig,
+        next: Next<'_>,
+    ) -> Result<Response, RequestError>;
+}
+
+impl<F> Middleware for F
+where
+    F: Fn(&mut Request, &ServerConfig, Next<'_>) -> Result<Response, RequestError> + Send + Sync,
+{
+    fn handle(
+        &self,
+        request: &mut Request,
+        config: &ServerConfig,
+        next: Next<'_>,
+    ) -> Result<Response, RequestError> {
+        self(request, config, next)
+    }
+}
+
+/// The rest of a middleware chain: the middleware after the current one, then the handler.
+pub struct Next<'a> {
+    middleware: &'a [Box<dyn Middleware>],
+    endpoint: &'a dyn Fn(&mut Request, &ServerConfig) -> Result<Response, RequestError>,
+}
+
+impl<'a> Next<'a> {
+    /// Runs `endpoint` behind `middleware`, outermost first.
+    pub(crate) fn new(
+        middleware: &'a [Box<dyn Middleware>],
+        endpoint: &'a dyn Fn(&mut Request, &ServerConfig) -> Result<Response, RequestError>,
+    ) -> Self {
+        Self {
+            middleware,
+            endpoint,
+        }
+    }
+
+    /// Passes the request to the next middleware, or to the handler if there is none left.
+    ///
+    /// # Returns
+    /// The response (or error) produced further down the chain.
+    pub fn run(
+        self,
+        request: &mut Request,
+        config: &ServerConfig,
+    ) -> Result<Response, RequestError> {
+        match self.middleware.split_first() {
+            Some((first, rest)) => first.handle(request, config, Next::new(rest, self.endpoint)),
+            None => (self.endpoint)(request, config),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::StatusCode;
+
+    use std::io::BufReader;
+    use std::sync::{Arc, Mutex};
+
+    type Log = Arc<Mutex<Vec<String>>>;
+
+    /// Middleware that logs `name` before and after the rest of the chain and tags the response.
+    fn logging(name: &'static str, log: &Log) -> Box<dyn Middleware> {
+        let log = Arc::clone(log);
+        Box::new(
+            move |request: &mut Request, config: &ServerConfig, next: Next<'_>| {
+                log.lock().unwrap().push(format!("{name} before"));
+                let response = next.run(request, config)?;
+                log.lock().unwrap().push(format!("{name} after"));
+                Ok(response.with_header("X-Seen-By", name))
+            },
+        )
+    }
+
+    /// Runs a request for `path` through `middleware` in front of an endpoint that logs it.
+    fn run(middleware: &[Box<dyn Middleware>], log: &Log, path: &str) -> Response {
+        let raw = format!("GET {path} HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        let mut request = Request::new(&mut BufReader::new(raw.as_bytes())).unwrap();
+        let endpoint = |request: &mut Request, _: &ServerConfig| {
+            log.lock()
+                .unwrap()
+                .push(format!("endpoint {}", request.path()));
+            Ok(Response::new(StatusCode::Ok))
+        };
+        Next::new(middleware, &endpoint)
+            .run(&mut request, &ServerConfig::default())
+            .unwrap()
+    }
+
+    #[test]
+    fn middleware_runs_outermost_first_around_the_endpoint() {
+        let log = Log::default();
+        let chain = [logging("outer", &log), logging("inner", &log)];
+
+        let response = run(&chain, &log, "/page");
+        assert_eq!(
+            *log.lock().unwrap(),
+            [
+                "outer before",
+                "inner before",
+                "endpoint /page",
+                "inner after",
+                "outer after"
+            ]
+        );
+        let seen_by = response.headers().get_all("X-Seen-By").collect::<Vec<_>>();
+        assert_eq!(seen_by, ["inner", "outer"]);
+    }
+
+    #[test]
+    fn middleware_can_short_circuit_the_rest_of_the_chain() {
+        let log = Log::default();
+        let deny = |request: &mut Request, config: &ServerConfig, next: Next<'_>| {
+            if request.path() == "/private" {
+                return Ok(Response::new(StatusCode::Forbidden));
+            }
+            next.run(request, config)
+        };
+        let chain = [
+            logging("outer", &log),
+            Box::new(deny),
+            logging("inner", &log),
+        ];
+
+        let response = run(&chain, &log, "/private");
+        assert_eq!(response.status(), StatusCode::Forbidden);
+        assert_eq!(*log.lock().unwrap(), ["outer before", "outer after"]);
+        assert_eq!(response.headers().get("X-Seen-By"), Some("outer"));
+
+        log.lock().unwrap().clear();
+        let response = run(&chain, &log, "/public");
+        assert_eq!(response.status(), StatusCode::Ok);
+        assert_eq!(log.lock().unwrap().len(), 5);
+    }
+
+    #[test]
+    fn errors_skip_the_post_processing_of_outer_middleware() {
+        let log = Log::default();
+        let fail = |_: &mut Request, _: &ServerConfig, _: Next<'_>| Err(RequestError::InvalidURL);
+        let chain = [logging("outer", &log), Box::new(fail)];
+
+        let raw = "GET / HTTP/1.1\r\nHost: localhost\r\n\r\n";
+        let mut request = Request::new(&mut BufReader::new(raw.as_bytes())).unwrap();
+        let endpoint = |_: &mut Request, _: &ServerConfig| Ok(Response::new(StatusCode::Ok));
+        let result = Next::new(&chain, &endpoint).run(&mut request, &ServerConfig::default());
+        assert!(matches!(result, Err(RequestError::InvalidURL)));
+        assert_eq!(*log.lock().unwrap(), ["outer before"]);
+    }
+
+    #[test]
+    fn an_empty_chain_runs_the_endpoint() {
+        let log = Log::default();
+        let response = run(&[], &log, "/");
+        assert_eq!(response.status(), StatusCode::Ok);
+        assert_eq!(*log.lock().unwrap(), ["endpoint /"]);
+    }
+}
@@ -0,0 +1,183 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+/// The MIME type sent for files whose extension is unknown.
+pub static DEFAULT_MIME_TYPE: &str = "application/octet-stream";
+
+/// The built-in extension to MIME type table used by [`MimeTypes::new`].
+static BUILT_IN_MIME_TYPES: &[(&str, &str)] = &[
+    /* Documents */
+    ("html", "text/html"),
+    ("htm", "text/html"),
+    ("css", "text/css"),
+    ("js", "text/javascript"),
+    ("mjs", "text/javascript"),
+    ("json", "application/json"),
+    ("map", "application/json"),
+    ("webmanifest", "application/manifest+json"),
+    ("xml", "application/xml"),
+    ("txt", "text/plain"),
+    ("csv", "text/csv"),
+    ("md", "text/markdown"),
+    ("pdf", "application/pdf"),
+    ("wasm", "application/wasm"),
+    /* Images */
+    ("png", "image/png"),
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("gif", "image/gif"),
+    ("webp", "image/webp"),
+    ("avif", "image/avif"),
+    ("bmp", "image/bmp"),
+    ("ico", "image/x-icon"),
+    ("svg", "image/svg+xml"),
+    /* Fonts */
+    ("woff", "font/woff"),
+    ("woff2", "font/woff2"),
+    ("ttf", "font/ttf"),
+    ("otf", "font/otf"),
+    /* Audio and video */
+    ("mp3", "audio/mpeg"),
+    ("ogg", "audio/ogg"),
+    ("wav", "audio/wav"),
+    ("mp4", "video/mp4"),
+    ("webm", "video/webm"),
+    /* Archives */
+    ("zip", "application/zip"),
+    ("gz", "application/gzip"),
+    ("tar", "application/x-tar"),
+];
+
+/// A table from file extension to MIME type, used to pick the `Content-Type` of static files.
+///
+/// [`MimeTypes::new`] starts from a built-in table of common web types; [`MimeTypes::insert`]
+/// adds new extensions or overrides built-in ones. Extensions are matched case-insensitively.
+#[derive(Debug, Clone)]
+pub struct MimeTypes {
+    types: HashMap<String, String>,
+}
+
+impl MimeTypes {
+    /// Creates a table with the built-in extensions.
+    pub fn new() -> Self {
+        let types = BUILT_IN_MIME_TYPES
+            .iter()
+            .map(|(extension, mime)| (extension.to_string(), mime.to_string()))
+            .collect();
+        Self { types }
+    }
+
+    /// Maps `extension` (without the leading `.`) to `mime`, replacing any existing mapping.
+    pub fn insert(&mut self, extension: &str, mime: impl Into<String>) {
+        let extension = extension.trim_start_matches('.').to_ascii_lowercase();
+        self.types.insert(extension, mime.into());
+    }
+
+    /// Returns the MIME type for `extension` (without the leading `.`), if it is known.
+    pub fn get(&self, extension: &str) -> Option<&str> {
+        self.types
+            .get(&extension.to_ascii_lowercase())
+            .map(String::as_str)
+    }
+
+    /// Returns the `Content-Type` value for the file at `path`.
+    ///
+    /// - Unknown or missing extensions fall back to [`DEFAULT_MIME_TYPE`].
+    /// - Text types get `; charset=utf-8` appended (see [`is_text`]).
+    pub fn content_type(&self, path: &Path) -> String {
+        let mime = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .and_then(|extension| self.get(extension))
+            .unwrap_or(DEFAULT_MIME_TYPE);
+
+        if is_text(mime) && !mime.contains("charset=") {
+            format!("{mime}; charset=utf-8")
+        } else {
+            mime.to_string()
+        }
+    }
+}
+
+impl Default for MimeTypes {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Returns `true` for MIME types whose content is text and should carry a `charset` parameter:
+/// every `text/*` type plus JSON, XML, JavaScript and SVG.
+pub fn is_text(mime: &str) -> bool {
+    let essence = mime.split(';').next().unwrap_or_default().trim();
+    essence.starts_with("text/")
+        || essence.ends_with("+json")
+        || essence.ends_with("+xml")
+        || matches!(
+            essence,
+            "application/json" | "application/xml" | "application/javascript"
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn known_extensions_get_their_type_and_text_a_charset() {
+        let types = MimeTypes::new();
+        assert_eq!(
+            types.content_type(Path::new("index.html")),
+            "text/html; charset=utf-8"
+        );
+        assert_eq!(
+            types.content_type(Path::new("data.json")),
+            "application/json; charset=utf-8"
+        );
+        assert_eq!(
+            types.content_type(Path::new("icon.svg")),
+            "image/svg+xml; charset=utf-8"
+        );
+        assert_eq!(types.content_type(Path::new("photo.png")), "image/png");
+        assert_eq!(
+            types.content_type(Path::new("archive.tar.gz")),
+            "application/gzip"
+        );
+    }
+
+    #[test]
+    fn unknown_or_missing_extensions_fall_back_to_the_default() {
+        let types = MimeTypes::new();
+        for path in ["file.unknown", "Makefile", ".hidden", "dir.d/file"] {
+            assert_eq!(
+                types.content_type(Path::new(path)),
+                DEFAULT_MIME_TYPE,
+                "{path}"
+            );
+        }
+        assert_eq!(types.get("unknown"), None);
+    }
+
+    #[test]
+    fn extensions_are_matched_case_insensitively() {
+        let mut types = MimeTypes::new();
+        assert_eq!(types.content_type(Path::new("PHOTO.JPG")), "image/jpeg");
+        assert_eq!(types.get("Html"), Some("text/html"));
+
+        types.insert(".YAML", "application/yaml");
+        assert_eq!(types.get("yaml"), Some("application/yaml"));
+        assert_eq!(
+            types.content_type(Path::new("config.Yaml")),
+            "application/yaml"
+        );
+    }
+
+    #[test]
+    fn insert_overrides_built_in_types() {
+        let mut types = MimeTypes::new();
+        types.insert("txt", "text/plain; charset=iso-8859-1");
+        assert_eq!(
+            types.content_type(Path::new("notes.txt")),
+            "text/plain; charset=iso-8859-1"
+        );
+    }
+}
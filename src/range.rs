@@ -0,0 +1,374 @@
+use crate::{Response, StatusCode};
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, Cursor, ErrorKind, Read, Seek, SeekFrom};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The most ranges a single request may ask for (after merging overlapping ones).
+/// Requests asking for more are answered with the whole file instead.
+pub static MAX_RANGES: usize = 16;
+
+/// Makes every `multipart/byteranges` boundary in this process unique.
+static BOUNDARY_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// An inclusive range of byte positions, `start..=end`, as it appears in `Content-Range`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl ByteRange {
+    /// The number of bytes in the range.
+    pub fn byte_count(&self) -> u64 {
+        self.end - self.start + 1
+    }
+
+    /// The `Content-Range` value for this range of a representation `length` bytes long.
+    pub fn content_range(&self, length: u64) -> String {
+        format!("bytes {}-{}/{}", self.start, self.end, length)
+    }
+}
+
+/// What a `Range` header field asks for, after checking it against the file length.
+///
+/// # Variants
+/// - `Ignored`: The field is malformed, uses a unit other than `bytes` or asks for more than
+///   [`MAX_RANGES`] ranges. The whole file is sent with `200 OK`, as RFC 9110 section 14.2 allows.
+/// - `Unsatisfiable`: Every range starts past the end of the file. Answered with `416`.
+/// - `Ranges`: The satisfiable ranges, sorted and with overlapping or adjacent ones merged.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RangeRequest {
+    Ignored,
+    Unsatisfiable,
+    Ranges(Vec<ByteRange>),
+}
+
+/// Parses a `Range` header field value for a representation `length` bytes long.
+///
+/// Accepts the forms of RFC 9110 section 14.1.2, separated by commas:
+/// - `bytes=0-499`: the first 500 bytes (an end past the file is clamped to the last byte),
+/// - `bytes=500-`: everything from byte 500,
+/// - `bytes=-500`: the last 500 bytes.
+pub fn parse_range(value: &str, length: u64) -> RangeRequest {
+    let Some((unit, specs)) = value.trim().split_once('=') else {
+        return RangeRequest::Ignored;
+    };
+    if !unit.trim().eq_ignore_ascii_case("bytes") {
+        return RangeRequest::Ignored;
+    }
+
+    let mut ranges = Vec::new();
+    let mut any = false;
+    for spec in specs
+        .split(',')
+        .map(str::trim)
+        .filter(|spec| !spec.is_empty())
+    {
+        any = true;
+        let Some((first, last)) = spec.split_once('-') else {
+            return RangeRequest::Ignored;
+        };
+        let (Some(first), Some(last)) = (parse_position(first), parse_position(last)) else {
+            return RangeRequest::Ignored;
+        };
+
+        let range = match (first, last) {
+            /* "-N": the last N bytes. */
+            (None, Some(suffix)) if suffix > 0 && length > 0 => Some(ByteRange {
+                start: length.saturating_sub(suffix),
+                end: length - 1,
+            }),
+            (None, Some(_)) => None,
+            (Some(first), last) => {
+                if last.is_some_and(|last| last < first) {
+                    return RangeRequest::Ignored;
+                }
+                (first < length).then(|| ByteRange {
+                    start: first,
+                    end: last.map_or(length - 1, |last| last.min(length - 1)),
+                })
+            }
+            (None, None) => return RangeRequest::Ignored,
+        };
+        ranges.extend(range);
+    }
+
+    if !any {
+        return RangeRequest::Ignored;
+    }
+    if ranges.is_empty() {
+        return RangeRequest::Unsatisfiable;
+    }
+
+    let ranges = merge_ranges(ranges);
+    if ranges.len() > MAX_RANGES {
+        return RangeRequest::Ignored;
+    }
+    RangeRequest::Ranges(ranges)
+}
+
+/// Builds the `206 Partial Content` response for `ranges` of `file`.
+///
+/// - A single range is sent as is, with a `Content-Range` header.
+/// - Several ranges are sent as `multipart/byteranges`, each part carrying its own
+///   `Content-Type` and `Content-Range`.
+///
+/// # Arguments
+/// - `file`: The open file.
+/// - `length`: The full file length in bytes.
+/// - `ranges`: The ranges to send, as returned in [`RangeRequest::Ranges`].
+/// - `content_type`: The `Content-Type` of the file.
+///
+/// # Returns
+/// - `Ok(Response)`: The partial response.
+/// - `Err(io::Error)`: If seeking in the file fails.
+pub fn partial_response(
+    mut file: File,
+    length: u64,
+    ranges: &[ByteRange],
+    content_type: &str,
+) -> io::Result<Response> {
+    if let [range] = ranges {
+        file.seek(SeekFrom::Start(range.start))?;
+        return Ok(Response::new(StatusCode::PartialContent)
+            .with_header("Content-Type", content_type)
+            .with_header("Content-Range", range.content_range(length))
+            .with_reader(file.take(range.byte_count()), Some(range.byte_count())));
+    }
+
+    let boundary = new_boundary();
+    let mut segments = VecDeque::new();
+    for (index, range) in ranges.iter().enumerate() {
+        /* The first delimiter needs no leading CRLF, see RFC 2046 section 5.1.1. */
+        let separator = if index == 0 { "" } else { "\r\n" };
+        let part_headers = format!(
+            "{separator}--{boundary}\r\nContent-Type: {content_type}\r\nContent-Range: {}\r\n\r\n",
+            range.content_range(length)
+        );
+        segments.push_back(Segment::Bytes(Cursor::new(part_headers.into_bytes())));
+        segments.push_back(Segment::File(*range));
+    }
+    segments.push_back(Segment::Bytes(Cursor::new(
+        format!("\r\n--{boundary}--\r\n").into_bytes(),
+    )));
+
+    let body = MultipartBody { file, segments };
+    let body_length = body.len();
+    Ok(Response::new(StatusCode::PartialContent)
+        .with_header(
+            "Content-Type",
+            format!("multipart/byteranges; boundary={boundary}"),
+        )
+        .with_reader(body, Some(body_length)))
+}
+
+/// Parses one side of a range spec. An empty side is `Some(None)`; anything but digits is `None`.
+fn parse_position(value: &str) -> Option<Option<u64>> {
+    if value.is_empty() {
+        return Some(None);
+    }
+    if !value.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+    /* A position too large for u64 is past the end of any file. */
+    Some(Some(value.parse().unwrap_or(u64::MAX)))
+}
+
+/// Sorts `ranges` and merges the ones that overlap or touch.
+fn merge_ranges(mut ranges: Vec<ByteRange>) -> Vec<ByteRange> {
+    ranges.sort_by_key(|range| range.start);
+    let mut merged: Vec<ByteRange> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end.saturating_add(1) => {
+                last.end = last.end.max(range.end);
+            }
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
+/// Returns a boundary string that does not occur in any other response of this process.
+fn new_boundary() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since_epoch| since_epoch.subsec_nanos())
+        .unwrap_or_default();
+    let count = BOUNDARY_COUNTER.fetch_add(1, Ordering::Relaxed);
+    format!("rust_server_{nanos:08x}{count:016x}")
+}
+
+/// One piece of a `multipart/byteranges` body: part headers or a range of the file.
+enum Segment {
+    Bytes(Cursor<Vec<u8>>),
+    File(ByteRange),
+}
+
+/// Streams a `multipart/byteranges` body, reading each range from the file only when it is sent.
+struct MultipartBody {
+    file: File,
+    segments: VecDeque<Segment>,
+}
+
+impl MultipartBody {
+    /// The total body length in bytes.
+    fn len(&self) -> u64 {
+        self.segments
+            .iter()
+            .map(|segment| match segment {
+                Segment::Bytes(bytes) => bytes.get_ref().len() as u64,
+                Segment::File(range) => range.byte_count(),
+            })
+            .sum()
+    }
+}
+
+impl Read for MultipartBody {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while let Some(segment) = self.segments.front_mut() {
+            let read = match segment {
+                Segment::Bytes(bytes) => bytes.read(buf)?,
+                Segment::File(range) => {
+                    let wanted = (buf.len() as u64).min(range.end + 1 - range.start) as usize;
+                    self.file.seek(SeekFrom::Start(range.start))?;
+                    let read = self.file.read(&mut buf[..wanted])?;
+                    /* The file shrank: stop rather than send a body shorter than Content-Length. */
+                    if read == 0 && wanted > 0 {
+                        return Err(io::Error::new(
+                            ErrorKind::UnexpectedEof,
+                            "file shrank while sending ranges",
+                        ));
+                    }
+                    range.start += read as u64;
+                    if range.start > range.end {
+                        self.segments.pop_front();
+                    }
+                    return Ok(read);
+                }
+            };
+            if read > 0 {
+                return Ok(read);
+            }
+            self.segments.pop_front();
+        }
+
+        Ok(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ranges(value: &str, length: u64) -> Vec<(u64, u64)> {
+        match parse_range(value, length) {
+            RangeRequest::Ranges(ranges) => ranges
+                .into_iter()
+                .map(|range| (range.start, range.end))
+                .collect(),
+            other => panic!("{value}: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn closed_ranges_are_clamped_to_the_last_byte() {
+        assert_eq!(ranges("bytes=0-499", 1000), [(0, 499)]);
+        assert_eq!(ranges("bytes=500-999", 1000), [(500, 999)]);
+        assert_eq!(ranges("bytes=900-5000", 1000), [(900, 999)]);
+        assert_eq!(ranges("bytes=7-7", 1000), [(7, 7)]);
+        assert_eq!(ranges(" BYTES = 1-2 ", 1000), [(1, 2)]);
+    }
+
+    #[test]
+    fn open_ended_ranges_run_to_the_end() {
+        assert_eq!(ranges("bytes=500-", 1000), [(500, 999)]);
+        assert_eq!(ranges("bytes=0-", 1), [(0, 0)]);
+    }
+
+    #[test]
+    fn suffix_ranges_take_the_last_bytes() {
+        assert_eq!(ranges("bytes=-500", 1000), [(500, 999)]);
+        assert_eq!(ranges("bytes=-5000", 1000), [(0, 999)]);
+        assert_eq!(ranges("bytes=-1", 1000), [(999, 999)]);
+    }
+
+    #[test]
+    fn overlapping_and_adjacent_ranges_are_sorted_and_merged() {
+        assert_eq!(ranges("bytes=500-599,0-99", 1000), [(0, 99), (500, 599)]);
+        assert_eq!(ranges("bytes=0-99,50-149", 1000), [(0, 149)]);
+        assert_eq!(ranges("bytes=0-99,100-199", 1000), [(0, 199)]);
+        assert_eq!(ranges("bytes=0-99,-100,950-", 1000), [(0, 99), (900, 999)]);
+        assert_eq!(ranges("bytes=0-0, ,2-2,", 1000), [(0, 0), (2, 2)]);
+    }
+
+    #[test]
+    fn unsatisfiable_ranges_are_dropped_and_none_left_is_unsatisfiable() {
+        assert_eq!(ranges("bytes=0-9,2000-3000", 1000), [(0, 9)]);
+        for value in [
+            "bytes=1000-",
+            "bytes=2000-3000",
+            "bytes=-0",
+            "bytes=1000-,-0",
+        ] {
+            assert_eq!(
+                parse_range(value, 1000),
+                RangeRequest::Unsatisfiable,
+                "{value}"
+            );
+        }
+        assert_eq!(parse_range("bytes=-10", 0), RangeRequest::Unsatisfiable);
+        assert_eq!(
+            parse_range("bytes=99999999999999999999999-", 1000),
+            RangeRequest::Unsatisfiable
+        );
+    }
+
+    #[test]
+    fn malformed_specs_are_ignored() {
+        for value in [
+            "",
+            "bytes",
+            "bytes=",
+            "bytes=,",
+            "items=0-9",
+            "bytes=5",
+            "bytes=-",
+            "bytes=a-9",
+            "bytes=0-9x",
+            "bytes=+1-2",
+            "bytes=9-0",
+            "bytes=0-9,oops",
+        ] {
+            assert_eq!(parse_range(value, 1000), RangeRequest::Ignored, "{value:?}");
+        }
+    }
+
+    #[test]
+    fn more_than_max_ranges_after_merging_are_ignored() {
+        let spec = |count: u64| {
+            (0..count)
+                .map(|index| format!("{}-{}", index * 10, index * 10))
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+        let allowed = parse_range(&format!("bytes={}", spec(MAX_RANGES as u64)), 1000);
+        assert!(matches!(allowed, RangeRequest::Ranges(ranges) if ranges.len() == MAX_RANGES));
+        let too_many = parse_range(&format!("bytes={}", spec(MAX_RANGES as u64 + 1)), 1000);
+        assert_eq!(too_many, RangeRequest::Ignored);
+
+        /* Ranges that merge into one count once. */
+        let merging = (0..100).map(|_| "0-9").collect::<Vec<_>>().join(",");
+        assert_eq!(ranges(&format!("bytes={merging}"), 1000), [(0, 9)]);
+    }
+
+    #[test]
+    fn byte_range_reports_its_size_and_content_range() {
+        let range = ByteRange { start: 10, end: 19 };
+        assert_eq!(range.byte_count(), 10);
+        assert_eq!(range.content_range(100), "bytes 10-19/100");
+    }
+}
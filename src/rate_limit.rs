@@ -0,0 +1,452 @@
+use crate::{
+    Middleware, Next, RATE_LIMIT_SWEEP_INTERVAL, RateLimitConfig, Request, RequestError, Response,
+    ServerConfig, StatusCode, error_response,
+};
+
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+/// What the limiter knows about one client IP.
+#[derive(Debug)]
+struct Client {
+    /// Requests the client may make right now, refilled at `requests_per_second` up to `burst`.
+    tokens: f64,
+    /// When `tokens` was last brought up to date, which is also when the client was last seen.
+    updated: Instant,
+    /// Connections currently open, see [`ConnectionPermit`].
+    connections: usize,
+}
+
+impl Client {
+    /// Adds the tokens earned since the last update.
+    fn refill(&mut self, now: Instant, config: &RateLimitConfig) {
+        let earned = now.duration_since(self.updated).as_secs_f64() * config.requests_per_second;
+        self.tokens = (self.tokens + earned).min(config.burst as f64);
+        self.updated = now;
+    }
+
+    /// Returns `true` if forgetting the client loses nothing: its bucket has refilled and it has
+    /// no connection open.
+    fn is_idle(&self, now: Instant, config: &RateLimitConfig) -> bool {
+        let earned = now.duration_since(self.updated).as_secs_f64() * config.requests_per_second;
+        self.connections == 0 && self.tokens + earned >= config.burst as f64
+    }
+}
+
+/// The clients a [`RateLimiter`] tracks.
+#[derive(Debug)]
+struct Clients {
+    table: HashMap<IpAddr, Client>,
+    /// When the table was last swept for idle clients, `None` before the first sweep.
+    swept: Option<Instant>,
+}
+
+/// A token-bucket rate limiter with a connection cap, keyed by client IP.
+///
+/// - Every client IP has a bucket of `burst` tokens, refilled at `requests_per_second`. Each
+///   request takes a token; a request finding the bucket empty gets `429 Too Many Requests` with
+///   a `Retry-After` of the seconds until the next token.
+/// - A client IP may hold at most `max_connections_per_ip` connections open, see
+///   [`RateLimiter::open_connection`].
+/// - The client IP is the address of the connection, unless that is one of the
+///   `trusted_proxies`: then it is taken from the `client_ip_header` the proxy set.
+///
+/// At most `max_clients` IPs are tracked. When a new one arrives at the limit, the table is swept
+/// for clients that would start over with a full bucket and have no connection open, at most once
+/// per [`RATE_LIMIT_SWEEP_INTERVAL`]. If that frees no room, the new IP is refused: clients with
+/// open connections or half-empty buckets are never forgotten, so nobody can reset their limits by
+/// crowding them out.
+///
+/// As a [`Middleware`], [`crate::Server`] runs it for every request after the probes and metrics.
+/// Clones share their state.
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    config: Arc<RateLimitConfig>,
+    clients: Arc<Mutex<Clients>>,
+}
+
+impl RateLimiter {
+    /// Creates a limiter with no clients tracked yet.
+    pub fn new(config: &RateLimitConfig) -> Self {
+        Self {
+            config: Arc::new(config.clone()),
+            clients: Arc::new(Mutex::new(Clients {
+                table: HashMap::new(),
+                swept: None,
+            })),
+        }
+    }
+
+    /// Returns the IP `request` is limited under, see [`RateLimiter`].
+    ///
+    /// Behind a trusted proxy the header is read right to left, skipping the trusted proxies, so
+    /// a client can't choose its own key by sending the header itself.
+    ///
+    /// # Returns
+    /// - `Some(IpAddr)`: The client IP.
+    /// - `None`: If the request has no connection address (see [`Request::remote_addr`]).
+    pub fn client_ip(&self, request: &Request) -> Option<IpAddr> {
+        let peer = request.remote_addr()?.ip();
+        if !self.is_trusted(peer) {
+            return Some(peer);
+        }
+
+        let forwarded = request
+            .headers()
+            .get_all(&self.config.client_ip_header)
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .collect::<Vec<_>>();
+        let client = forwarded
+            .iter()
+            .rev()
+            .map_while(|address| parse_ip(address))
+            .find(|ip| !self.is_trusted(*ip));
+        Some(client.unwrap_or(peer))
+    }
+
+    /// Takes a token from the bucket of `ip`.
+    ///
+    /// # Returns
+    /// - `Ok(())`: If the request may go ahead.
+    /// - `Err(Duration)`: If the bucket is empty, with the time until the next token, or if `ip`
+    ///   is new and no room could be made for it, with the time until the next sweep.
+    pub fn check(&self, ip: IpAddr) -> Result<(), Duration> {
+        let now = Instant::now();
+        let mut clients = self.lock();
+        let Some(client) = self.client(&mut clients, ip, now) else {
+            return Err(RATE_LIMIT_SWEEP_INTERVAL);
+        };
+        client.refill(now, &self.config);
+
+        if client.tokens >= 1.0 {
+            client.tokens -= 1.0;
+            return Ok(());
+        }
+        let missing = 1.0 - client.tokens;
+        Err(Duration::from_secs_f64(
+            missing / self.config.requests_per_second,
+        ))
+    }
+
+    /// Counts a new connection from `ip` until the returned permit is dropped.
+    ///
+    /// Connections from trusted proxies carry many clients and are never refused for their count.
+    ///
+    /// # Returns
+    /// - `Some(ConnectionPermit)`: If the connection may be served.
+    /// - `None`: If `ip` already holds `max_connections_per_ip` connections, or if it is new and
+    ///   no room could be made for it.
+    pub fn open_connection(&self, ip: IpAddr) -> Option<ConnectionPermit> {
+        let now = Instant::now();
+        let limit = self.config.max_connections_per_ip;
+        let capped = limit > 0 && !self.is_trusted(ip);
+
+        let mut clients = self.lock();
+        let client = self.client(&mut clients, ip, now)?;
+        if capped && client.connections >= limit {
+            return None;
+        }
+        client.connections += 1;
+        Some(ConnectionPermit {
+            limiter: self.clone(),
+            ip,
+        })
+    }
+
+    /// Number of client IPs currently tracked, at most `max_clients`.
+    pub fn tracked_clients(&self) -> usize {
+        self.lock().table.len()
+    }
+
+    /// This function is a private helper function for [`RateLimiter`].
+    /// - It returns `true` if `ip` is one of the trusted proxies.
+    fn is_trusted(&self, ip: IpAddr) -> bool {
+        self.config.trusted_proxies.contains(&ip)
+    }
+
+    /// This function is a private helper function for [`RateLimiter`].
+    /// - It locks the client table, recovering it if a thread panicked while holding it.
+    fn lock(&self) -> MutexGuard<'_, Clients> {
+        self.clients
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// This function is a private helper function for [`RateLimiter`].
+    /// - It returns the entry of `ip`, adding one with a full bucket if there is none.
+    /// - At `max_clients`, idle clients are swept out first if the last sweep is at least
+    ///   [`RATE_LIMIT_SWEEP_INTERVAL`] ago.
+    /// - It returns `None` if `ip` is new and the table is still full.
+    fn client<'a>(
+        &self,
+        clients: &'a mut Clients,
+        ip: IpAddr,
+        now: Instant,
+    ) -> Option<&'a mut Client> {
+        let full = |clients: &Clients| clients.table.len() >= self.config.max_clients;
+        if !clients.table.contains_key(&ip) && full(clients) {
+            let due = clients
+                .swept
+                .is_none_or(|swept| now.duration_since(swept) >= RATE_LIMIT_SWEEP_INTERVAL);
+            if due {
+                clients
+                    .table
+                    .retain(|_, client| !client.is_idle(now, &self.config));
+                clients.swept = Some(now);
+            }
+            if full(clients) {
+                return None;
+            }
+        }
+
+        Some(clients.table.entry(ip).or_insert_with(|| Client {
+            tokens: self.config.burst as f64,
+            updated: now,
+            connections: 0,
+        }))
+    }
+}
+
+impl Middleware for RateLimiter {
+    fn handle(
+        &self,
+        request: &mut Request,
+        config: &ServerConfig,
+        next: Next<'_>,
+    ) -> Result<Response, RequestError> {
+        let Some(ip) = self.client_ip(request) else {
+            return next.run(request, config);
+        };
+        match self.check(ip) {
+            Ok(()) => next.run(request, config),
+            Err(retry_after) => {
+                let seconds = retry_after.as_secs_f64().ceil().max(1.0) as u64;
+                Ok(error_response(StatusCode::TooManyRequests, config)
+                    .with_header("Retry-After", seconds.to_string()))
+            }
+        }
+    }
+}
+
+/// Keeps a connection counted against its client IP, see [`RateLimiter::open_connection`].
+#[derive(Debug)]
+pub struct ConnectionPermit {
+    limiter: RateLimiter,
+    ip: IpAddr,
+}
+
+impl Drop for ConnectionPermit {
+    fn drop(&mut self) {
+        /* Clients with open connections are never forgotten, so the entry is still there. */
+        if let Some(client) = self.limiter.lock().table.get_mut(&self.ip) {
+            client.connections = client.connections.saturating_sub(1);
+        }
+    }
+}
+
+/// This function is a private helper function for [`RateLimiter::client_ip`].
+/// - It parses one address from a client IP header, with or without a port
+///   (`203.0.113.7`, `203.0.113.7:4711`, `[2001:db8::1]:4711`).
+fn parse_ip(address: &str) -> Option<IpAddr> {
+    address
+        .parse::<IpAddr>()
+        .or_else(|_| address.parse::<SocketAddr>().map(|address| address.ip()))
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::BufReader;
+    use std::thread;
+
+    fn limiter(configure: impl FnOnce(&mut RateLimitConfig)) -> RateLimiter {
+        let mut config = RateLimitConfig {
+            enabled: true,
+            ..RateLimitConfig::default()
+        };
+        configure(&mut config);
+        RateLimiter::new(&config)
+    }
+
+    fn ip(address: &str) -> IpAddr {
+        address.parse().unwrap()
+    }
+
+    /// A request from `peer` with the given `X-Forwarded-For` header lines.
+    fn request(peer: &str, forwarded: &[&str]) -> Request {
+        let mut raw = "GET / HTTP/1.1\r\nHost: localhost\r\n".to_string();
+        for value in forwarded {
+            raw.push_str(&format!("X-Forwarded-For: {value}\r\n"));
+        }
+        raw.push_str("\r\n");
+        let mut request = Request::new(&mut BufReader::new(raw.as_bytes())).unwrap();
+        request.set_remote_addr(Some(SocketAddr::new(ip(peer), 4711)));
+        request
+    }
+
+    #[test]
+    fn check_allows_the_burst_then_refuses_until_a_token_is_earned() {
+        let limiter = limiter(|config| {
+            config.requests_per_second = 2.0;
+            config.burst = 3;
+        });
+        let client = ip("192.0.2.1");
+
+        for _ in 0..3 {
+            assert_eq!(limiter.check(client), Ok(()));
+        }
+        let retry_after = limiter.check(client).unwrap_err();
+        assert!(retry_after > Duration::ZERO, "{retry_after:?}");
+        assert!(retry_after <= Duration::from_millis(500), "{retry_after:?}");
+
+        /* Other clients have buckets of their own. */
+        assert_eq!(limiter.check(ip("192.0.2.2")), Ok(()));
+
+        thread::sleep(retry_after);
+        assert_eq!(limiter.check(client), Ok(()));
+        assert!(limiter.check(client).is_err());
+    }
+
+    #[test]
+    fn open_connection_caps_connections_per_ip() {
+        let limiter = limiter(|config| config.max_connections_per_ip = 2);
+        let client = ip("192.0.2.1");
+
+        let first = limiter.open_connection(client).unwrap();
+        let _second = limiter.open_connection(client).unwrap();
+        assert!(limiter.open_connection(client).is_none());
+        assert!(limiter.open_connection(ip("192.0.2.2")).is_some());
+
+        drop(first);
+        assert!(limiter.open_connection(client).is_some());
+    }
+
+    #[test]
+    fn trusted_proxies_and_a_zero_cap_are_never_refused_connections() {
+        let proxied = limiter(|config| {
+            config.max_connections_per_ip = 1;
+            config.trusted_proxies = vec![ip("10.0.0.1")];
+        });
+        let permits = (0..5)
+            .map(|_| proxied.open_connection(ip("10.0.0.1")).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(permits.len(), 5);
+
+        let uncapped = limiter(|config| config.max_connections_per_ip = 0);
+        let permits = (0..50)
+            .map(|_| uncapped.open_connection(ip("192.0.2.1")).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(permits.len(), 50);
+    }
+
+    #[test]
+    fn client_ip_is_the_peer_unless_it_is_a_trusted_proxy() {
+        let limiter = limiter(|config| config.trusted_proxies = vec![ip("10.0.0.1")]);
+
+        /* An untrusted peer can't pick its key with the header. */
+        let spoofed = request("192.0.2.9", &["203.0.113.7"]);
+        assert_eq!(limiter.client_ip(&spoofed), Some(ip("192.0.2.9")));
+
+        let proxied = request("10.0.0.1", &["203.0.113.7"]);
+        assert_eq!(limiter.client_ip(&proxied), Some(ip("203.0.113.7")));
+
+        /* Without the header the proxy itself is the client. */
+        let direct = request("10.0.0.1", &[]);
+        assert_eq!(limiter.client_ip(&direct), Some(ip("10.0.0.1")));
+
+        let mut unknown = request("10.0.0.1", &[]);
+        unknown.set_remote_addr(None);
+        assert_eq!(limiter.client_ip(&unknown), None);
+    }
+
+    #[test]
+    fn client_ip_reads_the_header_right_to_left_skipping_trusted_proxies() {
+        let limiter = limiter(|config| {
+            config.trusted_proxies = vec![ip("10.0.0.1"), ip("10.0.0.2")];
+        });
+
+        /* The leftmost entry was sent by the client and is ignored. */
+        let chain = request("10.0.0.1", &["198.51.100.1, 203.0.113.7, 10.0.0.2"]);
+        assert_eq!(limiter.client_ip(&chain), Some(ip("203.0.113.7")));
+
+        /* Repeated header lines count as one list, in order. */
+        let lines = request(
+            "10.0.0.1",
+            &["198.51.100.1", "203.0.113.7:4711", "10.0.0.2"],
+        );
+        assert_eq!(limiter.client_ip(&lines), Some(ip("203.0.113.7")));
+
+        let ipv6 = request("10.0.0.1", &["[2001:db8::1]:4711"]);
+        assert_eq!(limiter.client_ip(&ipv6), Some(ip("2001:db8::1")));
+
+        /* Garbage stops the walk: the last proxy that could be trusted is the key. */
+        let garbage = request("10.0.0.1", &["203.0.113.7, not-an-ip, 10.0.0.2"]);
+        assert_eq!(limiter.client_ip(&garbage), Some(ip("10.0.0.1")));
+
+        let only_proxies = request("10.0.0.1", &["10.0.0.2"]);
+        assert_eq!(limiter.client_ip(&only_proxies), Some(ip("10.0.0.1")));
+    }
+
+    #[test]
+    fn idle_clients_are_swept_out_to_make_room() {
+        let limiter = limiter(|config| {
+            config.requests_per_second = 1000.0;
+            config.max_clients = 2;
+        });
+        assert_eq!(limiter.check(ip("192.0.2.1")), Ok(()));
+        assert_eq!(limiter.check(ip("192.0.2.2")), Ok(()));
+        assert_eq!(limiter.tracked_clients(), 2);
+
+        /* Both buckets refill within milliseconds, so both may go. */
+        thread::sleep(Duration::from_millis(20));
+        assert_eq!(limiter.check(ip("192.0.2.3")), Ok(()));
+        assert_eq!(limiter.tracked_clients(), 1);
+    }
+
+    #[test]
+    fn clients_with_open_connections_or_used_tokens_are_never_forgotten() {
+        let limiter = limiter(|config| {
+            config.requests_per_second = 0.001;
+            config.burst = 1;
+            config.max_connections_per_ip = 1;
+            config.max_clients = 2;
+        });
+        let victim = ip("192.0.2.1");
+        let _permit = limiter.open_connection(victim).unwrap();
+        assert_eq!(limiter.check(ip("192.0.2.2")), Ok(()));
+
+        /* New IPs are refused instead of pushing out the tracked ones. */
+        for last in 3..10 {
+            let attacker = ip(&format!("192.0.2.{last}"));
+            assert!(limiter.check(attacker).is_err());
+            assert!(limiter.open_connection(attacker).is_none());
+        }
+        assert_eq!(limiter.tracked_clients(), 2);
+        assert!(limiter.open_connection(victim).is_none());
+        assert!(limiter.check(ip("192.0.2.2")).is_err());
+    }
+
+    #[test]
+    fn a_full_table_is_swept_at_most_once_per_interval() {
+        let limiter = limiter(|config| {
+            config.requests_per_second = 1000.0;
+            config.max_clients = 1;
+        });
+        let permit = limiter.open_connection(ip("192.0.2.1")).unwrap();
+        assert!(limiter.check(ip("192.0.2.2")).is_err());
+
+        /* The first client is idle now, but the table was just swept. */
+        drop(permit);
+        thread::sleep(Duration::from_millis(20));
+        assert!(limiter.check(ip("192.0.2.2")).is_err());
+
+        thread::sleep(RATE_LIMIT_SWEEP_INTERVAL);
+        assert_eq!(limiter.check(ip("192.0.2.2")), Ok(()));
+        assert_eq!(limiter.tracked_clients(), 1);
+    }
+}
@@ -1,19 +1,28 @@
-use crate::BASE_DIR;
+use crate::body::read_body;
+use crate::{Headers, Limits, Method, StatusCode, decode_target, parse_query};
 
 use std::fmt::{Display, Formatter};
-use std::io::{self, BufRead, BufReader};
-use std::net::TcpStream;
-use std::path::PathBuf;
+use std::io::{self, BufRead, Read};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
-/// Represents a parsed HTTP request line.
+/// Represents a parsed HTTP request.
 ///
-/// This struct stores only the essential components of an HTTP request:
-/// the request method, the requested URL path, and the HTTP version.
-/// It does not include headers or a request body.
+/// This struct stores the request line (method, requested URL with its percent-decoded path and
+/// query parameters, HTTP version),
+/// every header field that followed it, and the decoded request body with any chunked trailers.
 pub struct Request {
     url: String,
-    method: String,
+    path: String,
+    query: Vec<(String, String)>,
+    params: Vec<(String, String)>,
+    remote_addr: Option<SocketAddr>,
+    method: Method,
     version: String,
+    headers: Headers,
+    body: Vec<u8>,
+    trailers: Headers,
 }
 
 /// Errors that can occur while parsing or validating an incoming HTTP request.
@@ -23,8 +32,19 @@ pub struct Request {
 /// - `InvalidLength`: The request line did not contain exactly three parts
 ///   (`METHOD`, `PATH`, `VERSION`).
 /// - `Io`: An underlying I/O error occurred while reading from the stream.
-/// - `InvalidHeader`: The request line failed validation (unsupported method/version).
-/// - `InvalidURL`: The URL is not valid.
+/// - `InvalidHeader`: The request line failed validation (the version is not an `HTTP/x.y` version).
+/// - `InvalidURL`: The URL is malformed or unsafe to map onto the filesystem, see [`decode_target`].
+/// - `MalformedHeader`: A header line is not a valid `name: value` field, or the header section ended early.
+/// - `TooManyHeaders`: The request has more than [`Limits::max_header_count`] header fields.
+/// - `HeaderTooLarge`: A header line is longer than [`Limits::max_header_line_length`] bytes.
+/// - `InvalidBody`: The body framing (`Content-Length` or chunked encoding) is malformed or the body is cut short.
+/// - `UnsupportedTransferEncoding`: `Transfer-Encoding` names something other than `chunked`.
+/// - `PayloadTooLarge`: The body is larger than [`Limits::max_body_size`] bytes.
+/// - `UnknownMethod`: The method is not one of the [`Method`] variants.
+/// - `UnsupportedVersion`: The version is a well-formed `HTTP/x.y` other than `HTTP/1.0` or `HTTP/1.1`.
+/// - `UriTooLong`: The request line is longer than [`Limits::max_request_line_length`] bytes.
+/// - `Timeout`: The client sent the request or read the response too slowly, see
+///   [`crate::TimeoutConfig`].
 #[derive(Debug, thiserror::Error)]
 pub enum RequestError {
     #[error("Request was empty.")]
@@ -32,36 +52,120 @@ pub enum RequestError {
     #[error("Invalid length in header.")]
     InvalidLength,
     #[error(transparent)]
-    Io(#[from] io::Error),
+    Io(io::Error),
     #[error("The request is not a valid request")]
     InvalidHeader,
     #[error("The url is not a valid url")]
     InvalidURL,
+    #[error("Malformed header line.")]
+    MalformedHeader,
+    #[error("Too many header fields.")]
+    TooManyHeaders,
+    #[error("Header line is too large.")]
+    HeaderTooLarge,
+    #[error("The request body is not framed correctly.")]
+    InvalidBody,
+    #[error("Unsupported transfer encoding.")]
+    UnsupportedTransferEncoding,
+    #[error("The request body is too large.")]
+    PayloadTooLarge,
+    #[error("Unknown request method.")]
+    UnknownMethod,
+    #[error("Unsupported HTTP version.")]
+    UnsupportedVersion,
+    #[error("The request URI is too long.")]
+    UriTooLong,
+    #[error("The client was too slow.")]
+    Timeout,
+}
+
+impl From<io::Error> for RequestError {
+    /// Reads and writes that timed out become [`RequestError::Timeout`], every other I/O error
+    /// [`RequestError::Io`].
+    fn from(error: io::Error) -> Self {
+        match error.kind() {
+            io::ErrorKind::TimedOut => RequestError::Timeout,
+            _ => RequestError::Io(error),
+        }
+    }
+}
+
+impl RequestError {
+    /// Maps the error to the status the client should get back.
+    ///
+    /// # Returns
+    /// - `Some(StatusCode)`: For errors caused by what the client sent.
+    /// - `None`: For [`RequestError::Io`], where the connection itself is broken and no response can be sent.
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            RequestError::Io(_) => None,
+            RequestError::EmptyRequest
+            | RequestError::InvalidLength
+            | RequestError::InvalidHeader
+            | RequestError::InvalidURL
+            | RequestError::MalformedHeader
+            | RequestError::InvalidBody => Some(StatusCode::BadRequest),
+            RequestError::PayloadTooLarge => Some(StatusCode::PayloadTooLarge),
+            RequestError::UriTooLong => Some(StatusCode::UriTooLong),
+            RequestError::TooManyHeaders | RequestError::HeaderTooLarge => {
+                Some(StatusCode::RequestHeaderFieldsTooLarge)
+            }
+            RequestError::UnknownMethod | RequestError::UnsupportedTransferEncoding => {
+                Some(StatusCode::NotImplemented)
+            }
+            RequestError::UnsupportedVersion => Some(StatusCode::HttpVersionNotSupported),
+            RequestError::Timeout => Some(StatusCode::RequestTimeout),
+        }
+    }
 }
 
 impl Request {
-    /// Parses the HTTP request line from the given TCP stream and constructs a [`Request`].
+    /// Parses an HTTP request (request line, header fields and body) from `reader` and constructs a [`Request`].
     ///
-    /// Expects a request line in the form: `GET <path> HTTP/1.1`.
+    /// Expects a request line in the form: `<METHOD> <path> HTTP/1.1` (or `HTTP/1.0`), followed by zero or more
+    /// `Name: value` header lines, an empty line, and a body framed by `Content-Length`
+    /// or `Transfer-Encoding: chunked`.
     ///
     /// # Note
-    /// - Only the first line of the request is parsed. Everything else is irrelevant.
+    /// - Header names are matched case-insensitively, see [`Headers`].
+    /// - Without `Content-Length` or `Transfer-Encoding` the request has no body.
+    /// - Reading stops right after the body, so the next request on the connection is left unread.
     ///
     /// # Arguments
-    /// - `stream`: Reference to the client [`TcpStream`] to read from.
+    /// - `reader`: Buffered reader over the client connection.
     ///
     /// # Returns
-    /// - `Ok(Request)`: If the request line is present and valid.
+    /// - `Ok(Request)`: If the request line and headers are present and valid.
     /// - `Err(RequestError::EmptyRequest)`: If the connection contains no request line.
     /// - `Err(RequestError::InvalidLength)`: If the request line does not have exactly three parts.
-    /// - `Err(RequestError::InvalidHeader)`: If the method or HTTP version is invalid.
+    /// - `Err(RequestError::InvalidHeader)`: If the HTTP version is malformed.
+    /// - `Err(RequestError::InvalidURL)`: If the URL or its query string is malformed, see
+    ///   [`decode_target`] and [`parse_query`].
+    /// - `Err(RequestError::UnknownMethod)`: If the method is unknown.
+    /// - `Err(RequestError::UnsupportedVersion)`: If the HTTP version is neither `HTTP/1.0` nor `HTTP/1.1`.
+    /// - `Err(RequestError::UriTooLong)`: If the request line exceeds [`Limits::max_request_line_length`] bytes.
+    /// - `Err(RequestError::MalformedHeader)`: If a header line is malformed or the connection ends before the empty line.
+    /// - `Err(RequestError::TooManyHeaders)`: If there are more than [`Limits::max_header_count`] header fields.
+    /// - `Err(RequestError::HeaderTooLarge)`: If a header line exceeds [`Limits::max_header_line_length`] bytes.
+    /// - `Err(RequestError::InvalidBody)`: If the body framing is malformed or the body is cut short.
+    /// - `Err(RequestError::UnsupportedTransferEncoding)`: If the body uses a transfer coding other than `chunked`.
+    /// - `Err(RequestError::PayloadTooLarge)`: If the body exceeds [`Limits::max_body_size`] bytes.
+    /// - `Err(RequestError::Timeout)`: If a read from the stream timed out.
     /// - `Err(RequestError::Io(_))`: If an I/O error occurs while reading from the stream.
-    pub fn new(stream: &TcpStream) -> Result<Self, RequestError> {
-        let request_buf = BufReader::new(stream);
+    pub fn new<R: BufRead>(reader: &mut R) -> Result<Self, RequestError> {
+        Self::with_limits(reader, &Limits::default())
+    }
 
+    /// Same as [`Self::new`], but enforces the given `limits` instead of the defaults.
+    pub fn with_limits<R: BufRead>(reader: &mut R, limits: &Limits) -> Result<Self, RequestError> {
         /* If request is not empty, parse into string. */
-        let request = match request_buf.lines().next() {
-            Some(value) => value?,
+        let line =
+            read_line(reader, limits.max_request_line_length).map_err(|error| match error {
+                RequestError::HeaderTooLarge => RequestError::UriTooLong,
+                error => error,
+            })?;
+        let request = match line {
+            Some(line) => String::from_utf8(line).map_err(|_| RequestError::InvalidHeader)?,
             None => return Err(RequestError::EmptyRequest),
         };
 
@@ -75,23 +179,147 @@ impl Request {
         if data.len() != 3 {
             return Err(RequestError::InvalidLength);
         }
-        if !(data[0] == "GET" && data[2] == "HTTP/1.1") {
-            return Err(RequestError::InvalidHeader);
+        let method: Method = data[0].parse()?;
+        let path = decode_target(data[1])?;
+        if path == "*" && method != Method::Options {
+            return Err(RequestError::InvalidURL);
+        }
+        let query = match data[1].split_once('?') {
+            Some((_, query)) => parse_query(query.split('#').next().unwrap_or_default())?,
+            None => Vec::new(),
+        };
+        if !matches!(data[2], "HTTP/1.1" | "HTTP/1.0") {
+            let is_http_version = data[2]
+                .strip_prefix("HTTP/")
+                .and_then(|number| number.split_once('.'))
+                .is_some_and(|(major, minor)| {
+                    [major, minor].iter().all(|part| {
+                        !part.is_empty() && part.bytes().all(|byte| byte.is_ascii_digit())
+                    })
+                });
+            return Err(if is_http_version {
+                RequestError::UnsupportedVersion
+            } else {
+                RequestError::InvalidHeader
+            });
         }
 
+        let headers = Self::parse_headers(reader, limits)?;
+        let (body, trailers) = read_body(reader, &headers, limits)?;
+
         Ok(Self {
             url: data[1].to_string(),
-            method: data[0].to_string(),
+            path,
+            query,
+            params: Vec::new(),
+            remote_addr: None,
+            method,
             version: data[2].to_string(),
+            headers,
+            body,
+            trailers,
         })
     }
 
-    /// Resolves the request URL into a filesystem path under [`BASE_DIR`] and returns it only if it is safe and exists.
+    /// Returns the request method.
+    pub fn method(&self) -> Method {
+        self.method
+    }
+
+    /// Returns the HTTP version from the request line (`HTTP/1.1` or `HTTP/1.0`).
+    pub fn version(&self) -> &str {
+        &self.version
+    }
+
+    /// Returns `true` if the client wants to keep the connection open after this request.
+    ///
+    /// - `HTTP/1.1` connections are persistent unless the `Connection` header contains `close`.
+    /// - `HTTP/1.0` connections close unless the `Connection` header contains `keep-alive`.
+    pub fn keep_alive(&self) -> bool {
+        let has_option = |option: &str| {
+            self.headers
+                .get_all("Connection")
+                .flat_map(|value| value.split(','))
+                .any(|token| token.trim().eq_ignore_ascii_case(option))
+        };
+
+        if has_option("close") {
+            false
+        } else if self.version == "HTTP/1.0" {
+            has_option("keep-alive")
+        } else {
+            true
+        }
+    }
+
+    /// Returns the value of the first header field called `name` (case-insensitive), if any.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name)
+    }
+
+    /// Returns every header field of the request.
+    pub fn headers(&self) -> &Headers {
+        &self.headers
+    }
+
+    /// Returns the decoded request body. Empty if the request had none.
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+
+    /// Returns the trailer fields sent after a chunked body. Empty for any other request.
+    pub fn trailers(&self) -> &Headers {
+        &self.trailers
+    }
+
+    /// This function is a helper function for [`Self::new`] and the chunked body trailers.
+    /// - It reads header lines until the empty line that ends the section.
+    /// - Whitespace around the value is trimmed; the name must be a non-empty token with no
+    ///   whitespace before the `:`.
+    /// - Obsolete line folding (a line starting with a space or tab) is rejected.
+    pub(crate) fn parse_headers<R: BufRead>(
+        reader: &mut R,
+        limits: &Limits,
+    ) -> Result<Headers, RequestError> {
+        let mut headers = Headers::new();
+
+        loop {
+            let line = match read_line(reader, limits.max_header_line_length)? {
+                Some(line) => line,
+                None => return Err(RequestError::MalformedHeader),
+            };
+            if line.is_empty() {
+                return Ok(headers);
+            }
+            if headers.len() == limits.max_header_count {
+                return Err(RequestError::TooManyHeaders);
+            }
+
+            let line = String::from_utf8(line).map_err(|_| RequestError::MalformedHeader)?;
+            let (name, value) = line.split_once(':').ok_or(RequestError::MalformedHeader)?;
+            if name.is_empty() || !name.bytes().all(is_token_byte) {
+                return Err(RequestError::MalformedHeader);
+            }
+            let value = value.trim_matches([' ', '\t']);
+            if value
+                .chars()
+                .any(|char| char.is_ascii_control() && char != '\t')
+            {
+                return Err(RequestError::MalformedHeader);
+            }
+
+            headers.append(name, value);
+        }
+    }
+
+    /// Resolves the request URL into a filesystem path under `base_dir` and returns it only if it is safe and exists.
     ///
     /// Routing rules
-    /// - Routes are folder-based (directory routing).
-    /// - Every incoming path is treated as a route, not a direct file reference.
-    /// - The server always serves the `index.html` file inside that route directory.
+    /// - A path that names a file is served directly.
+    ///   Examples:
+    ///   - `/style.css` -> `style.css`
+    ///   - `/img/logo.png` -> `img/logo.png`
+    /// - A path that names a directory is a route, and the `index.html` file inside it is served.
     ///   Examples:
     ///   - `/` -> `index.html`
     ///   - `/docs` -> `docs/index.html`
@@ -99,75 +327,236 @@ impl Request {
     ///   - `/about/value/something/` -> `about/value/something/index.html`
     /// - Query strings (`?`) and fragments (`#`) are ignored for routing.
     ///   Example: `/docs?x=1#top` routes the same as `/docs`.
+    /// - The path is percent-decoded first, so `/my%20page.html` serves `my page.html`.
+    ///   Encoded slashes, backslashes, NUL bytes and bad escapes never get here: the request is
+    ///   rejected with [`RequestError::InvalidURL`] while it is parsed.
     ///
     /// Security model
     /// - The base directory is canonicalized first (absolute path, resolves symlinks).
     /// - The candidate file path is then built under the canonical base directory and canonicalized.
     /// - The canonical candidate must start with the canonical base (`starts_with`).
     ///   This blocks both `..` directory traversal and symlink-based escapes.
+    /// - For a directory, the `index.html` inside it is canonicalized and checked the same way.
     ///
     /// Returns
-    /// - `Some(PathBuf)` if the resolved file exists, is a regular file, and remains inside [`BASE_DIR`].
+    /// - `Some(PathBuf)` if the resolved file exists, is a regular file, and remains inside `base_dir`.
     /// - `None` if the file does not exist or the resolved path is unsafe (outside the base directory).
+    pub fn path_exists(&self, base_dir: &Path) -> Option<PathBuf> {
+        resolve_file(&self.path, base_dir)
+    }
 
-    pub fn path_exists(&self) -> Option<PathBuf> {
-        println!("The request is: {}", &self);
-        let base_dir_relative = if BASE_DIR.is_empty() {
-            PathBuf::from(".")
-        } else {
-            PathBuf::from(BASE_DIR)
-        };
+    /// Resolves the request URL into a directory under `base_dir`, with the same security checks
+    /// as [`Self::path_exists`]. Used for directory listings when the directory has no `index.html`.
+    ///
+    /// Returns
+    /// - `Some(PathBuf)` with the canonical directory path if it exists and remains inside `base_dir`.
+    /// - `None` if the URL names a file, nothing, or a path outside the base directory.
+    pub fn directory_exists(&self, base_dir: &Path) -> Option<PathBuf> {
+        resolve_directory(&self.path, base_dir)
+    }
 
-        /* Canonicalize and verify the relative path given by user in the program. */
-        let base_dir_canonical = match base_dir_relative.canonicalize() {
-            Ok(path) => path,
-            Err(error) => {
-                eprintln!("BASE_DIR cannot be canonicalized: {error}");
-                return None;
-            }
-        };
+    /// Returns the value of the route parameter called `name`, if the matched route has one.
+    ///
+    /// Example: with the route `/users/:id`, `param("id")` is `Some("42")` for `/users/42`.
+    /// A trailing wildcard (`/files/*path`) captures the rest of the path without its leading `/`.
+    /// See [`crate::Router`].
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
 
-        /* Join and canonicalize the normalized url with the base dir */
-        /* This helps prevent both .. traversal and symlink escapes*/
-        let normalized_relative = Self::normalize_path_string(self.url.as_str())?;
-        let path_canonical = base_dir_canonical
-            .join(normalized_relative)
-            .canonicalize()
-            .ok()?;
+    /// Returns every route parameter as `(name, value)` pairs, in pattern order.
+    pub fn params(&self) -> &[(String, String)] {
+        &self.params
+    }
+
+    /// Replaces the route parameters. Called by [`crate::Router`] once a route matches.
+    pub(crate) fn set_params(&mut self, params: Vec<(String, String)>) {
+        self.params = params;
+    }
 
-        /* Check if the new path stays inside base directory.*/
-        if !path_canonical.starts_with(base_dir_canonical) {
+    /// Returns the address of the connection the request came in on: the client, or the proxy
+    /// in front of it. `None` for requests not read from a connection.
+    pub fn remote_addr(&self) -> Option<SocketAddr> {
+        self.remote_addr
+    }
+
+    /// Sets the connection address. Called by [`crate::handle_connection`] once the request is parsed.
+    pub(crate) fn set_remote_addr(&mut self, remote_addr: Option<SocketAddr>) {
+        self.remote_addr = remote_addr;
+    }
+
+    /// Returns the request URL exactly as it appeared on the request line.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Returns the percent-decoded path of the request URL, without the query string or fragment.
+    /// Example: `/my%20page?x=1` -> `/my page`.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Returns the raw query string of the request URL (the part after `?`, without the
+    /// fragment), if any. See [`Self::query`] for the decoded parameters.
+    pub fn query_string(&self) -> Option<&str> {
+        let (_, query) = self.url.split_once('?')?;
+        query.split('#').next()
+    }
+
+    /// Returns the decoded value of the first query parameter called `name`, if any.
+    ///
+    /// Example: for `/search?q=rust+server&page=2`, `query("q")` is `Some("rust server")`.
+    /// Names are case-sensitive. A parameter without `=` (`?debug`) has the value `""`.
+    pub fn query(&self, name: &str) -> Option<&str> {
+        self.query
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Returns the decoded values of every query parameter called `name`, in URL order.
+    ///
+    /// Example: for `/posts?tag=rust&tag=http`, `query_all("tag")` yields `"rust"`, `"http"`.
+    pub fn query_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.query
+            .iter()
+            .filter(move |(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Parses the first query parameter called `name` as a `T`.
+    ///
+    /// # Returns
+    /// - `None`: If there is no such parameter.
+    /// - `Some(Ok(T))`: If it parses. Example: `query_as::<u32>("page")` for `?page=2` is `Some(Ok(2))`.
+    /// - `Some(Err(T::Err))`: If it doesn't.
+    pub fn query_as<T: FromStr>(&self, name: &str) -> Option<Result<T, T::Err>> {
+        self.query(name).map(str::parse)
+    }
+
+    /// Returns every decoded query parameter as `(name, value)` pairs, in URL order.
+    pub fn query_pairs(&self) -> &[(String, String)] {
+        &self.query
+    }
+}
+
+/// Resolves a decoded URL path (such as [`Request::path`]) into a file under `base_dir`, with the
+/// routing rules and security checks described on [`Request::path_exists`].
+pub fn resolve_file(path: &str, base_dir: &Path) -> Option<PathBuf> {
+    let (base_dir_canonical, mut path_canonical) = resolve(path, base_dir)?;
+
+    /* A directory is a route: serve its index.html, which may itself be a symlink. */
+    if path_canonical.is_dir() {
+        path_canonical = path_canonical.join("index.html").canonicalize().ok()?;
+        if !path_canonical.starts_with(&base_dir_canonical) {
             return None;
         }
+    }
 
-        /* Check if the path is a file or not. */
-        if path_canonical.is_file() {
-            Some(path_canonical)
-        } else {
-            None
+    /* Check if the path is a file or not. */
+    if path_canonical.is_file() {
+        Some(path_canonical)
+    } else {
+        None
+    }
+}
+
+/// Resolves a decoded URL path into a directory under `base_dir`, see [`Request::directory_exists`].
+pub fn resolve_directory(path: &str, base_dir: &Path) -> Option<PathBuf> {
+    let (_, path_canonical) = resolve(path, base_dir)?;
+    path_canonical.is_dir().then_some(path_canonical)
+}
+
+/// This function is a private helper function for [`resolve_file`] and [`resolve_directory`].
+/// - It canonicalizes `base_dir` and the path joined onto it.
+/// - It returns both, or `None` if either can't be canonicalized or the path escapes the base.
+fn resolve(path: &str, base_dir: &Path) -> Option<(PathBuf, PathBuf)> {
+    let base_dir_relative = if base_dir.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        base_dir
+    };
+
+    /* Canonicalize and verify the relative path given by user in the program. */
+    let base_dir_canonical = match base_dir_relative.canonicalize() {
+        Ok(path) => path,
+        Err(error) => {
+            eprintln!("The document root cannot be canonicalized: {error}");
+            return None;
         }
+    };
+
+    /* Join and canonicalize the normalized url with the base dir */
+    /* This helps prevent both .. traversal and symlink escapes*/
+    let normalized_relative = normalize_path_string(path)?;
+    let path_canonical = base_dir_canonical
+        .join(normalized_relative)
+        .canonicalize()
+        .ok()?;
+
+    /* Check if the new path stays inside base directory.*/
+    if !path_canonical.starts_with(&base_dir_canonical) {
+        return None;
     }
 
-    /// This function is a private helper function for [`Self::path_exists`].
-    /// - It normalizes the string by removing anything after `?` or `#`.
-    /// - Leading or trailing "/" are ignored.
-    fn normalize_path_string(raw: &str) -> Option<PathBuf> {
-        let query_stripped = raw.splitn(2, '?').next().unwrap();
-        let fragment_stripped = query_stripped.splitn(2, '#').next().unwrap();
+    Some((base_dir_canonical, path_canonical))
+}
 
-        /* Get the OS specific path from the string */
-        let mut normalized_path_string = PathBuf::new();
-        for element in fragment_stripped.split('/') {
-            if element.is_empty() {
-                continue;
-            }
-            normalized_path_string.push(element)
+/// This function is a private helper function for [`resolve`].
+/// - It turns the decoded URL path into a relative filesystem path.
+/// - Leading, trailing and repeated "/" are ignored.
+fn normalize_path_string(decoded_path: &str) -> Option<PathBuf> {
+    /* Get the OS specific path from the string */
+    let mut normalized_path_string = PathBuf::new();
+    for element in decoded_path.split('/') {
+        if element.is_empty() {
+            continue;
         }
-        normalized_path_string.push("index");
-        normalized_path_string.set_extension("html");
+        normalized_path_string.push(element)
+    }
+
+    Some(normalized_path_string)
+}
+
+/// Reads one line terminated by `\n` (with an optional `\r` before it) and returns it without the terminator.
+///
+/// # Returns
+/// - `Ok(Some(line))`: If a line was read. A final line without a terminator is returned as is.
+/// - `Ok(None)`: If the reader was already at end of stream.
+/// - `Err(RequestError::HeaderTooLarge)`: If the line is longer than `limit` bytes.
+/// - `Err(RequestError::Io(_))`: If reading fails.
+pub(crate) fn read_line<R: BufRead>(
+    reader: &mut R,
+    limit: usize,
+) -> Result<Option<Vec<u8>>, RequestError> {
+    let mut line = Vec::new();
+    let max_read = (limit as u64).saturating_add(2);
+    reader
+        .by_ref()
+        .take(max_read)
+        .read_until(b'\n', &mut line)?;
 
-        Some(normalized_path_string)
+    if line.is_empty() {
+        return Ok(None);
     }
+    if line.last() == Some(&b'\n') {
+        line.pop();
+        if line.last() == Some(&b'\r') {
+            line.pop();
+        }
+    }
+    if line.len() > limit {
+        return Err(RequestError::HeaderTooLarge);
+    }
+    Ok(Some(line))
+}
+
+/// Returns `true` for the bytes allowed in an HTTP token such as a header name (RFC 9110 section 5.6.2).
+fn is_token_byte(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&byte)
 }
 
 impl Display for Request {
@@ -178,3 +567,111 @@ impl Display for Request {
         write!(f, "{} {} {}", self.method, self.url, self.version)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::BufReader;
+
+    /// Parses `head` (the header lines after `GET / HTTP/1.1`) with `limits`.
+    fn parse_with(head: &str, limits: &Limits) -> Result<Request, RequestError> {
+        let raw = format!("GET / HTTP/1.1\r\n{head}\r\n");
+        Request::with_limits(&mut BufReader::new(raw.as_bytes()), limits)
+    }
+
+    fn parse(head: &str) -> Result<Request, RequestError> {
+        parse_with(head, &Limits::default())
+    }
+
+    #[test]
+    fn header_fields_are_parsed_and_trimmed() {
+        let request = parse("Host: localhost\r\nX-Empty:\r\nX-Padded: \t a b \t\r\n").unwrap();
+
+        assert_eq!(request.header("host"), Some("localhost"));
+        assert_eq!(request.header("X-Empty"), Some(""));
+        assert_eq!(request.header("x-padded"), Some("a b"));
+        assert_eq!(request.headers().len(), 3);
+    }
+
+    #[test]
+    fn duplicate_header_fields_are_all_kept() {
+        let request = parse("Accept: text/html\r\nHost: localhost\r\naccept: */*\r\n").unwrap();
+
+        assert_eq!(request.header("Accept"), Some("text/html"));
+        assert_eq!(
+            request.headers().get_all("Accept").collect::<Vec<_>>(),
+            ["text/html", "*/*"]
+        );
+    }
+
+    #[test]
+    fn malformed_header_lines_are_rejected() {
+        for head in [
+            "Host localhost\r\n",
+            ": no name\r\n",
+            "Host : localhost\r\n",
+            "Bad Name: value\r\n",
+            "Host: local\u{1}host\r\n",
+            "Host: localhost\r\n folded: continuation\r\n",
+            "Host: localhost\r\n\tfolded\r\n",
+        ] {
+            assert!(
+                matches!(parse(head), Err(RequestError::MalformedHeader)),
+                "{head:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn a_head_cut_off_before_the_empty_line_is_malformed() {
+        let raw = "GET / HTTP/1.1\r\nHost: localhost\r\n";
+        assert!(matches!(
+            Request::new(&mut BufReader::new(raw.as_bytes())),
+            Err(RequestError::MalformedHeader)
+        ));
+    }
+
+    #[test]
+    fn header_lines_over_the_length_limit_get_431() {
+        let limits = Limits {
+            max_header_line_length: 16,
+            ..Limits::default()
+        };
+        assert!(parse_with("X-Fits: 12345678\r\n", &limits).is_ok());
+
+        let Err(error) = parse_with("X-Long: 123456789\r\n", &limits) else {
+            panic!("the limit was not enforced");
+        };
+        assert!(matches!(error, RequestError::HeaderTooLarge));
+        assert_eq!(
+            error.status(),
+            Some(StatusCode::RequestHeaderFieldsTooLarge)
+        );
+    }
+
+    #[test]
+    fn more_header_fields_than_the_limit_get_431() {
+        let limits = Limits {
+            max_header_count: 2,
+            ..Limits::default()
+        };
+        assert!(parse_with("A: 1\r\nB: 2\r\n", &limits).is_ok());
+
+        let Err(error) = parse_with("A: 1\r\nB: 2\r\nC: 3\r\n", &limits) else {
+            panic!("the limit was not enforced");
+        };
+        assert!(matches!(error, RequestError::TooManyHeaders));
+        assert_eq!(
+            error.status(),
+            Some(StatusCode::RequestHeaderFieldsTooLarge)
+        );
+    }
+
+    #[test]
+    fn lines_end_with_crlf_or_a_bare_lf() {
+        let raw = "GET / HTTP/1.1\nHost: localhost\nAccept: */*\r\n\n";
+        let request = Request::new(&mut BufReader::new(raw.as_bytes())).unwrap();
+        assert_eq!(request.header("Host"), Some("localhost"));
+        assert_eq!(request.header("Accept"), Some("*/*"));
+    }
+}
@@ -0,0 +1,445 @@
+use crate::{Headers, http_date};
+
+use std::fmt::{Debug, Display, Formatter};
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use std::time::SystemTime;
+
+/// The value sent in the `Server` header of every response.
+pub static SERVER_NAME: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+
+/// The HTTP status codes this server sends, with their standard reason phrases (RFC 9110 section 15).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StatusCode {
+    Ok,
+    NoContent,
+    PartialContent,
+    MovedPermanently,
+    Found,
+    NotModified,
+    TemporaryRedirect,
+    PermanentRedirect,
+    BadRequest,
+    Unauthorized,
+    Forbidden,
+    NotFound,
+    MethodNotAllowed,
+    NotAcceptable,
+    RequestTimeout,
+    PreconditionFailed,
+    PayloadTooLarge,
+    UriTooLong,
+    RangeNotSatisfiable,
+    TooManyRequests,
+    RequestHeaderFieldsTooLarge,
+    InternalServerError,
+    NotImplemented,
+    ServiceUnavailable,
+    HttpVersionNotSupported,
+}
+
+impl StatusCode {
+    /// The numeric status code, e.g. `404`.
+    pub fn code(&self) -> u16 {
+        match self {
+            StatusCode::Ok => 200,
+            StatusCode::NoContent => 204,
+            StatusCode::PartialContent => 206,
+            StatusCode::MovedPermanently => 301,
+            StatusCode::Found => 302,
+            StatusCode::NotModified => 304,
+            StatusCode::TemporaryRedirect => 307,
+            StatusCode::PermanentRedirect => 308,
+            StatusCode::BadRequest => 400,
+            StatusCode::Unauthorized => 401,
+            StatusCode::Forbidden => 403,
+            StatusCode::NotFound => 404,
+            StatusCode::MethodNotAllowed => 405,
+            StatusCode::NotAcceptable => 406,
+            StatusCode::RequestTimeout => 408,
+            StatusCode::PreconditionFailed => 412,
+            StatusCode::PayloadTooLarge => 413,
+            StatusCode::UriTooLong => 414,
+            StatusCode::RangeNotSatisfiable => 416,
+            StatusCode::TooManyRequests => 429,
+            StatusCode::RequestHeaderFieldsTooLarge => 431,
+            StatusCode::InternalServerError => 500,
+            StatusCode::NotImplemented => 501,
+            StatusCode::ServiceUnavailable => 503,
+            StatusCode::HttpVersionNotSupported => 505,
+        }
+    }
+
+    /// The standard reason phrase, e.g. `Not Found`.
+    pub fn reason(&self) -> &'static str {
+        match self {
+            StatusCode::Ok => "OK",
+            StatusCode::NoContent => "No Content",
+            StatusCode::PartialContent => "Partial Content",
+            StatusCode::MovedPermanently => "Moved Permanently",
+            StatusCode::Found => "Found",
+            StatusCode::NotModified => "Not Modified",
+            StatusCode::TemporaryRedirect => "Temporary Redirect",
+            StatusCode::PermanentRedirect => "Permanent Redirect",
+            StatusCode::BadRequest => "Bad Request",
+            StatusCode::Unauthorized => "Unauthorized",
+            StatusCode::Forbidden => "Forbidden",
+            StatusCode::NotFound => "Not Found",
+            StatusCode::MethodNotAllowed => "Method Not Allowed",
+            StatusCode::NotAcceptable => "Not Acceptable",
+            StatusCode::RequestTimeout => "Request Timeout",
+            StatusCode::PreconditionFailed => "Precondition Failed",
+            StatusCode::PayloadTooLarge => "Payload Too Large",
+            StatusCode::UriTooLong => "URI Too Long",
+            StatusCode::RangeNotSatisfiable => "Range Not Satisfiable",
+            StatusCode::TooManyRequests => "Too Many Requests",
+            StatusCode::RequestHeaderFieldsTooLarge => "Request Header Fields Too Large",
+            StatusCode::InternalServerError => "Internal Server Error",
+            StatusCode::NotImplemented => "Not Implemented",
+            StatusCode::ServiceUnavailable => "Service Unavailable",
+            StatusCode::HttpVersionNotSupported => "HTTP Version Not Supported",
+        }
+    }
+
+    /// Returns `true` for `2xx` codes.
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.code())
+    }
+
+    /// Returns `true` for statuses that must not carry a body (`204` and `304`).
+    pub fn forbids_body(&self) -> bool {
+        matches!(self, StatusCode::NoContent | StatusCode::NotModified)
+    }
+}
+
+impl Display for StatusCode {
+    /// Formats the status as it appears on the status line: `<code> <reason>`.
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.code(), self.reason())
+    }
+}
+
+/// Where the bytes of a [`Response`] body come from.
+///
+/// # Variants
+/// - `Empty`: No body. `Content-Length: 0` is sent.
+/// - `Bytes`: An in-memory buffer.
+/// - `File`: An open file. Its length is taken from the file metadata.
+/// - `Reader`: Any other source. If the length is `None` the body is sent with `Transfer-Encoding: chunked`.
+pub enum Body {
+    Empty,
+    Bytes(Vec<u8>),
+    File(File),
+    Reader(Box<dyn Read + Send>, Option<u64>),
+}
+
+impl Body {
+    /// The body length in bytes, or `None` if it isn't known before sending.
+    pub fn len(&self) -> io::Result<Option<u64>> {
+        Ok(match self {
+            Body::Empty => Some(0),
+            Body::Bytes(bytes) => Some(bytes.len() as u64),
+            Body::File(file) => Some(file.metadata()?.len()),
+            Body::Reader(_, length) => *length,
+        })
+    }
+}
+
+impl Debug for Body {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Body::Empty => write!(f, "Empty"),
+            Body::Bytes(bytes) => write!(f, "Bytes({} bytes)", bytes.len()),
+            Body::File(file) => write!(f, "File({file:?})"),
+            Body::Reader(_, length) => write!(f, "Reader({length:?})"),
+        }
+    }
+}
+
+/// An HTTP response: status, header fields and a [`Body`].
+///
+/// Built with [`Response::new`] and the `with_*` methods, then sent with [`Response::write_to`],
+/// which takes care of the status line and the `Content-Length`/`Transfer-Encoding`, `Date` and
+/// `Server` headers.
+#[derive(Debug)]
+pub struct Response {
+    status: StatusCode,
+    headers: Headers,
+    body: Body,
+    omit_body: bool,
+    close_delimited: bool,
+}
+
+impl Response {
+    /// Creates a response with the given status, no headers and an empty body.
+    pub fn new(status: StatusCode) -> Self {
+        Self {
+            status,
+            headers: Headers::new(),
+            body: Body::Empty,
+            omit_body: false,
+            close_delimited: false,
+        }
+    }
+
+    /// Adds a header field, keeping existing fields with the same name.
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.append(name, value);
+        self
+    }
+
+    /// Replaces the body with an in-memory buffer.
+    pub fn with_bytes(mut self, bytes: impl Into<Vec<u8>>) -> Self {
+        self.body = Body::Bytes(bytes.into());
+        self
+    }
+
+    /// Replaces the body with the contents of an open file.
+    pub fn with_file(mut self, file: File) -> Self {
+        self.body = Body::File(file);
+        self
+    }
+
+    /// Replaces the body with a streaming reader. Pass `None` as `length` to send it chunked.
+    pub fn with_reader(mut self, reader: impl Read + Send + 'static, length: Option<u64>) -> Self {
+        self.body = Body::Reader(Box::new(reader), length);
+        self
+    }
+
+    /// Replaces the body.
+    pub fn with_body(mut self, body: Body) -> Self {
+        self.body = body;
+        self
+    }
+
+    /// Keeps every header (including the `Content-Length` of the real body) but sends no body bytes.
+    /// Used to answer `HEAD` requests.
+    pub fn omit_body(mut self) -> Self {
+        self.omit_body = true;
+        self
+    }
+
+    /// Sends a body of unknown length as is, ended by closing the connection, instead of with
+    /// `Transfer-Encoding: chunked`. Used to answer `HTTP/1.0` requests, which have no chunked framing.
+    pub fn close_delimited(mut self) -> Self {
+        self.close_delimited = true;
+        self
+    }
+
+    /// The response status.
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    /// Changes the response status.
+    pub fn set_status(&mut self, status: StatusCode) {
+        self.status = status;
+    }
+
+    /// The response header fields.
+    pub fn headers(&self) -> &Headers {
+        &self.headers
+    }
+
+    /// Mutable access to the response header fields.
+    pub fn headers_mut(&mut self) -> &mut Headers {
+        &mut self.headers
+    }
+
+    /// The response body.
+    pub fn body(&self) -> &Body {
+        &self.body
+    }
+
+    /// Takes the body out of the response, leaving [`Body::Empty`] in its place.
+    pub fn take_body(&mut self) -> Body {
+        std::mem::replace(&mut self.body, Body::Empty)
+    }
+
+    /// Writes the status line, headers and body to `writer`.
+    ///
+    /// - `Date` and `Server` are added unless already set.
+    /// - `Content-Length` is set from the body; a body of unknown length is sent with
+    ///   `Transfer-Encoding: chunked` instead, or, after [`Self::close_delimited`], without
+    ///   framing and with `Connection: close`. The caller must then close the connection.
+    /// - `204` and `304` responses never get a body or framing headers.
+    /// - A body of known length sends exactly `Content-Length` bytes, even if a file grew since.
+    ///
+    /// # Returns
+    /// - `Ok(bytes)`: The number of body bytes written (not counting chunk framing).
+    /// - `Err(io::Error)`: If reading the body or writing to `writer` fails, or the body ends
+    ///   before its `Content-Length` (the connection must then be closed).
+    pub fn write_to(mut self, writer: impl Write) -> io::Result<u64> {
+        let length = self.body.len()?;
+        let send_body = !self.omit_body && !self.status.forbids_body();
+
+        if !self.headers.contains("Date") {
+            self.headers.insert("Date", http_date(SystemTime::now()));
+        }
+        if !self.headers.contains("Server") {
+            self.headers.insert("Server", SERVER_NAME);
+        }
+        if self.status.forbids_body() {
+            self.headers.remove("Content-Length");
+            self.headers.remove("Transfer-Encoding");
+        } else if let Some(length) = length {
+            self.headers.remove("Transfer-Encoding");
+            self.headers.insert("Content-Length", length.to_string());
+        } else if self.close_delimited {
+            self.headers.remove("Content-Length");
+            self.headers.remove("Transfer-Encoding");
+            self.headers.insert("Connection", "close");
+        } else {
+            self.headers.remove("Content-Length");
+            self.headers.insert("Transfer-Encoding", "chunked");
+        }
+
+        let mut buf_writer = BufWriter::new(writer);
+        write!(buf_writer, "HTTP/1.1 {}\r\n", self.status)?;
+        write!(buf_writer, "{}", self.headers)?;
+        write!(buf_writer, "\r\n")?;
+
+        let mut written = 0;
+        if send_body {
+            written = match self.body {
+                Body::Empty => 0,
+                Body::Bytes(bytes) => {
+                    buf_writer.write_all(&bytes)?;
+                    bytes.len() as u64
+                }
+                Body::File(file) => copy_exact(file, &mut buf_writer, length.unwrap_or(0))?,
+                Body::Reader(reader, Some(length)) => copy_exact(reader, &mut buf_writer, length)?,
+                Body::Reader(mut reader, None) if self.close_delimited => {
+                    io::copy(&mut reader, &mut buf_writer)?
+                }
+                Body::Reader(mut reader, None) => {
+                    let mut chunked = ChunkedWriter::new(&mut buf_writer);
+                    let written = io::copy(&mut reader, &mut chunked)?;
+                    chunked.finish()?;
+                    written
+                }
+            };
+        }
+        buf_writer.flush()?;
+
+        Ok(written)
+    }
+}
+
+/// This function is a private helper function for [`Response::write_to`].
+/// - It copies exactly `length` bytes of `reader` to `writer`, so a file that grew after its
+///   `Content-Length` was sent can't spill into the next response.
+/// - A body that ends early is an `UnexpectedEof` error: the framing can't be kept anymore.
+fn copy_exact(reader: impl Read, writer: &mut impl Write, length: u64) -> io::Result<u64> {
+    let written = io::copy(&mut reader.take(length), writer)?;
+    if written < length {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            format!("the body ended after {written} of its {length} bytes"),
+        ));
+    }
+    Ok(written)
+}
+
+/// Wraps a writer and frames everything written to it as `Transfer-Encoding: chunked`.
+///
+/// Each `write` call becomes one chunk, so the inner writer should be buffered.
+/// [`ChunkedWriter::finish`] writes the terminating zero-size chunk.
+pub struct ChunkedWriter<W: Write> {
+    inner: W,
+}
+
+impl<W: Write> ChunkedWriter<W> {
+    /// Creates a chunked writer on top of `inner`.
+    pub fn new(inner: W) -> Self {
+        Self { inner }
+    }
+
+    /// Writes the last (empty) chunk and returns the inner writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.inner.write_all(b"0\r\n\r\n")?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for ChunkedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        /* An empty chunk would end the body early. */
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        write!(self.inner, "{:X}\r\n", buf.len())?;
+        self.inner.write_all(buf)?;
+        self.inner.write_all(b"\r\n")?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn written(response: Response) -> String {
+        let mut output = Vec::new();
+        response.write_to(&mut output).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn unknown_length_bodies_are_chunked() {
+        let output = written(Response::new(StatusCode::Ok).with_reader(&b"hello"[..], None));
+
+        assert!(output.contains("Transfer-Encoding: chunked\r\n"));
+        assert!(!output.contains("Content-Length"));
+        assert!(output.ends_with("\r\n\r\n5\r\nhello\r\n0\r\n\r\n"));
+    }
+
+    #[test]
+    fn close_delimited_bodies_are_sent_unframed_and_close_the_connection() {
+        let output = written(
+            Response::new(StatusCode::Ok)
+                .with_header("Transfer-Encoding", "chunked")
+                .with_reader(&b"hello"[..], None)
+                .close_delimited(),
+        );
+
+        assert!(output.contains("Connection: close\r\n"));
+        assert!(!output.contains("Transfer-Encoding"));
+        assert!(!output.contains("Content-Length"));
+        assert!(output.ends_with("\r\n\r\nhello"));
+    }
+
+    #[test]
+    fn bodies_of_known_length_send_exactly_content_length_bytes() {
+        let output =
+            written(Response::new(StatusCode::Ok).with_reader(&b"hello world"[..], Some(5)));
+
+        assert!(output.contains("Content-Length: 5\r\n"));
+        assert!(output.ends_with("\r\n\r\nhello"));
+    }
+
+    #[test]
+    fn bodies_shorter_than_their_content_length_are_an_error() {
+        let response = Response::new(StatusCode::Ok).with_reader(&b"hello"[..], Some(10));
+        let error = response.write_to(Vec::new()).unwrap_err();
+
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn close_delimited_leaves_bodies_of_known_length_alone() {
+        let output = written(
+            Response::new(StatusCode::Ok)
+                .with_bytes("hello")
+                .close_delimited(),
+        );
+
+        assert!(output.contains("Content-Length: 5\r\n"));
+        assert!(!output.contains("Connection"));
+        assert!(output.ends_with("\r\n\r\nhello"));
+    }
+}
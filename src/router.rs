@@ -0,0 +1,585 @@
+use crate::{
+    Method, Middleware, Next, Request, RequestError, Response, ServerConfig, StaticFiles,
+    StatusCode, error_response, remove_dot_segments,
+};
+
+use std::fmt::{Debug, Formatter};
+
+/// Something that turns a request into a response.
+///
+/// Implemented by [`StaticFiles`] and by every closure
+/// `Fn(&Request, &ServerConfig) -> Result<Response, RequestError>`, so simple endpoints don't
+/// need a type of their own:
+///
+/// ```no_run
+/// use rust_server::{Method, Response, Router, StatusCode};
+///
+/// let router = Router::new().route(Method::Get, "/users/:id", |request: &rust_server::Request, _: &_| {
+///     let id = request.param("id").unwrap_or_default();
+///     Ok(Response::new(StatusCode::Ok).with_bytes(format!("user {id}")))
+/// });
+/// ```
+///
+/// Handlers are shared by every worker thread, hence `Send + Sync`.
+pub trait Handler: Send + Sync {
+    /// Builds the response to `request`. Route parameters are available through [`Request::param`].
+    ///
+    /// # Returns
+    /// - `Ok(Response)`: The response to send.
+    /// - `Err(RequestError)`: If the request can't be answered. The client gets the error's status
+    ///   (`500 Internal Server Error` for [`RequestError::Io`]) with an error page, and the connection
+    ///   is closed.
+    fn handle(&self, request: &Request, config: &ServerConfig) -> Result<Response, RequestError>;
+}
+
+impl<F> Handler for F
+where
+    F: Fn(&Request, &ServerConfig) -> Result<Response, RequestError> + Send + Sync,
+{
+    fn handle(&self, request: &Request, config: &ServerConfig) -> Result<Response, RequestError> {
+        self(request, config)
+    }
+}
+
+/// One segment of a route [`Pattern`].
+///
+/// # Variants
+/// - `Literal`: Matches exactly this text.
+/// - `Param`: `:name`, matches any single non-empty segment and captures it as `name`.
+/// - `Wildcard`: `*name` (or just `*`), only allowed last. Matches the rest of the path, including
+///   nothing at all, and captures it as `name` (or `*`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Param(String),
+    Wildcard(String),
+}
+
+/// A route path pattern such as `/users/:id` or `/static/*path`.
+///
+/// Paths are compared segment by segment after splitting on `/`; empty segments are ignored, so
+/// `/users/42/` matches `/users/:id` just like `/users/42` does. Dot segments are resolved first
+/// (see [`remove_dot_segments`]), so `/x/../users/42` matches too and can't slip past a route.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pattern {
+    source: String,
+    segments: Vec<Segment>,
+}
+
+impl Pattern {
+    /// Parses a pattern.
+    ///
+    /// # Panics
+    /// If a wildcard isn't the last segment or a `:` parameter has no name. Routes are set up once
+    /// at startup, so a broken pattern is a programming error.
+    pub fn parse(pattern: &str) -> Self {
+        let parts: Vec<&str> = pattern.split('/').filter(|part| !part.is_empty()).collect();
+        let segments = parts
+            .iter()
+            .enumerate()
+            .map(|(index, part)| {
+                if let Some(name) = part.strip_prefix(':') {
+                    assert!(
+                        !name.is_empty(),
+                        "route `{pattern}`: parameter without a name"
+                    );
+                    Segment::Param(name.to_string())
+                } else if let Some(name) = part.strip_prefix('*') {
+                    assert!(
+                        index == parts.len() - 1,
+                        "route `{pattern}`: a wildcard must be the last segment"
+                    );
+                    let name = if name.is_empty() { "*" } else { name };
+                    Segment::Wildcard(name.to_string())
+                } else {
+                    Segment::Literal(part.to_string())
+                }
+            })
+            .collect();
+
+        Self {
+            source: pattern.to_string(),
+            segments,
+        }
+    }
+
+    /// The pattern as it was written.
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    /// Matches a decoded request path against the pattern, once its dot segments are resolved.
+    ///
+    /// # Returns
+    /// - `Some(params)`: The captured `(name, value)` pairs if the path matches.
+    /// - `None`: If it doesn't.
+    pub fn matches(&self, path: &str) -> Option<Vec<(String, String)>> {
+        let path = remove_dot_segments(path);
+        let parts: Vec<&str> = path.split('/').filter(|part| !part.is_empty()).collect();
+        let mut params = Vec::new();
+
+        for (index, segment) in self.segments.iter().enumerate() {
+            match segment {
+                Segment::Literal(literal) => {
+                    if parts.get(index) != Some(&literal.as_str()) {
+                        return None;
+                    }
+                }
+                Segment::Param(name) => {
+                    params.push((name.clone(), parts.get(index)?.to_string()));
+                }
+                Segment::Wildcard(name) => {
+                    let rest = parts.get(index..).unwrap_or_default().join("/");
+                    params.push((name.clone(), rest));
+                    return Some(params);
+                }
+            }
+        }
+
+        (parts.len() == self.segments.len()).then_some(params)
+    }
+}
+
+/// A registered route: which methods and paths it answers and the handler that does it.
+struct Route {
+    /// `None` answers every method.
+    methods: Option<Vec<Method>>,
+    pattern: Pattern,
+    handler: Box<dyn Handler>,
+    /// Runs around `handler` only, see [`Router::layer`].
+    middleware: Vec<Box<dyn Middleware>>,
+}
+
+impl Route {
+    /// Returns `true` if the route handles `method`. A `GET` route also answers `HEAD`; the body is
+    /// dropped when the response is written.
+    fn answers(&self, method: Method) -> bool {
+        match &self.methods {
+            None => true,
+            Some(methods) => {
+                methods.contains(&method)
+                    || (method == Method::Head && methods.contains(&Method::Get))
+            }
+        }
+    }
+}
+
+/// Dispatches requests to [`Handler`]s by method and path.
+///
+/// Routes are tried in the order they were added; the first one whose [`Pattern`] matches the
+/// path and whose method matches the request handles it. `GET` routes answer `HEAD` too.
+/// When no route matches:
+/// - If some route matches the path but not the method, `OPTIONS` gets `200 OK` and anything else
+///   `405 Method Not Allowed`, both with an `Allow` header listing the methods that would match.
+/// - Otherwise the response is `404 Not Found` with the 404 error page.
+///
+/// Middleware added with [`Router::with_middleware`] runs for every request the router sees,
+/// before the route is picked (so it may rewrite the path) and around the `404`/`405` fallbacks.
+/// Middleware added with [`Router::layer`] only runs around one route's handler.
+///
+/// [`Router::default`] mounts [`StaticFiles`] at `/`, which is what a [`crate::Server`] uses
+/// unless it is given another router.
+///
+/// ```no_run
+/// use rust_server::{Method, Response, Router, StaticFiles, StatusCode};
+///
+/// let router = Router::new()
+///     .route(Method::Get, "/api/health", |_: &rust_server::Request, _: &_| {
+///         Ok(Response::new(StatusCode::Ok).with_bytes("ok"))
+///     })
+///     .mount("/", StaticFiles::new());
+/// ```
+pub struct Router {
+    routes: Vec<Route>,
+    middleware: Vec<Box<dyn Middleware>>,
+}
+
+impl Router {
+    /// Creates a router without routes. Every request gets `404 Not Found`.
+    pub fn new() -> Self {
+        Self {
+            routes: Vec::new(),
+            middleware: Vec::new(),
+        }
+    }
+
+    /// Adds a route answering `method` on paths matching `pattern` (see [`Pattern::parse`]).
+    pub fn route(self, method: Method, pattern: &str, handler: impl Handler + 'static) -> Self {
+        self.add(Some(vec![method]), pattern, handler)
+    }
+
+    /// Adds a route answering every method on paths matching `pattern`.
+    pub fn any(self, pattern: &str, handler: impl Handler + 'static) -> Self {
+        self.add(None, pattern, handler)
+    }
+
+    /// Shorthand for [`Self::route`] with [`Method::Get`].
+    pub fn get(self, pattern: &str, handler: impl Handler + 'static) -> Self {
+        self.route(Method::Get, pattern, handler)
+    }
+
+    /// Shorthand for [`Self::route`] with [`Method::Post`].
+    pub fn post(self, pattern: &str, handler: impl Handler + 'static) -> Self {
+        self.route(Method::Post, pattern, handler)
+    }
+
+    /// Mounts `handler` for every method on `prefix` and everything below it.
+    ///
+    /// The part of the path after `prefix` is available as the `path` parameter, which is how
+    /// [`StaticFiles`] finds the file to serve. `mount("/", ...)` catches every path.
+    pub fn mount(self, prefix: &str, handler: impl Handler + 'static) -> Self {
+        let pattern = format!("{}/*path", prefix.trim_end_matches('/'));
+        self.add(None, &pattern, handler)
+    }
+
+    fn add(
+        mut self,
+        methods: Option<Vec<Method>>,
+        pattern: &str,
+        handler: impl Handler + 'static,
+    ) -> Self {
+        self.routes.push(Route {
+            methods,
+            pattern: Pattern::parse(pattern),
+            handler: Box::new(handler),
+            middleware: Vec::new(),
+        });
+        self
+    }
+
+    /// Adds middleware that runs for every request the router handles, after the middleware
+    /// added before it.
+    pub fn with_middleware(mut self, middleware: impl Middleware + 'static) -> Self {
+        self.middleware.push(Box::new(middleware));
+        self
+    }
+
+    /// Adds middleware that only runs around the handler of the route added last:
+    ///
+    /// ```no_run
+    /// # use rust_server::{Next, Request, Response, Router, ServerConfig, StaticFiles, StatusCode};
+    /// # let require_token = |request: &mut Request, config: &ServerConfig, next: Next| next.run(request, config);
+    /// let router = Router::new()
+    ///     .mount("/admin", StaticFiles::new().with_root("admin"))
+    ///     .layer(require_token)
+    ///     .mount("/", StaticFiles::new());
+    /// ```
+    ///
+    /// Routes match the path with its dot segments resolved (see [`Pattern`]), so
+    /// `/x/../admin/page.html` still goes through `require_token`.
+    ///
+    /// # Panics
+    /// If no route has been added yet.
+    pub fn layer(mut self, middleware: impl Middleware + 'static) -> Self {
+        let route = self
+            .routes
+            .last_mut()
+            .expect("Router::layer called before any route was added");
+        route.middleware.push(Box::new(middleware));
+        self
+    }
+
+    /// Puts `middleware` in front of the router's own middleware, for [`crate::Server::with_middleware`].
+    pub(crate) fn wrap(mut self, middleware: Vec<Box<dyn Middleware>>) -> Self {
+        self.middleware.splice(0..0, middleware);
+        self
+    }
+
+    /// Runs the router's middleware, then finds the route for `request`, stores its parameters on
+    /// the request (see [`Request::param`]) and runs its handler with the route's middleware.
+    /// See [`Router`] for what happens without a match.
+    ///
+    /// # Returns
+    /// - `Ok(Response)`: The response, or the `404`/`405`/`OPTIONS` fallback.
+    /// - `Err(RequestError)`: If a handler or middleware fails.
+    pub fn handle(
+        &self,
+        request: &mut Request,
+        config: &ServerConfig,
+    ) -> Result<Response, RequestError> {
+        Next::new(&self.middleware, &|request, config| {
+            self.dispatch(request, config)
+        })
+        .run(request, config)
+    }
+
+    /// This function is a private helper function for [`Router::handle`].
+    /// - It picks the route for `request` and runs it, with the route's own middleware.
+    /// - Without a matching route it builds the `404`/`405`/`OPTIONS` response.
+    fn dispatch(
+        &self,
+        request: &mut Request,
+        config: &ServerConfig,
+    ) -> Result<Response, RequestError> {
+        let method = request.method();
+        let mut allowed: Vec<Method> = Vec::new();
+
+        for route in &self.routes {
+            let Some(params) = route.pattern.matches(request.path()) else {
+                continue;
+            };
+            if route.answers(method) {
+                request.set_params(params);
+                return Next::new(&route.middleware, &|request, config| {
+                    route.handler.handle(request, config)
+                })
+                .run(request, config);
+            }
+            for method in route.methods.iter().flatten() {
+                if !allowed.contains(method) {
+                    allowed.push(*method);
+                }
+            }
+        }
+
+        if allowed.is_empty() {
+            return Ok(error_response(StatusCode::NotFound, config));
+        }
+
+        if allowed.contains(&Method::Get) && !allowed.contains(&Method::Head) {
+            allowed.push(Method::Head);
+        }
+        if !allowed.contains(&Method::Options) {
+            allowed.push(Method::Options);
+        }
+        let allow = allowed
+            .iter()
+            .map(Method::as_str)
+            .collect::<Vec<_>>()
+            .join(", ");
+        let status = if method == Method::Options {
+            StatusCode::Ok
+        } else {
+            StatusCode::MethodNotAllowed
+        };
+        Ok(Response::new(status).with_header("Allow", allow))
+    }
+}
+
+impl Default for Router {
+    /// Serves [`ServerConfig::document_root`] on every path, the behavior of a plain [`crate::Server`].
+    fn default() -> Self {
+        Router::new().mount("/", StaticFiles::new())
+    }
+}
+
+impl Debug for Router {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_list()
+            .entries(self.routes.iter().map(|route| {
+                let methods = match &route.methods {
+                    Some(methods) => methods
+                        .iter()
+                        .map(Method::as_str)
+                        .collect::<Vec<_>>()
+                        .join("|"),
+                    None => "*".to_string(),
+                };
+                format!("{methods} {}", route.pattern.as_str())
+            }))
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Body;
+
+    use std::io::BufReader;
+
+    fn params(pattern: &str, path: &str) -> Option<Vec<(String, String)>> {
+        Pattern::parse(pattern).matches(path)
+    }
+
+    fn pairs(pairs: &[(&str, &str)]) -> Option<Vec<(String, String)>> {
+        Some(
+            pairs
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+        )
+    }
+
+    /// A handler answering with `name`, to see which route was picked.
+    fn answer(name: &'static str) -> impl Handler {
+        move |request: &Request, _: &ServerConfig| {
+            let params = request
+                .params()
+                .iter()
+                .map(|(name, value)| format!("{name}={value}"))
+                .collect::<Vec<_>>()
+                .join("&");
+            Ok(Response::new(StatusCode::Ok).with_bytes(format!("{name} {params}")))
+        }
+    }
+
+    /// Sends `method path` through `router`.
+    ///
+    /// # Returns
+    /// The status, the `Allow` header and the body.
+    fn send(router: &Router, method: &str, path: &str) -> (StatusCode, Option<String>, String) {
+        let raw = format!("{method} {path} HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        let mut request = Request::new(&mut BufReader::new(raw.as_bytes())).unwrap();
+        let response = router
+            .handle(&mut request, &ServerConfig::default())
+            .unwrap();
+        let allow = response.headers().get("Allow").map(str::to_string);
+        let body = match response.body() {
+            Body::Bytes(bytes) => String::from_utf8_lossy(bytes).into_owned(),
+            _ => String::new(),
+        };
+        (response.status(), allow, body)
+    }
+
+    #[test]
+    fn literal_patterns_match_whole_paths_only() {
+        assert_eq!(params("/users", "/users"), pairs(&[]));
+        assert_eq!(params("/users", "/users/"), pairs(&[]));
+        assert_eq!(params("/users", "//users//"), pairs(&[]));
+        assert_eq!(params("/", "/"), pairs(&[]));
+        assert_eq!(params("/users", "/users/42"), None);
+        assert_eq!(params("/users", "/user"), None);
+        assert_eq!(params("/users", "/Users"), None);
+        assert_eq!(params("/users/list", "/users"), None);
+    }
+
+    #[test]
+    fn parameters_capture_one_segment() {
+        assert_eq!(params("/users/:id", "/users/42"), pairs(&[("id", "42")]));
+        assert_eq!(params("/users/:id", "/users/42/"), pairs(&[("id", "42")]));
+        assert_eq!(
+            params("/users/:user/posts/:post", "/users/7/posts/hello"),
+            pairs(&[("user", "7"), ("post", "hello")])
+        );
+        assert_eq!(params("/users/:id", "/users"), None);
+        assert_eq!(params("/users/:id", "/users/"), None);
+        assert_eq!(params("/users/:id", "/users/42/posts"), None);
+    }
+
+    #[test]
+    fn wildcards_capture_the_rest_of_the_path() {
+        assert_eq!(
+            params("/static/*path", "/static/css/site.css"),
+            pairs(&[("path", "css/site.css")])
+        );
+        assert_eq!(params("/static/*path", "/static"), pairs(&[("path", "")]));
+        assert_eq!(params("/static/*path", "/static/"), pairs(&[("path", "")]));
+        assert_eq!(params("/*", "/a/b"), pairs(&[("*", "a/b")]));
+        assert_eq!(
+            params("/:user/*rest", "/ann/a//b/"),
+            pairs(&[("user", "ann"), ("rest", "a/b")])
+        );
+        assert_eq!(params("/static/*path", "/other/file"), None);
+    }
+
+    #[test]
+    fn dot_segments_are_resolved_before_matching() {
+        assert_eq!(
+            params("/private/*path", "/x/../private/secret"),
+            pairs(&[("path", "secret")])
+        );
+        assert_eq!(params("/users/:id", "/./users/42"), pairs(&[("id", "42")]));
+        assert_eq!(params("/users/:id", "/users/42/.."), None);
+        assert_eq!(params("/x/*path", "/x/../private/secret"), None);
+    }
+
+    #[test]
+    fn dotted_paths_cannot_skip_a_layered_route() {
+        let deny =
+            |_: &mut Request, _: &ServerConfig, _: Next| Ok(Response::new(StatusCode::Forbidden));
+        let router = Router::new()
+            .get("/private/*path", answer("private"))
+            .layer(deny)
+            .mount("/", answer("fallback"));
+
+        for path in [
+            "/private/secret",
+            "/x/../private/secret",
+            "/./private/secret",
+        ] {
+            assert_eq!(
+                send(&router, "GET", path).0,
+                StatusCode::Forbidden,
+                "{path}"
+            );
+        }
+        assert_eq!(send(&router, "GET", "/public").2, "fallback path=public");
+    }
+
+    #[test]
+    #[should_panic(expected = "a wildcard must be the last segment")]
+    fn wildcards_must_come_last() {
+        Pattern::parse("/*path/more");
+    }
+
+    #[test]
+    #[should_panic(expected = "parameter without a name")]
+    fn parameters_need_a_name() {
+        Pattern::parse("/users/:");
+    }
+
+    #[test]
+    fn the_first_matching_route_wins() {
+        let router = Router::new()
+            .get("/users/me", answer("me"))
+            .get("/users/:id", answer("user"))
+            .mount("/", answer("fallback"));
+
+        assert_eq!(send(&router, "GET", "/users/me").2, "me ");
+        assert_eq!(send(&router, "GET", "/users/42").2, "user id=42");
+        assert_eq!(
+            send(&router, "GET", "/users/42/x").2,
+            "fallback path=users/42/x"
+        );
+        assert_eq!(
+            send(&router, "POST", "/users/me").2,
+            "fallback path=users/me"
+        );
+
+        /* Added in the other order, the parameter route shadows the literal one. */
+        let shadowed = Router::new()
+            .get("/users/:id", answer("user"))
+            .get("/users/me", answer("me"));
+        assert_eq!(send(&shadowed, "GET", "/users/me").2, "user id=me");
+    }
+
+    #[test]
+    fn routes_for_other_methods_are_skipped_for_later_matches() {
+        let router = Router::new()
+            .post("/items", answer("create"))
+            .get("/items", answer("list"));
+        assert_eq!(send(&router, "GET", "/items").2, "list ");
+        assert_eq!(send(&router, "POST", "/items").2, "create ");
+        /* GET routes answer HEAD. */
+        assert_eq!(send(&router, "HEAD", "/items").2, "list ");
+    }
+
+    #[test]
+    fn a_path_match_with_the_wrong_method_is_405_with_allow() {
+        let router = Router::new().get("/items/:id", answer("show")).route(
+            Method::Delete,
+            "/items/:id",
+            answer("delete"),
+        );
+
+        let (status, allow, _) = send(&router, "PUT", "/items/1");
+        assert_eq!(status, StatusCode::MethodNotAllowed);
+        assert_eq!(allow.as_deref(), Some("GET, DELETE, HEAD, OPTIONS"));
+
+        let (status, allow, _) = send(&router, "OPTIONS", "/items/1");
+        assert_eq!(status, StatusCode::Ok);
+        assert_eq!(allow.as_deref(), Some("GET, DELETE, HEAD, OPTIONS"));
+    }
+
+    #[test]
+    fn no_path_match_is_404() {
+        let router = Router::new().get("/items/:id", answer("show"));
+        for (method, path) in [("GET", "/items"), ("PUT", "/other"), ("OPTIONS", "/")] {
+            let (status, allow, _) = send(&router, method, path);
+            assert_eq!(status, StatusCode::NotFound, "{method} {path}");
+            assert_eq!(allow, None);
+        }
+        assert_eq!(send(&Router::new(), "GET", "/").0, StatusCode::NotFound);
+    }
+}
@@ -0,0 +1,462 @@
+use crate::{
+    AccessLog, ConnectionPermit, Health, Metrics, MetricsEndpoint, Middleware, MimeTypes,
+    PoolError, QueuePolicy, RateLimiter, ReadinessCheck, Rejected, Router,
+    SIDE_LISTENER_QUEUE_CAPACITY, SIDE_LISTENER_WORKERS, ServerConfig, ShutdownHandle, StatusCode,
+    ThreadPool, handle_connection, reject_connection,
+};
+#[cfg(feature = "tls")]
+use crate::{CertificateStore, HttpsRedirect, TlsError, tls};
+use std::io;
+#[cfg(feature = "tls")]
+use std::io::Write;
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// How often the accept loop checks whether draining is done once a shutdown is requested.
+static DRAIN_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Errors that can stop a [`Server`] from starting.
+///
+/// # Variants
+/// - `Io`: Binding the listener, reading its address or opening the access log failed.
+/// - `Pool`: The worker [`ThreadPool`] could not be built.
+/// - `Signal`: The `SIGINT`/`SIGTERM` handler could not be installed.
+/// - `Tls`: The certificates could not be loaded or the TLS configuration is unusable
+///   (only with the `tls` feature).
+#[derive(Debug, thiserror::Error)]
+pub enum ServerError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Pool(#[from] PoolError),
+    #[error(transparent)]
+    Signal(#[from] ctrlc::Error),
+    #[cfg(feature = "tls")]
+    #[error(transparent)]
+    Tls(#[from] TlsError),
+}
+
+/// A listening HTTP server: the accept loop plus the [`ThreadPool`] that handles connections.
+///
+/// Everything about how connections are handled comes from a [`ServerConfig`]; what each request
+/// gets back is decided by a [`Router`], by default one serving static files (see [`Router::default`]).
+/// The worker threads are only started by [`Server::run`], so the `with_*` methods can still
+/// change the configuration after the listener is bound.
+/// [`Server::run`] blocks until a shutdown is requested through a [`ShutdownHandle`].
+/// It then drains: new connections are still served one request each (readiness probes answer
+/// `503`) until the in-flight ones are done or the drain timeout passes, and returns.
+pub struct Server {
+    listener: TcpListener,
+    shutdown: ShutdownHandle,
+    config: ServerConfig,
+    router: Router,
+    middleware: Vec<Box<dyn Middleware>>,
+    metrics: Arc<Metrics>,
+    readiness_checks: Vec<(String, ReadinessCheck)>,
+    #[cfg(feature = "tls")]
+    certificates: Option<Arc<CertificateStore>>,
+}
+
+impl Server {
+    /// Binds a listener to `address` and uses the default [`ServerConfig`] for everything else.
+    ///
+    /// # Returns
+    /// - `Ok(Server)`: If the listener is bound.
+    /// - `Err(ServerError::Io(_))`: If binding fails.
+    pub fn bind(address: impl ToSocketAddrs) -> Result<Self, ServerError> {
+        let listener = TcpListener::bind(address)?;
+        Self::with_listener(listener, ServerConfig::default())
+    }
+
+    /// Binds a listener to `config.address` and serves with `config`.
+    ///
+    /// The configuration is used as is; see [`ServerConfig::validate`]. With `tls.enabled`, the
+    /// certificates are loaded here.
+    ///
+    /// # Returns
+    /// - `Ok(Server)`: If the listener is bound.
+    /// - `Err(ServerError::Io(_))`: If binding fails, or TLS is enabled without the `tls` feature.
+    /// - `Err(ServerError::Tls(_))`: If the certificates could not be loaded.
+    pub fn from_config(config: ServerConfig) -> Result<Self, ServerError> {
+        let listener = TcpListener::bind(&config.address)?;
+        Self::with_listener(listener, config)
+    }
+
+    fn with_listener(listener: TcpListener, config: ServerConfig) -> Result<Self, ServerError> {
+        let shutdown = ShutdownHandle::new(listener.local_addr()?);
+        #[cfg(feature = "tls")]
+        let certificates = if config.tls.enabled {
+            Some(Arc::new(CertificateStore::from_config(&config.tls)?))
+        } else {
+            None
+        };
+        #[cfg(not(feature = "tls"))]
+        if config.tls.enabled {
+            return Err(ServerError::Io(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "TLS is enabled but the server was built without the `tls` feature",
+            )));
+        }
+
+        Ok(Self {
+            listener,
+            shutdown,
+            config,
+            router: Router::default(),
+            middleware: Vec::new(),
+            metrics: Arc::new(Metrics::new()),
+            readiness_checks: Vec::new(),
+            #[cfg(feature = "tls")]
+            certificates,
+        })
+    }
+
+    /// Replaces the default static file [`Router`].
+    pub fn with_router(mut self, router: Router) -> Self {
+        self.router = router;
+        self
+    }
+
+    /// Adds [`Middleware`] that runs for every request, in the order added and before the router's
+    /// own middleware. It is kept when the router is replaced with [`Self::with_router`].
+    pub fn with_middleware(mut self, middleware: impl Middleware + 'static) -> Self {
+        self.middleware.push(Box::new(middleware));
+        self
+    }
+
+    /// Sets how long [`Self::run`] waits for in-flight connections once a shutdown is requested.
+    pub fn with_drain_timeout(mut self, drain_timeout: Duration) -> Self {
+        self.config.timeouts.shutdown = drain_timeout;
+        self
+    }
+
+    /// Sets the extension to MIME type table used for the `Content-Type` of static files.
+    pub fn with_mime_types(mut self, mime_types: MimeTypes) -> Self {
+        self.config.mime_types = mime_types;
+        self
+    }
+
+    /// The configuration the server runs with.
+    pub fn config(&self) -> &ServerConfig {
+        &self.config
+    }
+
+    /// The address the listener is actually bound to (useful when binding to port `0`).
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Adds a check to the readiness probe (see [`Health`]), reported under `name`. The server is
+    /// ready only while every check returns `Ok(())`.
+    pub fn with_readiness_check(
+        mut self,
+        name: impl Into<String>,
+        check: impl Fn() -> Result<(), String> + Send + Sync + 'static,
+    ) -> Self {
+        self.readiness_checks.push((name.into(), Box::new(check)));
+        self
+    }
+
+    /// The server's metrics registry. It is only updated while `metrics.enabled` is set.
+    pub fn metrics(&self) -> Arc<Metrics> {
+        Arc::clone(&self.metrics)
+    }
+
+    /// The certificates served when `tls.enabled` is set, for reloading them by hand with
+    /// [`CertificateStore::reload`].
+    #[cfg(feature = "tls")]
+    pub fn certificates(&self) -> Option<Arc<CertificateStore>> {
+        self.certificates.clone()
+    }
+
+    /// Returns a handle that can stop this server from another thread or a signal handler.
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown.clone()
+    }
+
+    /// Starts the worker threads and accepts connections until a shutdown is requested, then drains the workers.
+    ///
+    /// Connections that arrive while the queue is full get `503 Service Unavailable`
+    /// (under [`crate::QueuePolicy::Reject`]); with TLS they are closed instead.
+    /// The same goes, with `429 Too Many Requests`, for connections over the per-IP cap.
+    ///
+    /// Failures on individual connections are logged and never end the loop.
+    ///
+    /// With `health.enabled`, the liveness and readiness probes are answered (see [`Health`]);
+    /// readiness fails with `503` from the moment a shutdown is requested until the drain ends.
+    /// With `metrics.enabled`, the metrics are served on `metrics.path`, on the main listener or on
+    /// an admin listener at `metrics.address`.
+    /// With `tls.enabled`, every connection is served over TLS, the certificates are reloaded when
+    /// their files change (every `tls.reload_interval`) and `tls.redirect_address` redirects plain
+    /// HTTP to HTTPS (see [`HttpsRedirect`]).
+    /// With `rate_limit.enabled`, requests and connections are limited per client IP (see
+    /// [`RateLimiter`]); the probes and metrics are exempt.
+    ///
+    /// # Returns
+    /// - `Ok(())`: Once the listener is closed and the workers have drained or the drain timeout passed.
+    /// - `Err(ServerError::Pool(_))`: If the worker threads could not be started.
+    /// - `Err(ServerError::Io(_))`: If the access log file could not be opened or the metrics admin
+    ///   or redirect listener could not be bound.
+    /// - `Err(ServerError::Tls(_))`: If the TLS configuration could not be built.
+    pub fn run(self) -> Result<(), ServerError> {
+        let Self {
+            listener,
+            shutdown,
+            config,
+            router,
+            mut middleware,
+            metrics,
+            readiness_checks,
+            #[cfg(feature = "tls")]
+            certificates,
+        } = self;
+
+        let rate_limiter = config
+            .rate_limit
+            .enabled
+            .then(|| RateLimiter::new(&config.rate_limit));
+        if let Some(rate_limiter) = &rate_limiter {
+            middleware.insert(0, Box::new(rate_limiter.clone()));
+        }
+        /* The probes and metrics answer before any other middleware or route. */
+        if config.health.enabled {
+            let health = Health::new(&config, shutdown.clone()).with_checks(readiness_checks);
+            middleware.insert(0, Box::new(health));
+        }
+        let mut side_listeners = Vec::new();
+        if config.metrics.enabled {
+            let endpoint = MetricsEndpoint::new(Arc::clone(&metrics), &config.metrics.path);
+            match &config.metrics.address {
+                Some(address) => {
+                    let admin = Router::new().get(&config.metrics.path, endpoint);
+                    side_listeners.push(spawn_side_listener("admin", address, admin, &config)?);
+                }
+                None => middleware.insert(0, Box::new(endpoint)),
+            }
+        }
+        let metrics = config.metrics.enabled.then_some(metrics);
+
+        #[cfg(feature = "tls")]
+        let mut watcher = None;
+        #[cfg(feature = "tls")]
+        let tls_config = match &certificates {
+            Some(certificates) => {
+                if let Some(address) = &config.tls.redirect_address {
+                    let redirect = HttpsRedirect {
+                        port: listener.local_addr()?.port(),
+                    };
+                    let redirect = Router::new().any("/*", redirect);
+                    side_listeners
+                        .push(spawn_side_listener("redirect", address, redirect, &config)?);
+                }
+                if !config.tls.reload_interval.is_zero() {
+                    watcher = Some(tls::watch(
+                        Arc::clone(certificates),
+                        config.tls.reload_interval,
+                        shutdown.clone(),
+                    )?);
+                }
+                Some(certificates.server_config()?)
+            }
+            None => None,
+        };
+        #[cfg(feature = "tls")]
+        let plain_text = tls_config.is_none();
+        #[cfg(not(feature = "tls"))]
+        let plain_text = true;
+
+        let router = router.wrap(middleware);
+        let access_log = if config.logging.requests {
+            Some(AccessLog::from_config(&config.logging)?)
+        } else {
+            None
+        };
+        let worker_shutdown = shutdown.clone();
+        let config = Arc::new(config);
+        let worker_config = Arc::clone(&config);
+        let worker_metrics = metrics.clone();
+        let pool = ThreadPool::build(
+            config.workers,
+            config.queue_capacity,
+            config.queue_policy,
+            move |(mut connection, _permit): (TcpStream, Option<ConnectionPermit>)| {
+                if let Some(metrics) = &worker_metrics {
+                    metrics.connection_dequeued();
+                }
+                #[cfg(feature = "tls")]
+                if let Some(tls_config) = &tls_config {
+                    let mut stream = match tls::accept(tls_config, connection) {
+                        Ok(stream) => stream,
+                        Err(error) => {
+                            eprintln!("Failed to start TLS: {error}");
+                            return;
+                        }
+                    };
+                    if let Err(error) = handle_connection(
+                        &mut stream,
+                        &router,
+                        &worker_config,
+                        &worker_shutdown,
+                        access_log.as_ref(),
+                        worker_metrics.as_deref(),
+                    ) {
+                        eprintln!("Failed to handle connection: {error}");
+                    }
+                    stream.conn.send_close_notify();
+                    let _ = stream.flush();
+                    return;
+                }
+                if let Err(error) = handle_connection(
+                    &mut connection,
+                    &router,
+                    &worker_config,
+                    &worker_shutdown,
+                    access_log.as_ref(),
+                    worker_metrics.as_deref(),
+                ) {
+                    eprintln!("Failed to handle connection: {error}");
+                }
+            },
+        )?;
+
+        let mut draining = false;
+        let mut drain_deadline = None;
+        loop {
+            /* While draining, new clients are still served, one request each, so readiness probes
+            get their 503 instead of a refused connection. */
+            if shutdown.is_shutting_down() && !draining {
+                draining = true;
+                listener.set_nonblocking(true)?;
+                /* A timeout too long to add to the clock never runs out. */
+                drain_deadline = Instant::now().checked_add(config.timeouts.shutdown);
+            }
+            if draining
+                && (pool.is_idle()
+                    || drain_deadline.is_some_and(|deadline| Instant::now() >= deadline))
+            {
+                break;
+            }
+            let mut connection = match listener.accept() {
+                Ok((connection, _)) => connection,
+                Err(error) if error.kind() == io::ErrorKind::WouldBlock => {
+                    thread::sleep(DRAIN_POLL_INTERVAL);
+                    continue;
+                }
+                Err(error) => {
+                    eprintln!("Failed to accept connection: {error}");
+                    continue;
+                }
+            };
+            if draining && connection.set_nonblocking(false).is_err() {
+                continue;
+            }
+            /* A TLS client can't read a plain text refusal before the handshake; it is just closed. */
+            let mut permit = None;
+            if let Some(rate_limiter) = &rate_limiter
+                && let Ok(peer) = connection.peer_addr()
+            {
+                permit = rate_limiter.open_connection(peer.ip());
+                if permit.is_none() {
+                    if plain_text {
+                        let _ = reject_connection(&mut connection, StatusCode::TooManyRequests);
+                    }
+                    continue;
+                }
+            }
+            if let Some(metrics) = &metrics {
+                metrics.connection_queued();
+            }
+            if let Err(rejected) = pool.execute((connection, permit)) {
+                if let Some(metrics) = &metrics {
+                    metrics.connection_dequeued();
+                }
+                if let Rejected::QueueFull((mut connection, _)) = rejected
+                    && plain_text
+                {
+                    let _ = reject_connection(&mut connection, StatusCode::ServiceUnavailable);
+                }
+            }
+        }
+
+        /* Stop accepting before waiting on the workers. */
+        drop(listener);
+        for (side_shutdown, side_thread) in side_listeners {
+            side_shutdown.shutdown();
+            let _ = side_thread.join();
+        }
+        #[cfg(feature = "tls")]
+        if let Some(watcher) = watcher {
+            let _ = watcher.join();
+        }
+        let remaining = drain_deadline.map_or(config.timeouts.shutdown, |deadline| {
+            deadline.saturating_duration_since(Instant::now())
+        });
+        if !pool.shutdown(remaining) {
+            eprintln!("Shutdown deadline passed with connections still in flight.");
+        }
+
+        Ok(())
+    }
+}
+
+/// This function is a private helper function for [`Server::run`].
+/// - It binds a listener at `address` and serves `router` on a small pool of its own, one request
+///   per connection, so one slow client can't hold up the others. Used for the metrics admin
+///   listener and the HTTPS redirect.
+/// - The accept loop runs on a thread called `name`; the returned [`ShutdownHandle`] stops it,
+///   and joining it waits for the pool to drain.
+fn spawn_side_listener(
+    name: &str,
+    address: &str,
+    router: Router,
+    config: &ServerConfig,
+) -> Result<(ShutdownHandle, JoinHandle<()>), ServerError> {
+    let listener = TcpListener::bind(address)?;
+    let shutdown = ShutdownHandle::new(listener.local_addr()?);
+    let mut config = config.clone();
+    config.limits.max_keep_alive_requests = 1;
+    let drain_timeout = config.timeouts.shutdown;
+
+    let worker_shutdown = shutdown.clone();
+    let label = name.to_string();
+    let pool = ThreadPool::build(
+        SIDE_LISTENER_WORKERS,
+        SIDE_LISTENER_QUEUE_CAPACITY,
+        QueuePolicy::Reject,
+        move |mut connection: TcpStream| {
+            if let Err(error) = handle_connection(
+                &mut connection,
+                &router,
+                &config,
+                &worker_shutdown,
+                None,
+                None,
+            ) {
+                eprintln!("Failed to handle {label} connection: {error}");
+            }
+        },
+    )?;
+
+    let side_shutdown = shutdown.clone();
+    let thread = thread::Builder::new()
+        .name(name.to_string())
+        .spawn(move || {
+            for connection_attempt in listener.incoming() {
+                if shutdown.is_shutting_down() {
+                    break;
+                }
+                let Ok(connection) = connection_attempt else {
+                    continue;
+                };
+                if let Err(Rejected::QueueFull(mut connection)) = pool.execute(connection) {
+                    let _ = reject_connection(&mut connection, StatusCode::ServiceUnavailable);
+                }
+            }
+            drop(listener);
+            pool.shutdown(drain_timeout);
+        })?;
+
+    Ok((side_shutdown, thread))
+}
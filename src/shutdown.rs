@@ -0,0 +1,65 @@
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+/// A cloneable handle that asks a running [`crate::Server`] to stop.
+///
+/// Calling [`ShutdownHandle::shutdown`] makes the server fail its readiness probe, drain the
+/// connections in flight (still answering new ones, one request each) and return from
+/// [`crate::Server::run`].
+/// The handle can be moved to any thread, and [`ShutdownHandle::shutdown_on_signals`]
+/// wires it up to `SIGINT`/`SIGTERM`.
+#[derive(Debug, Clone)]
+pub struct ShutdownHandle {
+    requested: Arc<AtomicBool>,
+    address: SocketAddr,
+}
+
+impl ShutdownHandle {
+    /// Creates a handle for a server whose listener is bound to `address`.
+    pub(crate) fn new(address: SocketAddr) -> Self {
+        Self {
+            requested: Arc::new(AtomicBool::new(false)),
+            address,
+        }
+    }
+
+    /// Requests a graceful shutdown. Calling it more than once has no further effect.
+    ///
+    /// The accept loop is blocked inside `accept()`, so the handle opens (and immediately drops)
+    /// a connection to the listener to wake it up.
+    pub fn shutdown(&self) {
+        if self.requested.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        /* A listener bound to 0.0.0.0 or [::] is reachable through loopback. */
+        let mut wake_address = self.address;
+        if wake_address.ip().is_unspecified() {
+            match wake_address {
+                SocketAddr::V4(_) => wake_address.set_ip(Ipv4Addr::LOCALHOST.into()),
+                SocketAddr::V6(_) => wake_address.set_ip(Ipv6Addr::LOCALHOST.into()),
+            }
+        }
+        let _ = TcpStream::connect_timeout(&wake_address, Duration::from_secs(1));
+    }
+
+    /// Returns `true` once a shutdown has been requested.
+    pub fn is_shutting_down(&self) -> bool {
+        self.requested.load(Ordering::SeqCst)
+    }
+
+    /// Calls [`Self::shutdown`] when the process receives `SIGINT` or `SIGTERM`.
+    ///
+    /// # Returns
+    /// - `Ok(())`: If the signal handler was installed.
+    /// - `Err(ctrlc::Error)`: If a handler is already installed for this process.
+    pub fn shutdown_on_signals(&self) -> Result<(), ctrlc::Error> {
+        let handle = self.clone();
+        ctrlc::set_handler(move || {
+            println!("Shutdown signal received, draining connections.");
+            handle.shutdown();
+        })
+    }
+}
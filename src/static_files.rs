@@ -0,0 +1,339 @@
+use crate::{
+    Handler, Method, RangeRequest, Request, RequestError, Response, ServerConfig, StatusCode,
+    Validators, add_vary_accept_encoding, compress_response, error_response, listing_response,
+    negotiate_encoding, not_modified, parse_range, partial_response, precompressed_variants,
+    resolve_directory, resolve_file,
+};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+/// The methods the static file handler answers. Anything else gets `405 Method Not Allowed`.
+pub static ALLOWED_METHODS: &[Method] = &[Method::Get, Method::Head, Method::Options];
+
+/// A [`Handler`] that serves the files of a directory.
+///
+/// Mounted with [`crate::Router::mount`], the part of the path matched by the mount's wildcard is
+/// resolved under the directory, so `router.mount("/assets", StaticFiles::new().with_root("public"))`
+/// serves `/assets/app.js` from `public/app.js`. Used with any other pattern, the whole request
+/// path is resolved.
+///
+/// Without [`StaticFiles::with_root`] the files come from [`ServerConfig::document_root`].
+#[derive(Debug, Clone, Default)]
+pub struct StaticFiles {
+    root: Option<PathBuf>,
+}
+
+impl StaticFiles {
+    /// Creates a handler serving [`ServerConfig::document_root`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Serves the files under `root` instead of the configured document root.
+    pub fn with_root(mut self, root: impl Into<PathBuf>) -> Self {
+        self.root = Some(root.into());
+        self
+    }
+
+    /// The directory files are served from, given the server configuration.
+    pub fn root<'a>(&'a self, config: &'a ServerConfig) -> &'a Path {
+        self.root.as_deref().unwrap_or(&config.document_root)
+    }
+}
+
+impl Handler for StaticFiles {
+    /// Builds the response to a single parsed request.
+    ///
+    /// If the requested file exists under the root, a `200 OK` response is built with the
+    /// file contents and a `Content-Type` looked up in the configured MIME types. A directory without
+    /// an `index.html` is listed if [`ServerConfig::listing_allowed`] says so (see [`listing_response`]).
+    /// Otherwise, a `404 Not Found` response is built with the 404 error page (see [`crate::error_page`]).
+    ///
+    /// File responses carry `ETag` and `Last-Modified` validators (see [`Validators`]). When the
+    /// request's conditional header fields say the client's copy is current, `304 Not Modified` is
+    /// sent instead of the file, with the `ETag` and `Vary` the `200 OK` would have had after
+    /// [`compress_response`] (see [`not_modified`]); when a precondition fails, `412 Precondition Failed`.
+    ///
+    /// File responses advertise `Accept-Ranges: bytes`. A `GET` with a `Range` header (and a matching
+    /// `If-Range`, if any) gets `206 Partial Content` with the requested ranges (see
+    /// [`partial_response`]) and the `Vary` of the `200 OK`, or `416 Range Not Satisfiable` if none
+    /// of them overlap the file.
+    /// Whole-file responses may be served from a precompressed sibling (see [`full_file_response`]).
+    ///
+    /// - `HEAD` is answered like `GET`; [`crate::handle_connection`] drops the body when writing.
+    /// - `OPTIONS` gets an empty `200 OK` with an `Allow` header listing [`ALLOWED_METHODS`].
+    /// - Any other method gets `405 Method Not Allowed` with the same `Allow` header.
+    ///
+    /// # Returns
+    /// - `Ok(Response)`: The response to send.
+    /// - `Err(RequestError::Io(_))`: If the resolved file can't be opened or read.
+    fn handle(&self, request: &Request, config: &ServerConfig) -> Result<Response, RequestError> {
+        let allow = ALLOWED_METHODS
+            .iter()
+            .map(Method::as_str)
+            .collect::<Vec<_>>()
+            .join(", ");
+        match request.method() {
+            Method::Get | Method::Head => {}
+            Method::Options => {
+                return Ok(Response::new(StatusCode::Ok).with_header("Allow", allow));
+            }
+            _ => {
+                return Ok(Response::new(StatusCode::MethodNotAllowed).with_header("Allow", allow));
+            }
+        }
+
+        /* Under a mount only the wildcard part of the path names the file. */
+        let root = self.root(config);
+        let path = request.param("path").unwrap_or(request.path());
+        let Some(url) = resolve_file(path, root) else {
+            /* A directory without index.html may still be listed, if listings are on for it. */
+            return match resolve_directory(path, root) {
+                Some(directory) if config.listing_allowed(&directory) => {
+                    Ok(listing_response(request, &directory, root)?)
+                }
+                _ => Ok(error_response(StatusCode::NotFound, config)),
+            };
+        };
+        let file = File::open(&url)?;
+        let metadata = file.metadata()?;
+        let validators = Validators::from_metadata(&metadata);
+        let content_type = config.mime_types.content_type(&url);
+
+        if let Some(validators) = &validators {
+            match validators.evaluate(request) {
+                Some(StatusCode::PreconditionFailed) => {
+                    return Ok(error_response(StatusCode::PreconditionFailed, config));
+                }
+                Some(_) => {
+                    /* Built like the 200 it stands for, so ETag and Vary come out the same. */
+                    let response =
+                        full_file_response(request, config, root, &url, file, content_type)?;
+                    let response = compress_response(
+                        validators.apply(response),
+                        request,
+                        &config.compression,
+                    )?;
+                    return Ok(not_modified(&response));
+                }
+                None => {}
+            }
+        }
+
+        /* Range is only defined for GET, and If-Range drops it once the client's copy is stale. */
+        let length = metadata.len();
+        let if_range_holds = request.header("If-Range").is_none_or(|value| {
+            validators
+                .as_ref()
+                .is_some_and(|validators| validators.matches_if_range(value))
+        });
+        let range = match request.header("Range") {
+            Some(value) if request.method() == Method::Get && if_range_holds => {
+                parse_range(value, length)
+            }
+            _ => RangeRequest::Ignored,
+        };
+
+        let response = match range {
+            RangeRequest::Ignored => {
+                full_file_response(request, config, root, &url, file, content_type)?
+            }
+            RangeRequest::Unsatisfiable => {
+                return Ok(error_response(StatusCode::RangeNotSatisfiable, config)
+                    .with_header("Accept-Ranges", "bytes")
+                    .with_header("Content-Range", format!("bytes */{length}")));
+            }
+            RangeRequest::Ranges(ranges) => {
+                /* Caches key the parts by the 200's Vary, so the 206 has to send the same one. */
+                let full = full_file_response(
+                    request,
+                    config,
+                    root,
+                    &url,
+                    file.try_clone()?,
+                    content_type.clone(),
+                )?;
+                let full = compress_response(full, request, &config.compression)?;
+                let mut response = partial_response(file, length, &ranges, &content_type)?;
+                for vary in full.headers().get_all("Vary") {
+                    response.headers_mut().append("Vary", vary);
+                }
+                response
+            }
+        };
+        let response = response.with_header("Accept-Ranges", "bytes");
+
+        Ok(match &validators {
+            Some(validators) => validators.apply(response),
+            None => response,
+        })
+    }
+}
+
+/// This function is a private helper function for [`StaticFiles::handle`].
+/// - It builds the `200 OK` response for a whole file.
+/// - If a precompressed sibling (`<file>.br`, `<file>.gz`, see [`precompressed_variants`]) exists
+///   and the client accepts its coding, the sibling is sent instead, with `Content-Encoding` set
+///   and the `Content-Type` of the original file.
+/// - Whenever siblings exist, `Vary: Accept-Encoding` is added, whichever file is sent.
+fn full_file_response(
+    request: &Request,
+    config: &ServerConfig,
+    root: &Path,
+    url: &Path,
+    file: File,
+    content_type: String,
+) -> Result<Response, RequestError> {
+    let response = Response::new(StatusCode::Ok).with_header("Content-Type", content_type);
+    if !config.compression.precompressed {
+        return Ok(response.with_file(file));
+    }
+
+    let variants = precompressed_variants(url, root);
+    let available = variants
+        .iter()
+        .map(|(encoding, _)| *encoding)
+        .collect::<Vec<_>>();
+    let encoding = negotiate_encoding(request.header("Accept-Encoding"), &available);
+
+    let mut response = match variants
+        .into_iter()
+        .find(|(variant, _)| *variant == encoding)
+    {
+        Some((encoding, path)) => response
+            .with_header("Content-Encoding", encoding.as_str())
+            .with_file(File::open(path)?),
+        None => response.with_file(file),
+    };
+    if !available.is_empty() {
+        add_vary_accept_encoding(&mut response);
+    }
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Body;
+
+    use std::io::BufReader;
+
+    /// A document root in the temp directory holding `file.txt`, removed again when dropped.
+    struct Site(PathBuf);
+
+    impl Site {
+        fn new(name: &str) -> Self {
+            let root = std::env::temp_dir()
+                .join(format!("rust_server_static_{name}_{}", std::process::id()));
+            let _ = std::fs::remove_dir_all(&root);
+            std::fs::create_dir_all(&root).unwrap();
+            std::fs::write(root.join("file.txt"), "hello, world\n").unwrap();
+            Self(root)
+        }
+
+        /// Parses `method path` with the extra header lines `head`.
+        fn request(method: &str, path: &str, head: &str) -> Request {
+            let raw = format!("{method} {path} HTTP/1.1\r\nHost: localhost\r\n{head}\r\n");
+            Request::new(&mut BufReader::new(raw.as_bytes())).unwrap()
+        }
+
+        /// Answers `method path` with the extra header lines `head`.
+        fn handle(&self, method: &str, path: &str, head: &str) -> Response {
+            StaticFiles::new()
+                .with_root(&self.0)
+                .handle(&Self::request(method, path, head), &ServerConfig::default())
+                .unwrap()
+        }
+    }
+
+    impl Site {
+        fn write(&self, name: &str, contents: impl AsRef<[u8]>) {
+            std::fs::write(self.0.join(name), contents).unwrap();
+        }
+    }
+
+    impl Drop for Site {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// Writes `response` as it would go on the wire.
+    fn written(response: Response) -> String {
+        let mut out = Vec::new();
+        response.write_to(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn head_has_the_headers_of_get_without_the_body() {
+        let site = Site::new("head");
+        let get = site.handle("GET", "/file.txt", "");
+        let head = site.handle("HEAD", "/file.txt", "");
+        assert_eq!(head.status(), StatusCode::Ok);
+        assert_eq!(head.headers(), get.headers());
+
+        let get = written(get);
+        let head = written(head.omit_body());
+        assert!(get.ends_with("\r\n\r\nhello, world\n"), "{get}");
+        assert!(head.contains("Content-Length: 13\r\n"), "{head}");
+        assert!(head.ends_with("\r\n\r\n"), "{head}");
+        assert_eq!(head, get.trim_end_matches("hello, world\n"));
+    }
+
+    #[test]
+    fn options_lists_the_allowed_methods() {
+        let site = Site::new("options");
+        let response = site.handle("OPTIONS", "/file.txt", "");
+        assert_eq!(response.status(), StatusCode::Ok);
+        assert_eq!(response.headers().get("Allow"), Some("GET, HEAD, OPTIONS"));
+        assert!(matches!(response.body(), Body::Empty));
+    }
+
+    #[test]
+    fn other_methods_get_405_with_allow() {
+        let site = Site::new("not_allowed");
+        for method in ["POST", "PUT", "DELETE", "PATCH"] {
+            let response = site.handle(method, "/file.txt", "");
+            assert_eq!(response.status(), StatusCode::MethodNotAllowed, "{method}");
+            assert_eq!(
+                response.headers().get("Allow"),
+                Some("GET, HEAD, OPTIONS"),
+                "{method}"
+            );
+        }
+    }
+
+    #[test]
+    fn partial_and_not_modified_responses_vary_like_the_200() {
+        let site = Site::new("vary");
+        site.write("file.txt.gz", "not really gzip");
+        /* Big enough to be compressed on the fly, without a sibling. */
+        site.write("large.txt", "a".repeat(4096));
+
+        for path in ["/file.txt", "/large.txt"] {
+            let full = site.handle("GET", path, "");
+            let etag = full.headers().get("ETag").unwrap().to_string();
+            let request = Site::request("GET", path, "");
+            let full = compress_response(full, &request, &ServerConfig::default().compression);
+            let full = full.unwrap();
+            let partial = site.handle("GET", path, "Range: bytes=0-3\r\n");
+            let not_modified = site.handle("GET", path, &format!("If-None-Match: {etag}\r\n"));
+
+            assert_eq!(partial.status(), StatusCode::PartialContent, "{path}");
+            assert_eq!(not_modified.status(), StatusCode::NotModified, "{path}");
+            let vary = |response: &Response| {
+                let values = response.headers().get_all("Vary");
+                values.map(str::to_string).collect::<Vec<_>>()
+            };
+            assert_eq!(vary(&full), ["Accept-Encoding"], "{path}");
+            assert_eq!(vary(&partial), vary(&full), "{path}");
+            assert_eq!(vary(&not_modified), vary(&full), "{path}");
+        }
+
+        /* Nothing to vary on, nothing sent. */
+        std::fs::remove_file(site.0.join("file.txt.gz")).unwrap();
+        let partial = site.handle("GET", "/file.txt", "Range: bytes=0-3\r\n");
+        assert!(!partial.headers().contains("Vary"));
+    }
+}
@@ -0,0 +1,460 @@
+use std::collections::VecDeque;
+use std::fmt::{Debug, Formatter};
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::str::FromStr;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// What a [`ThreadPool`] does with new work when its job queue is already full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueuePolicy {
+    /// The caller waits until a worker takes a job off the queue.
+    Block,
+    /// The work is handed straight back to the caller inside [`Rejected::QueueFull`].
+    /// The server uses this to answer the client with `503 Service Unavailable`.
+    Reject,
+}
+
+impl FromStr for QueuePolicy {
+    type Err = String;
+
+    /// Parses `block` or `reject` (case-insensitive), as used in the server configuration.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "block" => Ok(QueuePolicy::Block),
+            "reject" => Ok(QueuePolicy::Reject),
+            _ => Err(format!("expected `block` or `reject`, got `{value}`")),
+        }
+    }
+}
+
+/// Errors that can occur while building a [`ThreadPool`].
+///
+/// # Variants
+/// - `NoWorkers`: The pool was asked to start with zero worker threads.
+/// - `NoQueueCapacity`: The job queue was given a capacity of zero.
+/// - `Io`: The operating system refused to spawn a worker thread.
+#[derive(Debug, thiserror::Error)]
+pub enum PoolError {
+    #[error("A thread pool needs at least one worker.")]
+    NoWorkers,
+    #[error("A thread pool needs a queue capacity of at least one.")]
+    NoQueueCapacity,
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+/// Work that [`ThreadPool::execute`] could not queue. The item is handed back untouched.
+///
+/// # Variants
+/// - `QueueFull`: The queue was full and the pool uses [`QueuePolicy::Reject`].
+/// - `ShuttingDown`: The pool no longer accepts work.
+pub enum Rejected<T> {
+    QueueFull(T),
+    ShuttingDown(T),
+}
+
+impl<T> Rejected<T> {
+    /// Returns the item that could not be queued.
+    pub fn into_inner(self) -> T {
+        match self {
+            Rejected::QueueFull(item) | Rejected::ShuttingDown(item) => item,
+        }
+    }
+}
+
+impl<T> Debug for Rejected<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Rejected::QueueFull(_) => write!(f, "QueueFull(..)"),
+            Rejected::ShuttingDown(_) => write!(f, "ShuttingDown(..)"),
+        }
+    }
+}
+
+/// A fixed set of worker threads that feed items from a bounded queue into one shared handler.
+///
+/// The accept loop hands every [`std::net::TcpStream`] to [`ThreadPool::execute`] and goes
+/// straight back to accepting, so one slow client only ties up one worker.
+///
+/// Dropping the pool closes the queue, lets the workers finish every queued item and joins them.
+pub struct ThreadPool<T: Send + 'static> {
+    workers: Vec<Worker>,
+    shared: Arc<Shared<T>>,
+    policy: QueuePolicy,
+}
+
+/// State shared between the pool handle and its workers.
+struct Shared<T> {
+    queue: Mutex<Queue<T>>,
+    /* Signalled when an item is pushed or the queue is closed. */
+    item_ready: Condvar,
+    /* Signalled when a worker pops an item and frees a slot. */
+    slot_free: Condvar,
+    capacity: usize,
+}
+
+struct Queue<T> {
+    items: VecDeque<T>,
+    closed: bool,
+    /* Items taken by a worker whose handler hasn't returned yet. */
+    busy: usize,
+}
+
+struct Worker {
+    id: usize,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl<T: Send + 'static> ThreadPool<T> {
+    /// Starts `size` worker threads that each call `handler` for every queued item.
+    ///
+    /// # Arguments
+    /// - `size`: Number of worker threads.
+    /// - `capacity`: Maximum number of items waiting in the queue (not counting the ones being handled).
+    /// - `policy`: What [`Self::execute`] does when the queue is full.
+    /// - `handler`: Called on a worker thread for every item. A panic inside it is caught so the worker survives.
+    ///
+    /// # Returns
+    /// - `Ok(ThreadPool)`: If every worker was spawned.
+    /// - `Err(PoolError::NoWorkers)`: If `size` is zero.
+    /// - `Err(PoolError::NoQueueCapacity)`: If `capacity` is zero.
+    /// - `Err(PoolError::Io(_))`: If a worker thread could not be spawned.
+    pub fn build<F>(
+        size: usize,
+        capacity: usize,
+        policy: QueuePolicy,
+        handler: F,
+    ) -> Result<Self, PoolError>
+    where
+        F: Fn(T) + Send + Sync + 'static,
+    {
+        if size == 0 {
+            return Err(PoolError::NoWorkers);
+        }
+        if capacity == 0 {
+            return Err(PoolError::NoQueueCapacity);
+        }
+
+        let shared = Arc::new(Shared {
+            queue: Mutex::new(Queue {
+                items: VecDeque::with_capacity(capacity),
+                closed: false,
+                busy: 0,
+            }),
+            item_ready: Condvar::new(),
+            slot_free: Condvar::new(),
+            capacity,
+        });
+        let handler = Arc::new(handler);
+
+        let mut pool = Self {
+            workers: Vec::with_capacity(size),
+            shared,
+            policy,
+        };
+        for id in 0..size {
+            /* If this fails, dropping `pool` shuts down the workers spawned so far. */
+            let worker = Worker::spawn(id, Arc::clone(&pool.shared), Arc::clone(&handler))?;
+            pool.workers.push(worker);
+        }
+
+        Ok(pool)
+    }
+
+    /// Queues `item` for the next free worker.
+    ///
+    /// # Returns
+    /// - `Ok(())`: If the item was queued.
+    /// - `Err(Rejected::QueueFull(item))`: If the queue is full and the policy is [`QueuePolicy::Reject`].
+    /// - `Err(Rejected::ShuttingDown(item))`: If the pool has stopped accepting work.
+    pub fn execute(&self, item: T) -> Result<(), Rejected<T>> {
+        let mut queue = self.shared.lock();
+
+        while !queue.closed && queue.items.len() >= self.shared.capacity {
+            match self.policy {
+                QueuePolicy::Block => {
+                    queue = self
+                        .shared
+                        .slot_free
+                        .wait(queue)
+                        .unwrap_or_else(|poisoned| poisoned.into_inner());
+                }
+                QueuePolicy::Reject => return Err(Rejected::QueueFull(item)),
+            }
+        }
+        if queue.closed {
+            return Err(Rejected::ShuttingDown(item));
+        }
+
+        queue.items.push_back(item);
+        self.shared.item_ready.notify_one();
+        Ok(())
+    }
+
+    /// Number of items waiting in the queue.
+    pub fn queue_len(&self) -> usize {
+        self.shared.lock().items.len()
+    }
+
+    /// Number of worker threads in the pool.
+    pub fn size(&self) -> usize {
+        self.workers.len()
+    }
+
+    /// Returns `true` if nothing is queued and no worker is handling an item.
+    pub fn is_idle(&self) -> bool {
+        let queue = self.shared.lock();
+        queue.items.is_empty() && queue.busy == 0
+    }
+
+    /// Stops accepting work and gives the workers up to `timeout` to finish every queued and running item.
+    ///
+    /// Workers that are still busy when the deadline passes are detached rather than joined,
+    /// so a stuck client cannot hold up the caller forever.
+    ///
+    /// # Returns
+    /// - `true`: If every worker finished within `timeout`.
+    /// - `false`: If at least one worker was still busy and got detached.
+    pub fn shutdown(mut self, timeout: Duration) -> bool {
+        self.close();
+        /* A timeout too long to add to the clock never runs out. */
+        let deadline = Instant::now().checked_add(timeout);
+
+        while deadline.is_none_or(|deadline| Instant::now() < deadline) {
+            let all_finished = self
+                .workers
+                .iter()
+                .all(|worker| worker.thread.as_ref().is_none_or(JoinHandle::is_finished));
+            if all_finished {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        let mut drained = true;
+        for worker in &mut self.workers {
+            let Some(thread) = worker.thread.take() else {
+                continue;
+            };
+            if !thread.is_finished() {
+                eprintln!(
+                    "Worker {} did not finish before the shutdown deadline.",
+                    worker.id
+                );
+                drained = false;
+            } else if thread.join().is_err() {
+                eprintln!("Worker {} exited abnormally.", worker.id);
+            }
+        }
+        drained
+    }
+
+    /// Marks the queue as closed and wakes every thread waiting on it.
+    fn close(&self) {
+        self.shared.lock().closed = true;
+        self.shared.item_ready.notify_all();
+        self.shared.slot_free.notify_all();
+    }
+}
+
+impl<T: Send + 'static> Drop for ThreadPool<T> {
+    fn drop(&mut self) {
+        self.close();
+
+        for worker in &mut self.workers {
+            if let Some(thread) = worker.thread.take()
+                && thread.join().is_err()
+            {
+                eprintln!("Worker {} exited abnormally.", worker.id);
+            }
+        }
+    }
+}
+
+impl<T> Shared<T> {
+    /// Locks the queue. A poisoned lock is recovered, since the queue itself is never left half-updated.
+    fn lock(&self) -> MutexGuard<'_, Queue<T>> {
+        self.queue
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl Worker {
+    /// Spawns a worker thread that handles queued items until the queue is closed and empty.
+    fn spawn<T, F>(id: usize, shared: Arc<Shared<T>>, handler: Arc<F>) -> io::Result<Self>
+    where
+        T: Send + 'static,
+        F: Fn(T) + Send + Sync + 'static,
+    {
+        let thread = thread::Builder::new()
+            .name(format!("worker-{id}"))
+            .spawn(move || {
+                loop {
+                    let item = {
+                        let mut queue = shared.lock();
+                        loop {
+                            if let Some(item) = queue.items.pop_front() {
+                                queue.busy += 1;
+                                break item;
+                            }
+                            if queue.closed {
+                                return;
+                            }
+                            queue = shared
+                                .item_ready
+                                .wait(queue)
+                                .unwrap_or_else(|poisoned| poisoned.into_inner());
+                        }
+                    };
+                    shared.slot_free.notify_one();
+
+                    /* A panicking handler must not take the worker down with it. */
+                    if panic::catch_unwind(AssertUnwindSafe(|| handler(item))).is_err() {
+                        eprintln!("Worker {id} recovered from a panic in the handler.");
+                    }
+                    shared.lock().busy -= 1;
+                }
+            })?;
+
+        Ok(Self {
+            id,
+            thread: Some(thread),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::{self, Receiver, Sender};
+
+    type Job = Box<dyn FnOnce() + Send>;
+
+    fn pool(size: usize, capacity: usize, policy: QueuePolicy) -> ThreadPool<Job> {
+        ThreadPool::build(size, capacity, policy, |job: Job| job()).unwrap()
+    }
+
+    /// A job that reports when it starts and then waits until `release` is signalled or dropped.
+    fn blocking_job(started: Sender<()>, release: Receiver<()>) -> Job {
+        Box::new(move || {
+            started.send(()).unwrap();
+            let _ = release.recv();
+        })
+    }
+
+    /// A job that reports `id` on `done`.
+    fn report(done: &Sender<usize>, id: usize) -> Job {
+        let done = done.clone();
+        Box::new(move || done.send(id).unwrap())
+    }
+
+    #[test]
+    fn build_needs_workers_and_queue_capacity() {
+        let handler = |_: ()| {};
+        assert!(matches!(
+            ThreadPool::build(0, 1, QueuePolicy::Block, handler),
+            Err(PoolError::NoWorkers)
+        ));
+        assert!(matches!(
+            ThreadPool::build(1, 0, QueuePolicy::Block, handler),
+            Err(PoolError::NoQueueCapacity)
+        ));
+    }
+
+    #[test]
+    fn reject_hands_work_back_when_the_queue_is_full() {
+        let pool = pool(1, 1, QueuePolicy::Reject);
+        let (started, worker_started) = mpsc::channel();
+        let (release, released) = mpsc::channel();
+        let (done, finished) = mpsc::channel();
+
+        pool.execute(blocking_job(started, released)).unwrap();
+        worker_started.recv().unwrap();
+        pool.execute(report(&done, 1)).unwrap();
+        assert_eq!(pool.queue_len(), 1);
+
+        let rejected = pool.execute(report(&done, 2)).unwrap_err();
+        assert!(matches!(rejected, Rejected::QueueFull(_)));
+        /* The rejected job is handed back and can still be run by the caller. */
+        rejected.into_inner()();
+        assert_eq!(finished.recv().unwrap(), 2);
+
+        release.send(()).unwrap();
+        assert_eq!(finished.recv().unwrap(), 1);
+        assert!(pool.shutdown(Duration::from_secs(5)));
+    }
+
+    #[test]
+    fn block_waits_for_a_free_slot() {
+        let pool = pool(1, 1, QueuePolicy::Block);
+        let (started, worker_started) = mpsc::channel();
+        let (release, released) = mpsc::channel();
+        let (done, finished) = mpsc::channel();
+
+        pool.execute(blocking_job(started, released)).unwrap();
+        worker_started.recv().unwrap();
+        pool.execute(report(&done, 1)).unwrap();
+
+        thread::scope(|scope| {
+            let blocked = scope.spawn(|| pool.execute(report(&done, 2)).is_ok());
+            thread::sleep(Duration::from_millis(100));
+            assert!(
+                !blocked.is_finished(),
+                "execute returned while the queue was full"
+            );
+
+            release.send(()).unwrap();
+            assert!(blocked.join().unwrap());
+        });
+
+        assert_eq!(finished.recv().unwrap(), 1);
+        assert_eq!(finished.recv().unwrap(), 2);
+        assert!(pool.shutdown(Duration::from_secs(5)));
+    }
+
+    #[test]
+    fn a_worker_survives_a_panicking_handler() {
+        let pool = pool(1, 4, QueuePolicy::Block);
+        let (done, finished) = mpsc::channel();
+
+        pool.execute(Box::new(|| panic!("handler failed"))).unwrap();
+        pool.execute(report(&done, 1)).unwrap();
+
+        assert_eq!(finished.recv_timeout(Duration::from_secs(5)), Ok(1));
+        assert!(pool.shutdown(Duration::from_secs(5)));
+    }
+
+    #[test]
+    fn shutdown_detaches_a_stuck_worker_after_the_timeout() {
+        let pool = pool(2, 4, QueuePolicy::Block);
+        let (started, worker_started) = mpsc::channel();
+        let (release, released) = mpsc::channel();
+
+        pool.execute(blocking_job(started, released)).unwrap();
+        worker_started.recv().unwrap();
+        assert!(!pool.is_idle());
+
+        let shutdown_started = Instant::now();
+        assert!(!pool.shutdown(Duration::from_millis(100)));
+        assert!(shutdown_started.elapsed() < Duration::from_secs(2));
+
+        /* Let the detached worker finish. */
+        drop(release);
+    }
+
+    #[test]
+    fn shutdown_finishes_queued_work_first() {
+        let pool = pool(1, 8, QueuePolicy::Block);
+        let (done, finished) = mpsc::channel();
+        for id in 0..5 {
+            pool.execute(report(&done, id)).unwrap();
+        }
+
+        assert!(pool.shutdown(Duration::from_secs(5)));
+        assert_eq!(finished.try_iter().collect::<Vec<_>>(), [0, 1, 2, 3, 4]);
+    }
+}
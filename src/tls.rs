@@ -0,0 +1,374 @@
+use crate::{
+    Connection, Handler, Method, Request, RequestError, Response, ServerConfig, ShutdownHandle,
+    StatusCode, TlsConfig, error_response,
+};
+
+use rustls::crypto::CryptoProvider;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::CertifiedKey;
+use rustls::{ServerConnection, StreamOwned};
+use std::collections::HashMap;
+use std::fmt::{Debug, Formatter};
+use std::io;
+use std::net::{SocketAddr, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime};
+
+/// A TLS connection to a client, served by [`crate::handle_connection`] like a plain [`TcpStream`].
+pub type TlsStream = StreamOwned<ServerConnection, TcpStream>;
+
+/// How often the certificate watcher checks whether the server is shutting down.
+static WATCH_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Errors that can occur while loading certificates or setting up TLS.
+///
+/// # Variants
+/// - `NotConfigured`: TLS is enabled but `tls.cert` or `tls.key` is missing.
+/// - `Pem`: A certificate or key file could not be read or is not valid PEM.
+/// - `NoCertificate`: A certificate file holds no certificate.
+/// - `InvalidKey`: A private key is not usable or doesn't match its certificate.
+/// - `Rustls`: The TLS configuration or a connection could not be set up.
+#[derive(Debug, thiserror::Error)]
+pub enum TlsError {
+    #[error("TLS is enabled but tls.cert or tls.key is not set.")]
+    NotConfigured,
+    #[error("Could not read `{path}`: {source}")]
+    Pem {
+        path: PathBuf,
+        source: rustls::pki_types::pem::Error,
+    },
+    #[error("No certificate found in `{0}`.")]
+    NoCertificate(PathBuf),
+    #[error("Invalid private key `{path}`: {source}")]
+    InvalidKey {
+        path: PathBuf,
+        source: rustls::Error,
+    },
+    #[error(transparent)]
+    Rustls(#[from] rustls::Error),
+}
+
+/// One certificate chain and its private key, and the server names it is used for.
+/// The default certificate has no names.
+#[derive(Debug, Clone)]
+struct CertificateSource {
+    names: Vec<String>,
+    cert: PathBuf,
+    key: PathBuf,
+}
+
+impl CertificateSource {
+    /// Reads the PEM certificate chain and private key.
+    fn load(&self, provider: &CryptoProvider) -> Result<Arc<CertifiedKey>, TlsError> {
+        let chain = CertificateDer::pem_file_iter(&self.cert)
+            .and_then(|certificates| certificates.collect::<Result<Vec<_>, _>>())
+            .map_err(|source| TlsError::Pem {
+                path: self.cert.clone(),
+                source,
+            })?;
+        if chain.is_empty() {
+            return Err(TlsError::NoCertificate(self.cert.clone()));
+        }
+        let key = PrivateKeyDer::from_pem_file(&self.key).map_err(|source| TlsError::Pem {
+            path: self.key.clone(),
+            source,
+        })?;
+
+        let certified = CertifiedKey::from_der(chain, key, provider).map_err(|source| {
+            TlsError::InvalidKey {
+                path: self.key.clone(),
+                source,
+            }
+        })?;
+        Ok(Arc::new(certified))
+    }
+
+    /// The modification times of the certificate and key files, to notice when they change.
+    fn modified(&self) -> [Option<SystemTime>; 2] {
+        let modified = |path: &Path| {
+            path.metadata()
+                .and_then(|metadata| metadata.modified())
+                .ok()
+        };
+        [modified(&self.cert), modified(&self.key)]
+    }
+}
+
+/// The certificates currently in use.
+struct Loaded {
+    default: Arc<CertifiedKey>,
+    /// Lowercase server name (or `*.domain` wildcard) to certificate.
+    by_name: HashMap<String, Arc<CertifiedKey>>,
+    /// [`CertificateSource::modified`] of every source when it was loaded.
+    modified: Vec<[Option<SystemTime>; 2]>,
+}
+
+/// The server's certificates, chosen per connection by the SNI server name, and reloadable
+/// while the server runs.
+///
+/// - A client asking for a name listed in `tls.certificates` gets that certificate. Names may be
+///   wildcards (`*.example.com`, one label deep).
+/// - Any other client, or one that sends no name, gets the default `tls.cert`.
+///
+/// [`CertificateStore::reload`] reads every file again and swaps the certificates in at once;
+/// connections already established keep theirs. If any file fails to load, the old certificates
+/// stay in use. [`watch`] reloads automatically when the files change.
+pub struct CertificateStore {
+    sources: Vec<CertificateSource>,
+    provider: Arc<CryptoProvider>,
+    loaded: RwLock<Loaded>,
+}
+
+impl CertificateStore {
+    /// Loads the certificates named in `config`.
+    ///
+    /// # Returns
+    /// - `Ok(CertificateStore)`: If every certificate and key loaded.
+    /// - `Err(TlsError)`: See [`TlsError`] for each failure.
+    pub fn from_config(config: &TlsConfig) -> Result<Self, TlsError> {
+        let (Some(cert), Some(key)) = (&config.cert, &config.key) else {
+            return Err(TlsError::NotConfigured);
+        };
+        let mut sources = vec![CertificateSource {
+            names: Vec::new(),
+            cert: cert.clone(),
+            key: key.clone(),
+        }];
+        sources.extend(
+            config
+                .certificates
+                .iter()
+                .map(|certificate| CertificateSource {
+                    names: certificate.names.clone(),
+                    cert: certificate.cert.clone(),
+                    key: certificate.key.clone(),
+                }),
+        );
+
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let loaded = load(&sources, &provider)?;
+        Ok(Self {
+            sources,
+            provider,
+            loaded: RwLock::new(loaded),
+        })
+    }
+
+    /// Reads every certificate and key again and starts using them for new connections.
+    ///
+    /// # Returns
+    /// - `Ok(())`: If everything loaded.
+    /// - `Err(TlsError)`: If something failed. The previous certificates are kept.
+    pub fn reload(&self) -> Result<(), TlsError> {
+        let loaded = load(&self.sources, &self.provider)?;
+        *self
+            .loaded
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = loaded;
+        Ok(())
+    }
+
+    /// Reloads if a certificate or key file was modified since the last load.
+    ///
+    /// # Returns
+    /// - `Ok(true)`: If the files changed and were reloaded.
+    /// - `Ok(false)`: If nothing changed.
+    /// - `Err(TlsError)`: If the files changed but failed to load.
+    pub fn reload_if_changed(&self) -> Result<bool, TlsError> {
+        let current: Vec<_> = self
+            .sources
+            .iter()
+            .map(CertificateSource::modified)
+            .collect();
+        let changed = self
+            .loaded
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .modified
+            != current;
+        if changed {
+            self.reload()?;
+        }
+        Ok(changed)
+    }
+
+    /// Builds the rustls configuration that picks certificates from this store.
+    /// Only `http/1.1` is offered through ALPN.
+    pub fn server_config(self: &Arc<Self>) -> Result<Arc<rustls::ServerConfig>, TlsError> {
+        let mut config = rustls::ServerConfig::builder_with_provider(Arc::clone(&self.provider))
+            .with_safe_default_protocol_versions()?
+            .with_no_client_auth()
+            .with_cert_resolver(Arc::clone(self) as Arc<dyn ResolvesServerCert>);
+        config.alpn_protocols = vec![b"http/1.1".to_vec()];
+        Ok(Arc::new(config))
+    }
+
+    /// Returns the certificate for the SNI `server_name`, see [`CertificateStore`].
+    fn certificate_for(&self, server_name: Option<&str>) -> Arc<CertifiedKey> {
+        let loaded = self
+            .loaded
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let Some(name) = server_name.map(str::to_ascii_lowercase) else {
+            return Arc::clone(&loaded.default);
+        };
+        let wildcard = name
+            .split_once('.')
+            .map(|(_, domain)| format!("*.{domain}"));
+
+        loaded
+            .by_name
+            .get(&name)
+            .or_else(|| wildcard.and_then(|wildcard| loaded.by_name.get(&wildcard)))
+            .unwrap_or(&loaded.default)
+            .clone()
+    }
+}
+
+impl ResolvesServerCert for CertificateStore {
+    fn resolve(&self, client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(self.certificate_for(client_hello.server_name()))
+    }
+}
+
+impl Debug for CertificateStore {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CertificateStore")
+            .field("sources", &self.sources)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Connection for TlsStream {
+    fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.sock.peer_addr()
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.sock.set_read_timeout(timeout)
+    }
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.sock.set_write_timeout(timeout)
+    }
+}
+
+/// Wraps an accepted TCP connection in TLS. The handshake happens on the first read.
+///
+/// # Returns
+/// - `Ok(TlsStream)`: The server side of the TLS connection.
+/// - `Err(TlsError::Rustls(_))`: If rustls can't start a connection with `config`.
+pub fn accept(
+    config: &Arc<rustls::ServerConfig>,
+    stream: TcpStream,
+) -> Result<TlsStream, TlsError> {
+    let connection = ServerConnection::new(Arc::clone(config))?;
+    Ok(StreamOwned::new(connection, stream))
+}
+
+/// Starts a thread that calls [`CertificateStore::reload_if_changed`] every `interval` until
+/// `shutdown` is requested. Reloads and failed reloads are reported on stdout and stderr.
+///
+/// # Returns
+/// - `Ok(JoinHandle)`: The watcher thread, finished soon after the shutdown.
+/// - `Err(io::Error)`: If the thread could not be spawned.
+pub fn watch(
+    store: Arc<CertificateStore>,
+    interval: Duration,
+    shutdown: ShutdownHandle,
+) -> io::Result<JoinHandle<()>> {
+    thread::Builder::new()
+        .name("tls-reload".to_string())
+        .spawn(move || {
+            /* An interval too long to add to the clock never comes around. */
+            let mut next_check = Instant::now().checked_add(interval);
+            while !shutdown.is_shutting_down() {
+                thread::sleep(WATCH_POLL_INTERVAL);
+                if next_check.is_none_or(|next_check| Instant::now() < next_check) {
+                    continue;
+                }
+                next_check = Instant::now().checked_add(interval);
+                match store.reload_if_changed() {
+                    Ok(true) => println!("Reloaded the TLS certificates."),
+                    Ok(false) => {}
+                    Err(error) => eprintln!("Failed to reload the TLS certificates: {error}"),
+                }
+            }
+        })
+}
+
+/// A [`Handler`] for the plain HTTP redirect listener: every request is sent to the same URL
+/// on `https://`, with `301 Moved Permanently` for `GET`/`HEAD` and `308 Permanent Redirect`
+/// (which keeps the method and body) for anything else.
+#[derive(Debug, Clone, Copy)]
+pub struct HttpsRedirect {
+    /// The HTTPS port, left out of the `Location` when it is `443`.
+    pub port: u16,
+}
+
+impl Handler for HttpsRedirect {
+    fn handle(&self, request: &Request, config: &ServerConfig) -> Result<Response, RequestError> {
+        let Some(host) = request.header("Host").and_then(host_name) else {
+            return Ok(error_response(StatusCode::BadRequest, config));
+        };
+        let port = match self.port {
+            443 => String::new(),
+            port => format!(":{port}"),
+        };
+        /* Keep the target as the client encoded it, minus the authority of an absolute-form URL. */
+        let url = request.url();
+        let target = match url.split_once("://") {
+            Some((_, rest)) => rest.find('/').map_or("/", |start| &rest[start..]),
+            None => url,
+        };
+
+        let status = match request.method() {
+            Method::Get | Method::Head => StatusCode::MovedPermanently,
+            _ => StatusCode::PermanentRedirect,
+        };
+        Ok(Response::new(status).with_header("Location", format!("https://{host}{port}{target}")))
+    }
+}
+
+/// This function is a private helper function for [`CertificateStore`].
+/// - It loads every source; the first one is the default certificate.
+fn load(sources: &[CertificateSource], provider: &CryptoProvider) -> Result<Loaded, TlsError> {
+    let mut default = None;
+    let mut by_name = HashMap::new();
+    for source in sources {
+        let certified = source.load(provider)?;
+        if source.names.is_empty() {
+            default.get_or_insert(certified);
+        } else {
+            for name in &source.names {
+                by_name.insert(name.to_ascii_lowercase(), Arc::clone(&certified));
+            }
+        }
+    }
+
+    Ok(Loaded {
+        default: default.ok_or(TlsError::NotConfigured)?,
+        by_name,
+        modified: sources.iter().map(CertificateSource::modified).collect(),
+    })
+}
+
+/// This function is a private helper function for [`HttpsRedirect`].
+/// - It returns the host of a `Host` header value without the port (`[::1]:80` -> `[::1]`).
+/// - Values with characters that don't belong in a host are refused.
+fn host_name(host: &str) -> Option<&str> {
+    let name = if host.starts_with('[') {
+        &host[..=host.find(']')?]
+    } else {
+        host.split(':').next()?
+    };
+    let valid = !name.is_empty()
+        && name
+            .bytes()
+            .all(|byte| byte.is_ascii_alphanumeric() || b".-[]:".contains(&byte));
+    valid.then_some(name)
+}
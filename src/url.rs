@@ -0,0 +1,336 @@
+use crate::RequestError;
+
+use std::fmt::Write;
+
+/// Checks a request target and returns its path, percent-decoded and without dot segments.
+///
+/// Accepted forms (RFC 9112 section 3.2):
+/// - origin-form: `/docs/my%20page?x=1` -> `/docs/my page`
+/// - absolute-form: `http://example.com/docs` -> `/docs` (an empty path becomes `/`)
+/// - asterisk-form: `*` -> `*` (only meaningful for `OPTIONS`)
+///
+/// The query string and fragment are not part of the path and are not decoded here, but they
+/// must still be well formed.
+///
+/// `.` and `..` segments, encoded (`%2e%2e`) or not, are resolved with [`remove_dot_segments`],
+/// so `/docs/../private/a` comes out as `/private/a` and everything that matches on the path
+/// (routes, mounts, the filesystem) sees the same one.
+///
+/// # Returns
+/// - `Ok(String)`: The decoded path.
+/// - `Err(RequestError::InvalidURL)`: If the target
+///   - is in none of the forms above,
+///   - contains a byte that isn't printable ASCII, or a backslash,
+///   - has a `%` that isn't followed by two hex digits,
+///   - decodes to invalid UTF-8, a control character (including NUL), `/` or `\` inside a segment
+///     (`%2F`, `%5C`), which would otherwise change how the path is split.
+pub fn decode_target(target: &str) -> Result<String, RequestError> {
+    if target == "*" {
+        return Ok(target.to_string());
+    }
+    if !target
+        .bytes()
+        .all(|byte| byte.is_ascii_graphic() && byte != b'\\')
+    {
+        return Err(RequestError::InvalidURL);
+    }
+    check_escapes(target)?;
+
+    let end = target.find(['?', '#']).unwrap_or(target.len());
+    let path = &target[..end];
+    let path = if path.starts_with('/') {
+        path
+    } else {
+        /* absolute-form: skip "scheme://authority". */
+        let (scheme, rest) = path.split_once("://").ok_or(RequestError::InvalidURL)?;
+        if scheme.is_empty()
+            || !scheme
+                .bytes()
+                .all(|byte| byte.is_ascii_alphanumeric() || b"+-.".contains(&byte))
+        {
+            return Err(RequestError::InvalidURL);
+        }
+        rest.find('/').map_or("/", |start| &rest[start..])
+    };
+
+    /* An encoded slash would survive decoding as a separator that was never in the URL. */
+    if has_encoded_slash(path) {
+        return Err(RequestError::InvalidURL);
+    }
+    let decoded = percent_decode(path.as_bytes())?;
+    if decoded
+        .chars()
+        .any(|char| char.is_control() || char == '\\')
+    {
+        return Err(RequestError::InvalidURL);
+    }
+
+    Ok(remove_dot_segments(&decoded))
+}
+
+/// Resolves the `.` and `..` segments of a decoded absolute path (RFC 3986 section 5.2.4).
+///
+/// - `.` is dropped and `..` drops the segment before it; `..` at the root stays at the root.
+/// - A path ending in a dot segment keeps a trailing `/`: `/docs/..` -> `/`.
+/// - Empty segments are kept: `//a/./b` -> `//a/b`.
+pub fn remove_dot_segments(path: &str) -> String {
+    let mut segments: Vec<&str> = Vec::new();
+    let mut parts = path.strip_prefix('/').unwrap_or(path).split('/').peekable();
+    while let Some(part) = parts.next() {
+        match part {
+            "." | ".." => {
+                if part == ".." {
+                    segments.pop();
+                }
+                /* The directory the dot segment named is still a directory. */
+                if parts.peek().is_none() {
+                    segments.push("");
+                }
+            }
+            part => segments.push(part),
+        }
+    }
+    format!("/{}", segments.join("/"))
+}
+
+/// Percent-decodes `input` (RFC 3986 section 2.1) and checks that the result is UTF-8.
+///
+/// # Returns
+/// - `Ok(String)`: The decoded text.
+/// - `Err(RequestError::InvalidURL)`: If a `%` isn't followed by two hex digits or the decoded
+///   bytes aren't valid UTF-8.
+pub fn percent_decode(input: &[u8]) -> Result<String, RequestError> {
+    let mut decoded = Vec::with_capacity(input.len());
+    let mut bytes = input.iter();
+
+    while let Some(&byte) = bytes.next() {
+        if byte != b'%' {
+            decoded.push(byte);
+            continue;
+        }
+        let (Some(high), Some(low)) = (bytes.next(), bytes.next()) else {
+            return Err(RequestError::InvalidURL);
+        };
+        let (Some(high), Some(low)) = (hex_value(*high), hex_value(*low)) else {
+            return Err(RequestError::InvalidURL);
+        };
+        decoded.push(high << 4 | low);
+    }
+
+    String::from_utf8(decoded).map_err(|_| RequestError::InvalidURL)
+}
+
+/// Parses a query string (`a=1&tag=x&tag=y`) into its `(name, value)` pairs, in order.
+///
+/// - Names and values are percent-decoded, and `+` is read as a space
+///   (`application/x-www-form-urlencoded`); a literal `+` is sent as `%2B`.
+/// - A pair without `=` has an empty value (`?debug` -> `("debug", "")`).
+/// - Empty pairs (`a=1&&b=2`) are skipped. Repeated names are all kept.
+///
+/// # Returns
+/// - `Ok(Vec<(String, String)>)`: The decoded pairs.
+/// - `Err(RequestError::InvalidURL)`: If a name or value has a bad escape or isn't UTF-8 once decoded.
+pub fn parse_query(query: &str) -> Result<Vec<(String, String)>, RequestError> {
+    let decode = |text: &str| percent_decode(text.replace('+', " ").as_bytes());
+
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            Ok((decode(name)?, decode(value)?))
+        })
+        .collect()
+}
+
+/// Percent-encodes `segment` for use as one URL path segment.
+/// Everything but unreserved characters (RFC 3986 section 2.3) is encoded.
+pub fn percent_encode(segment: &str) -> String {
+    let mut encoded = String::with_capacity(segment.len());
+    for byte in segment.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            let _ = write!(encoded, "%{byte:02X}");
+        }
+    }
+    encoded
+}
+
+/// Returns the value of an ASCII hex digit.
+fn hex_value(digit: u8) -> Option<u8> {
+    (digit as char).to_digit(16).map(|value| value as u8)
+}
+
+/// Checks that every `%` in `input` starts a valid escape, without decoding anything.
+fn check_escapes(input: &str) -> Result<(), RequestError> {
+    let bytes = input.as_bytes();
+    for (index, _) in input.match_indices('%') {
+        let escape = bytes
+            .get(index + 1..index + 3)
+            .ok_or(RequestError::InvalidURL)?;
+        if !escape.iter().all(u8::is_ascii_hexdigit) {
+            return Err(RequestError::InvalidURL);
+        }
+    }
+    Ok(())
+}
+
+/// Returns `true` if one of the escapes in `path` is `%2F` (`/`).
+fn has_encoded_slash(path: &str) -> bool {
+    path.match_indices('%').any(|(index, _)| {
+        path.get(index + 1..index + 3)
+            .is_some_and(|escape| escape.eq_ignore_ascii_case("2f"))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn query(query: &str) -> Vec<(String, String)> {
+        parse_query(query).unwrap()
+    }
+
+    fn pairs(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn query_pairs_are_decoded_in_order() {
+        assert_eq!(query("a=1&b=two"), pairs(&[("a", "1"), ("b", "two")]));
+        assert_eq!(
+            query("name=J%C3%BCrgen&path=%2Fetc%2F"),
+            pairs(&[("name", "Jürgen"), ("path", "/etc/")])
+        );
+        assert_eq!(query("a%3Db=c%26d"), pairs(&[("a=b", "c&d")]));
+        assert_eq!(query("x=1=2"), pairs(&[("x", "1=2")]));
+        assert_eq!(query(""), pairs(&[]));
+    }
+
+    #[test]
+    fn plus_is_a_space_and_an_encoded_plus_is_a_plus() {
+        assert_eq!(query("q=a+b+c"), pairs(&[("q", "a b c")]));
+        assert_eq!(query("q=1%2B1"), pairs(&[("q", "1+1")]));
+        assert_eq!(query("my+name=x"), pairs(&[("my name", "x")]));
+        assert_eq!(query("q=%20+"), pairs(&[("q", "  ")]));
+    }
+
+    #[test]
+    fn repeated_keys_are_all_kept() {
+        assert_eq!(
+            query("tag=x&other=1&tag=y&tag=x"),
+            pairs(&[("tag", "x"), ("other", "1"), ("tag", "y"), ("tag", "x")])
+        );
+    }
+
+    #[test]
+    fn empty_values_and_pairs() {
+        assert_eq!(query("debug"), pairs(&[("debug", "")]));
+        assert_eq!(query("a=&b"), pairs(&[("a", ""), ("b", "")]));
+        assert_eq!(query("=1"), pairs(&[("", "1")]));
+        assert_eq!(query("a=1&&b=2&"), pairs(&[("a", "1"), ("b", "2")]));
+        assert_eq!(query("&&"), pairs(&[]));
+    }
+
+    #[test]
+    fn invalid_escapes_are_rejected() {
+        for text in [
+            "a=%", "a=%4", "a=%zz", "%g1=x", "a=%C3", "a=%FF", "a=1&b=%2",
+        ] {
+            assert!(
+                matches!(parse_query(text), Err(RequestError::InvalidURL)),
+                "{text}"
+            );
+        }
+    }
+
+    #[test]
+    fn percent_decode_handles_any_case_and_checks_utf8() {
+        assert_eq!(percent_decode(b"a%20b%2fc%2Fd").unwrap(), "a b/c/d");
+        assert_eq!(percent_decode(b"%e2%82%AC").unwrap(), "€");
+        assert_eq!(percent_decode(b"a+b").unwrap(), "a+b");
+        assert!(percent_decode(b"%").is_err());
+        assert!(percent_decode(b"100%").is_err());
+        assert!(percent_decode(b"%C3%28").is_err());
+    }
+
+    #[test]
+    fn percent_encode_keeps_only_unreserved_characters() {
+        assert_eq!(percent_encode("a-b_c.d~e"), "a-b_c.d~e");
+        assert_eq!(percent_encode("a b/c?d"), "a%20b%2Fc%3Fd");
+        assert_eq!(percent_encode("ü+"), "%C3%BC%2B");
+        assert_eq!(
+            percent_decode(percent_encode("any thing/ü%").as_bytes()).unwrap(),
+            "any thing/ü%"
+        );
+    }
+
+    #[test]
+    fn targets_decode_to_their_path() {
+        assert_eq!(
+            decode_target("/docs/my%20page?x=%41#top").unwrap(),
+            "/docs/my page"
+        );
+        assert_eq!(decode_target("http://example.com/docs").unwrap(), "/docs");
+        assert_eq!(decode_target("http://example.com").unwrap(), "/");
+        assert_eq!(decode_target("*").unwrap(), "*");
+        assert_eq!(decode_target("/a+b").unwrap(), "/a+b");
+    }
+
+    #[test]
+    fn dot_segments_are_removed_encoded_or_not() {
+        assert_eq!(
+            decode_target("/x/../private/secret").unwrap(),
+            "/private/secret"
+        );
+        assert_eq!(
+            decode_target("/./private/secret").unwrap(),
+            "/private/secret"
+        );
+        assert_eq!(
+            decode_target("/%2e%2e/private/secret").unwrap(),
+            "/private/secret"
+        );
+        assert_eq!(decode_target("/x/%2E./private").unwrap(), "/private");
+        assert_eq!(decode_target("http://example.com/a/.%2e/b").unwrap(), "/b");
+        assert_eq!(decode_target("/a/..b/.c").unwrap(), "/a/..b/.c");
+    }
+
+    #[test]
+    fn remove_dot_segments_follows_rfc_3986() {
+        assert_eq!(remove_dot_segments("/"), "/");
+        assert_eq!(remove_dot_segments("/a/b/c/./../../g"), "/a/g");
+        assert_eq!(remove_dot_segments("/../../a"), "/a");
+        assert_eq!(remove_dot_segments("/a/.."), "/");
+        assert_eq!(remove_dot_segments("/a/b/."), "/a/b/");
+        assert_eq!(remove_dot_segments("/a/b/"), "/a/b/");
+        assert_eq!(remove_dot_segments("//a/./b"), "//a/b");
+    }
+
+    #[test]
+    fn unsafe_targets_are_rejected() {
+        for target in [
+            "docs",
+            "://example.com/",
+            "/a b",
+            "/a\\b",
+            "/a%5Cb",
+            "/a%2Fb",
+            "/a%2fb",
+            "/a%00b",
+            "/a%0Ab",
+            "/%",
+            "/?q=%zz",
+            "/caf\u{e9}",
+        ] {
+            assert!(
+                matches!(decode_target(target), Err(RequestError::InvalidURL)),
+                "{target:?}"
+            );
+        }
+    }
+}
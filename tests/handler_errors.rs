@@ -0,0 +1,75 @@
+use rust_server::{Request, RequestError, Response, Router, Server, ServerConfig, StatusCode};
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::thread;
+
+/// Starts a server whose `/broken` handler fails with an I/O error and whose `/bad` handler
+/// fails with a request error, next to a working `/ok` route.
+fn start() -> SocketAddr {
+    let router = Router::new()
+        .get("/ok", |_: &Request, _: &ServerConfig| {
+            Ok(Response::new(StatusCode::Ok).with_bytes("ok"))
+        })
+        .get("/broken", |_: &Request, _: &ServerConfig| {
+            Err(RequestError::Io(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "permission denied",
+            )))
+        })
+        .get("/bad", |_: &Request, _: &ServerConfig| {
+            Err(RequestError::InvalidURL)
+        });
+    let server = Server::bind("127.0.0.1:0").unwrap().with_router(router);
+    let address = server.local_addr().unwrap();
+    thread::spawn(move || server.run());
+    address
+}
+
+/// Sends `GET path` followed by a last `GET /ok` on one connection and reads until the server
+/// closes it.
+fn get_then_ok(address: SocketAddr, path: &str) -> String {
+    let mut client = TcpStream::connect(address).unwrap();
+    write!(
+        client,
+        "GET {path} HTTP/1.1\r\nHost: localhost\r\n\r\nGET /ok HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n"
+    )
+    .unwrap();
+    let mut response = String::new();
+    client.read_to_string(&mut response).unwrap();
+    response
+}
+
+#[test]
+fn io_errors_in_a_handler_get_500_and_close_the_connection() {
+    let response = get_then_ok(start(), "/broken");
+
+    assert!(
+        response.starts_with("HTTP/1.1 500 Internal Server Error\r\n"),
+        "{response}"
+    );
+    assert!(response.contains("Connection: close\r\n"), "{response}");
+    assert_eq!(response.matches("HTTP/1.1 ").count(), 1, "{response}");
+}
+
+#[test]
+fn request_errors_in_a_handler_get_their_own_status() {
+    let response = get_then_ok(start(), "/bad");
+
+    assert!(
+        response.starts_with("HTTP/1.1 400 Bad Request\r\n"),
+        "{response}"
+    );
+    assert!(response.contains("Connection: close\r\n"), "{response}");
+    assert_eq!(response.matches("HTTP/1.1 ").count(), 1, "{response}");
+}
+
+#[test]
+fn working_handlers_keep_the_connection_open() {
+    let response = get_then_ok(start(), "/ok");
+
+    assert_eq!(
+        response.matches("HTTP/1.1 200 OK\r\n").count(),
+        2,
+        "{response}"
+    );
+}
@@ -0,0 +1,210 @@
+use rust_server::{Request, RequestError, StatusCode};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A throwaway directory tree, removed again when dropped:
+///
+/// ```text
+/// <root>/secret.txt
+/// <root>/public/index.html
+/// <root>/public/my page.html
+/// <root>/public/café.html
+/// <root>/public/docs/index.html
+/// <root>/public/escape       -> <root>            (symlink, unix only)
+/// <root>/public/secret-link  -> <root>/secret.txt (symlink, unix only)
+/// ```
+struct Fixture {
+    root: PathBuf,
+}
+
+impl Fixture {
+    fn new(name: &str) -> Self {
+        let root = std::env::temp_dir().join(format!(
+            "rust_server_traversal_{name}_{}",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("public/docs")).unwrap();
+        fs::write(root.join("secret.txt"), "secret").unwrap();
+        fs::write(root.join("public/index.html"), "index").unwrap();
+        fs::write(root.join("public/my page.html"), "spaced").unwrap();
+        fs::write(root.join("public/café.html"), "accented").unwrap();
+        fs::write(root.join("public/docs/index.html"), "docs").unwrap();
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(&root, root.join("public/escape")).unwrap();
+            std::os::unix::fs::symlink(root.join("secret.txt"), root.join("public/secret-link"))
+                .unwrap();
+        }
+        Self { root }
+    }
+
+    fn public(&self) -> PathBuf {
+        self.root.join("public")
+    }
+}
+
+impl Drop for Fixture {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.root);
+    }
+}
+
+/// Parses a `GET` request for `target`.
+fn parse(target: &str) -> Result<Request, RequestError> {
+    let raw = format!("GET {target} HTTP/1.1\r\nHost: localhost\r\n\r\n");
+    Request::new(&mut raw.as_bytes())
+}
+
+/// Resolves `target` against `base_dir`, panicking if the request doesn't even parse.
+fn resolve(target: &str, base_dir: &Path) -> Option<PathBuf> {
+    parse(target)
+        .unwrap_or_else(|error| panic!("`{target}` should parse, got {error:?}"))
+        .path_exists(base_dir)
+}
+
+#[test]
+fn malformed_and_encoded_traversal_targets_are_rejected_while_parsing() {
+    let payloads = [
+        /* Encoded slashes. */
+        "/..%2fsecret.txt",
+        "/%2e%2e%2fsecret.txt",
+        "/docs%2F..%2F..%2Fsecret.txt",
+        /* Backslashes, raw and encoded. */
+        "/..\\secret.txt",
+        "/..%5csecret.txt",
+        "/%5C..%5C..%5Csecret.txt",
+        /* NUL and other control characters. */
+        "/index.html%00.txt",
+        "/%00",
+        "/%0d%0aSet-Cookie:%20x",
+        "/%7f",
+        /* Invalid escapes. */
+        "/%",
+        "/%2",
+        "/%zz",
+        "/index.html%g0",
+        "/index.html?q=%",
+        /* Invalid UTF-8, including overlong encodings of `.` and `/`. */
+        "/%ff",
+        "/%c0%ae%c0%ae/secret.txt",
+        "/%c0%af",
+        "/%e0%80%af",
+        /* Not an origin-, absolute- or asterisk-form target. */
+        "secret.txt",
+        "../secret.txt",
+        "*",
+    ];
+
+    for payload in payloads {
+        match parse(payload) {
+            Err(RequestError::InvalidURL) => {}
+            other => panic!(
+                "`{payload}` should be rejected with InvalidURL, got {:?}",
+                other.map(|request| request.path().to_string())
+            ),
+        }
+    }
+}
+
+#[test]
+fn invalid_urls_are_answered_with_bad_request() {
+    assert_eq!(
+        RequestError::InvalidURL.status(),
+        Some(StatusCode::BadRequest)
+    );
+}
+
+#[test]
+fn decoded_traversal_never_leaves_the_document_root() {
+    let fixture = Fixture::new("escape");
+    let public = fixture.public();
+
+    let payloads = [
+        "/../secret.txt",
+        "/../../../../../../etc/passwd",
+        "/docs/../../secret.txt",
+        "/%2e%2e/secret.txt",
+        "/%2E%2E/secret.txt",
+        "/.%2e/secret.txt",
+        "/%2e./secret.txt",
+        "/docs/%2e%2e/%2e%2e/secret.txt",
+        "/./../secret.txt",
+        "//../secret.txt",
+        "/..;/secret.txt",
+        "/escape/secret.txt",
+        "/escape/public/../secret.txt",
+        "/secret-link",
+        "http://localhost/../secret.txt",
+        "/nonexistent/../../secret.txt",
+    ];
+
+    for payload in payloads {
+        if let Ok(request) = parse(payload) {
+            assert_eq!(
+                request.path_exists(&public),
+                None,
+                "`{payload}` resolved outside the document root"
+            );
+        }
+    }
+}
+
+#[test]
+fn dot_segments_are_removed_before_the_path_reaches_the_router() {
+    let fixture = Fixture::new("dots");
+    let public = fixture.public().canonicalize().unwrap();
+
+    let cases = [
+        ("/x/../docs/index.html", "/docs/index.html"),
+        ("/./docs/index.html", "/docs/index.html"),
+        ("/%2e%2e/docs/index.html", "/docs/index.html"),
+        ("/x/%2E%2e/docs/index.html", "/docs/index.html"),
+        ("/docs/%2e/index.html", "/docs/index.html"),
+        ("/docs/.%2e", "/"),
+        ("http://localhost/a/../docs/index.html", "/docs/index.html"),
+    ];
+
+    for (target, expected) in cases {
+        let request =
+            parse(target).unwrap_or_else(|error| panic!("`{target}` should parse, got {error:?}"));
+        assert_eq!(request.path(), expected, "`{target}`");
+        assert_eq!(
+            request.path_exists(&public),
+            resolve(expected, &public),
+            "`{target}` should resolve like `{expected}`"
+        );
+    }
+}
+
+#[test]
+fn percent_encoded_names_resolve_inside_the_document_root() {
+    let fixture = Fixture::new("decode");
+    let public = fixture.public().canonicalize().unwrap();
+
+    let cases = [
+        ("/", "index.html"),
+        ("/my%20page.html", "my page.html"),
+        ("/caf%C3%A9.html", "café.html"),
+        ("/%64ocs/", "docs/index.html"),
+        ("/docs/%2e%2e/index.html", "index.html"),
+        ("/docs?x=%2F#top", "docs/index.html"),
+        ("http://localhost/docs/", "docs/index.html"),
+        ("http://localhost", "index.html"),
+    ];
+
+    for (target, expected) in cases {
+        assert_eq!(
+            resolve(target, &public),
+            Some(public.join(expected)),
+            "`{target}` should resolve to `{expected}`"
+        );
+    }
+}
+
+#[test]
+fn options_accepts_the_asterisk_target() {
+    let raw = "OPTIONS * HTTP/1.1\r\nHost: localhost\r\n\r\n";
+    let request = Request::new(&mut raw.as_bytes()).unwrap();
+    assert_eq!(request.path(), "*");
+}
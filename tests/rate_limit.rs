@@ -0,0 +1,127 @@
+use rust_server::{
+    Response, Router, Server, ServerConfig, ServerError, ShutdownHandle, StatusCode,
+};
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// A running server that answers `GET /` with `hello`, rate limited to a burst of 2 requests and
+/// 2 connections per IP. Shut down and joined again when dropped.
+struct Running {
+    address: SocketAddr,
+    handle: ShutdownHandle,
+    thread: Option<JoinHandle<Result<(), ServerError>>>,
+}
+
+impl Running {
+    fn start() -> Self {
+        let mut config = ServerConfig {
+            address: "127.0.0.1:0".to_string(),
+            ..ServerConfig::default()
+        };
+        config.logging.requests = false;
+        config.rate_limit.enabled = true;
+        config.rate_limit.requests_per_second = 0.5;
+        config.rate_limit.burst = 2;
+        config.rate_limit.max_connections_per_ip = 2;
+
+        let router = Router::new().get("/", |_: &_, _: &_| {
+            Ok(Response::new(StatusCode::Ok).with_bytes("hello"))
+        });
+        let server = Server::from_config(config).unwrap().with_router(router);
+        let address = server.local_addr().unwrap();
+        let handle = server.shutdown_handle();
+        let thread = Some(thread::spawn(move || server.run()));
+        Self {
+            address,
+            handle,
+            thread,
+        }
+    }
+
+    fn connect(&self) -> TcpStream {
+        let client = TcpStream::connect(self.address).unwrap();
+        client
+            .set_read_timeout(Some(Duration::from_secs(10)))
+            .unwrap();
+        client
+    }
+
+    /// Sends `GET <path>` on a connection of its own and returns the raw response.
+    fn get(&self, path: &str) -> String {
+        let mut client = self.connect();
+        write!(
+            client,
+            "GET {path} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n"
+        )
+        .unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        response
+    }
+}
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        self.handle.shutdown();
+        if let Some(thread) = self.thread.take() {
+            thread.join().unwrap().unwrap();
+        }
+    }
+}
+
+#[test]
+fn requests_beyond_the_burst_get_429_with_retry_after() {
+    let server = Running::start();
+
+    for _ in 0..2 {
+        let response = server.get("/");
+        assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+    }
+    let response = server.get("/");
+    assert!(response.starts_with("HTTP/1.1 429"), "{response}");
+    assert!(response.contains("Retry-After: 2\r\n"), "{response}");
+
+    /* Probes are answered before the limiter. */
+    let response = server.get("/healthz");
+    assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+}
+
+#[test]
+fn connections_beyond_the_cap_get_429() {
+    let server = Running::start();
+
+    let _open = [server.connect(), server.connect()];
+    /* Give the accept loop time to count both. */
+    thread::sleep(Duration::from_millis(200));
+
+    let mut refused = server.connect();
+    let mut response = String::new();
+    refused.read_to_string(&mut response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 429"), "{response}");
+    assert!(response.contains("Connection: close\r\n"), "{response}");
+}
+
+#[test]
+fn refused_clients_that_already_sent_a_request_still_read_the_429() {
+    let server = Running::start();
+
+    let _open = [server.connect(), server.connect()];
+    thread::sleep(Duration::from_millis(200));
+
+    let mut refused = server.connect();
+    write!(
+        refused,
+        "GET / HTTP/1.1\r\nHost: localhost\r\nUser-Agent: {}\r\n\r\n",
+        "x".repeat(1000)
+    )
+    .unwrap();
+    /* Read only after the server is done with the connection. */
+    thread::sleep(Duration::from_millis(200));
+
+    let mut response = String::new();
+    refused.read_to_string(&mut response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 429"), "{response}");
+    assert!(response.contains("Retry-After: 1\r\n"), "{response}");
+}
@@ -0,0 +1,116 @@
+use rust_server::Server;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// A client that is mid-request when the shutdown starts still gets its full response,
+/// and the server only returns from `run` after that response has been written.
+#[test]
+fn in_flight_request_finishes_while_shutdown_is_pending() {
+    let server = Server::bind("127.0.0.1:0")
+        .unwrap()
+        .with_drain_timeout(Duration::from_secs(5));
+    let address = server.local_addr().unwrap();
+    let handle = server.shutdown_handle();
+    let server_thread = thread::spawn(move || server.run());
+
+    let mut client = TcpStream::connect(address).unwrap();
+    /* Give the accept loop time to hand the connection to a worker. */
+    thread::sleep(Duration::from_millis(200));
+
+    handle.shutdown();
+    assert!(handle.is_shutting_down());
+
+    /* Trickle the request in while the shutdown is pending. */
+    let request = b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n";
+    let (head, last) = request.split_at(request.len() - 1);
+    for byte in head {
+        client.write_all(&[*byte]).unwrap();
+        thread::sleep(Duration::from_millis(20));
+    }
+    assert!(!server_thread.is_finished());
+    client.write_all(last).unwrap();
+
+    /* Download the response slowly, a few bytes at a time. */
+    let mut response = Vec::new();
+    let mut chunk = [0u8; 16];
+    loop {
+        let read = client.read(&mut chunk).unwrap();
+        if read == 0 {
+            break;
+        }
+        response.extend_from_slice(&chunk[..read]);
+        thread::sleep(Duration::from_millis(5));
+    }
+    let response = String::from_utf8(response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+    assert!(response.contains("<h1>./index.html</h1>"), "{response}");
+
+    server_thread.join().unwrap().unwrap();
+    assert!(TcpStream::connect(address).is_err());
+}
+
+/// A client that never sends anything cannot hold the shutdown past the drain timeout.
+#[test]
+fn shutdown_gives_up_on_stuck_connections_after_the_deadline() {
+    let server = Server::bind("127.0.0.1:0")
+        .unwrap()
+        .with_drain_timeout(Duration::from_millis(300));
+    let address = server.local_addr().unwrap();
+    let handle = server.shutdown_handle();
+    let server_thread = thread::spawn(move || server.run());
+
+    let _idle_client = TcpStream::connect(address).unwrap();
+    thread::sleep(Duration::from_millis(200));
+
+    let started = Instant::now();
+    handle.shutdown();
+    server_thread.join().unwrap().unwrap();
+    assert!(started.elapsed() < Duration::from_secs(3));
+}
+
+/// While in-flight connections drain, new clients are still answered, and the readiness probe
+/// reports `503 Service Unavailable` instead of the connection being refused.
+#[test]
+fn readiness_probe_gets_503_while_draining() {
+    let server = Server::bind("127.0.0.1:0")
+        .unwrap()
+        .with_drain_timeout(Duration::from_secs(5));
+    let address = server.local_addr().unwrap();
+    let handle = server.shutdown_handle();
+    let server_thread = thread::spawn(move || server.run());
+
+    let get = |path: &str| {
+        let mut client = TcpStream::connect(address).unwrap();
+        write!(
+            client,
+            "GET {path} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n"
+        )
+        .unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        response
+    };
+    let response = get("/readyz");
+    assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+
+    /* A request that is half sent keeps the server draining. */
+    let mut in_flight = TcpStream::connect(address).unwrap();
+    in_flight.write_all(b"GET / HTTP/1.1\r\n").unwrap();
+    thread::sleep(Duration::from_millis(200));
+    handle.shutdown();
+
+    let response = get("/readyz");
+    assert!(response.starts_with("HTTP/1.1 503"), "{response}");
+    assert!(response.contains("failed shutdown"), "{response}");
+    assert!(!server_thread.is_finished());
+
+    in_flight.write_all(b"Host: localhost\r\n\r\n").unwrap();
+    let mut response = String::new();
+    in_flight.read_to_string(&mut response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+
+    server_thread.join().unwrap().unwrap();
+    assert!(TcpStream::connect(address).is_err());
+}
@@ -0,0 +1,269 @@
+use rust_server::{
+    Request, Response, Router, Server, ServerConfig, ServerError, ShutdownHandle, StatusCode,
+};
+use std::io::{ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// A running server with short timeouts, shut down and joined again when dropped.
+///
+/// It answers `GET /` with `hello`, `POST /echo` with the request body, `GET /large` with
+/// a response far bigger than the socket buffers, and `GET /slow` with `done` after a second.
+struct Running {
+    address: SocketAddr,
+    handle: ShutdownHandle,
+    thread: Option<JoinHandle<Result<(), ServerError>>>,
+}
+
+impl Running {
+    fn start(configure: impl FnOnce(&mut ServerConfig)) -> Self {
+        let mut config = ServerConfig {
+            address: "127.0.0.1:0".to_string(),
+            ..ServerConfig::default()
+        };
+        config.logging.requests = false;
+        config.timeouts.keep_alive = Duration::from_millis(500);
+        config.timeouts.request_line = Duration::from_millis(500);
+        config.timeouts.headers = Duration::from_secs(1);
+        config.timeouts.body = Duration::from_millis(500);
+        config.timeouts.write = Duration::from_millis(500);
+        config.timeouts.min_rate = 0;
+        configure(&mut config);
+
+        let router = Router::new()
+            .get("/", |_: &_, _: &_| {
+                Ok(Response::new(StatusCode::Ok).with_bytes("hello"))
+            })
+            .post("/echo", |request: &Request, _: &_| {
+                Ok(Response::new(StatusCode::Ok).with_bytes(request.body().to_vec()))
+            })
+            .get("/large", |_: &_, _: &_| {
+                Ok(Response::new(StatusCode::Ok).with_bytes(vec![b'x'; 64 * 1024 * 1024]))
+            })
+            .get("/slow", |_: &_, _: &_| {
+                thread::sleep(Duration::from_secs(1));
+                Ok(Response::new(StatusCode::Ok).with_bytes("done"))
+            });
+        let server = Server::from_config(config).unwrap().with_router(router);
+        let address = server.local_addr().unwrap();
+        let handle = server.shutdown_handle();
+        let thread = Some(thread::spawn(move || server.run()));
+        Self {
+            address,
+            handle,
+            thread,
+        }
+    }
+
+    fn connect(&self) -> TcpStream {
+        let client = TcpStream::connect(self.address).unwrap();
+        client
+            .set_read_timeout(Some(Duration::from_secs(10)))
+            .unwrap();
+        client
+    }
+}
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        self.handle.shutdown();
+        if let Some(thread) = self.thread.take() {
+            thread.join().unwrap().unwrap();
+        }
+    }
+}
+
+/// Sends `data` one byte every `pause`, stopping early if the server closes the connection.
+fn trickle(client: &mut TcpStream, data: &[u8], pause: Duration) {
+    for byte in data {
+        if client.write_all(&[*byte]).is_err() {
+            return;
+        }
+        thread::sleep(pause);
+    }
+}
+
+/// Reads until the server closes the connection.
+fn read_response(client: &mut TcpStream) -> String {
+    let mut response = Vec::new();
+    match client.read_to_end(&mut response) {
+        Ok(_) => {}
+        /* The server may reset a connection it gave up on while data was still unread. */
+        Err(error) if error.kind() == ErrorKind::ConnectionReset => {}
+        Err(error) => panic!("{error}"),
+    }
+    String::from_utf8_lossy(&response).into_owned()
+}
+
+#[test]
+fn silent_client_gets_408_after_the_request_line_timeout() {
+    let server = Running::start(|_| {});
+    let started = Instant::now();
+    let mut client = server.connect();
+
+    let response = read_response(&mut client);
+    assert!(response.starts_with("HTTP/1.1 408"), "{response}");
+    assert!(response.contains("Connection: close\r\n"), "{response}");
+    let elapsed = started.elapsed();
+    assert!(elapsed >= Duration::from_millis(450), "{elapsed:?}");
+    assert!(elapsed < Duration::from_secs(3), "{elapsed:?}");
+}
+
+#[test]
+fn trickled_request_line_gets_408() {
+    let server = Running::start(|_| {});
+    let mut client = server.connect();
+
+    trickle(
+        &mut client,
+        b"GET /a-long-path-sent-one-byte-at-a-time HTTP/1.1\r\n",
+        Duration::from_millis(50),
+    );
+    let response = read_response(&mut client);
+    assert!(response.starts_with("HTTP/1.1 408"), "{response}");
+}
+
+#[test]
+fn trickled_headers_get_408_even_when_the_request_line_was_fast() {
+    let server = Running::start(|_| {});
+    let mut client = server.connect();
+
+    client.write_all(b"GET / HTTP/1.1\r\n").unwrap();
+    for _ in 0..20 {
+        if client.write_all(b"X-Slow: 1\r\n").is_err() {
+            break;
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    let response = read_response(&mut client);
+    assert!(response.starts_with("HTTP/1.1 408"), "{response}");
+}
+
+#[test]
+fn stalled_body_gets_408() {
+    let server = Running::start(|_| {});
+    let mut client = server.connect();
+
+    client
+        .write_all(
+            b"POST /echo HTTP/1.1\r\nHost: localhost\r\nContent-Length: 100\r\n\r\nonly ten..",
+        )
+        .unwrap();
+    let response = read_response(&mut client);
+    assert!(response.starts_with("HTTP/1.1 408"), "{response}");
+}
+
+#[test]
+fn body_below_the_minimum_rate_gets_408() {
+    let server = Running::start(|config| {
+        config.timeouts.min_rate = 100;
+        config.timeouts.min_rate_grace = Duration::from_millis(300);
+    });
+    let mut client = server.connect();
+
+    /* Every pause is shorter than the body timeout, but 10 bytes per second is too slow. */
+    client
+        .write_all(b"POST /echo HTTP/1.1\r\nHost: localhost\r\nContent-Length: 100\r\n\r\n")
+        .unwrap();
+    trickle(&mut client, &[b'x'; 100], Duration::from_millis(100));
+    let response = read_response(&mut client);
+    assert!(response.starts_with("HTTP/1.1 408"), "{response}");
+}
+
+#[test]
+fn slow_but_steady_clients_within_the_limits_are_served() {
+    let server = Running::start(|config| {
+        config.timeouts.min_rate = 10;
+        config.timeouts.min_rate_grace = Duration::from_millis(100);
+    });
+    let mut client = server.connect();
+
+    let body = b"0123456789";
+    let head = format!(
+        "POST /echo HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        body.len()
+    );
+    trickle(&mut client, head.as_bytes(), Duration::from_millis(5));
+    trickle(&mut client, body, Duration::from_millis(50));
+    let response = read_response(&mut client);
+    assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+    assert!(response.ends_with("0123456789"), "{response}");
+}
+
+#[test]
+fn slow_handlers_do_not_count_against_the_minimum_rate() {
+    let server = Running::start(|config| {
+        config.timeouts.min_rate = 1000;
+        config.timeouts.min_rate_grace = Duration::from_millis(300);
+    });
+    let mut client = server.connect();
+
+    client
+        .write_all(b"GET /slow HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+        .unwrap();
+    let response = read_response(&mut client);
+    assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+    assert!(response.ends_with("done"), "{response}");
+    assert_eq!(response.matches("HTTP/1.1").count(), 1, "{response}");
+}
+
+#[test]
+fn idle_persistent_connections_close_without_408() {
+    let server = Running::start(|_| {});
+    let mut client = server.connect();
+
+    client
+        .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .unwrap();
+    let response = read_response(&mut client);
+    assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+    assert!(!response.contains("408"), "{response}");
+}
+
+#[test]
+fn later_requests_on_a_persistent_connection_get_their_own_deadlines() {
+    let server = Running::start(|config| {
+        config.timeouts.keep_alive = Duration::from_secs(2);
+    });
+    let mut client = server.connect();
+
+    client
+        .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .unwrap();
+    let mut first = [0; 512];
+    let read = client.read(&mut first).unwrap();
+    assert!(first[..read].starts_with(b"HTTP/1.1 200"));
+
+    /* Idle past the request line timeout, then send the next request promptly. */
+    thread::sleep(Duration::from_millis(800));
+    client
+        .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+        .unwrap();
+    let response = read_response(&mut client);
+    assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+}
+
+#[test]
+fn client_that_stops_reading_is_dropped_after_the_write_timeout() {
+    let server = Running::start(|config| {
+        config.workers = 1;
+    });
+
+    /* This client never reads, so the only worker blocks writing to it. */
+    let mut stuck = server.connect();
+    stuck
+        .write_all(b"GET /large HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .unwrap();
+    thread::sleep(Duration::from_millis(200));
+
+    let started = Instant::now();
+    let mut client = server.connect();
+    client
+        .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+        .unwrap();
+    let response = read_response(&mut client);
+    assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+    assert!(started.elapsed() < Duration::from_secs(5));
+    drop(stuck);
+}
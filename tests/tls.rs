@@ -0,0 +1,346 @@
+#![cfg(feature = "tls")]
+
+use rcgen::{BasicConstraints, CertificateParams, IsCa, Issuer, KeyPair};
+use rust_server::{Server, ServerConfig, ServerError, ShutdownHandle, SniCertificate};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, ServerName};
+use rustls::{ClientConfig, ClientConnection, RootCertStore, StreamOwned};
+use std::fs;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// A throwaway directory with a self-signed CA and leaf certificates issued by it, removed
+/// again when dropped:
+///
+/// ```text
+/// <root>/default.pem, default.key  localhost
+/// <root>/example.pem, example.key  example.test, *.wild.test
+/// ```
+struct Fixture {
+    root: PathBuf,
+    ca: Issuer<'static, KeyPair>,
+    ca_der: CertificateDer<'static>,
+}
+
+impl Fixture {
+    fn new(name: &str) -> Self {
+        let root =
+            std::env::temp_dir().join(format!("rust_server_tls_{name}_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+
+        let mut params = CertificateParams::new(Vec::<String>::new()).unwrap();
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let key = KeyPair::generate().unwrap();
+        let ca_der = params.self_signed(&key).unwrap().der().clone();
+        let fixture = Self {
+            root,
+            ca: Issuer::new(params, key),
+            ca_der,
+        };
+        fixture.issue("default", &["localhost"]);
+        fixture.issue("example", &["example.test", "*.wild.test"]);
+        fixture
+    }
+
+    /// Issues a certificate for `names` into `<name>.pem` and `<name>.key`.
+    ///
+    /// # Returns
+    /// The DER of the new certificate, to compare with what the server sends.
+    fn issue(&self, name: &str, names: &[&str]) -> CertificateDer<'static> {
+        let names = names
+            .iter()
+            .map(|name| name.to_string())
+            .collect::<Vec<_>>();
+        let params = CertificateParams::new(names).unwrap();
+        let key = KeyPair::generate().unwrap();
+        let certificate = params.signed_by(&key, &self.ca).unwrap();
+        fs::write(self.root.join(format!("{name}.pem")), certificate.pem()).unwrap();
+        fs::write(self.root.join(format!("{name}.key")), key.serialize_pem()).unwrap();
+        certificate.der().clone()
+    }
+
+    fn path(&self, file: &str) -> PathBuf {
+        self.root.join(file)
+    }
+
+    /// A configuration serving HTTPS on a free port with the fixture's certificates.
+    fn config(&self) -> ServerConfig {
+        let mut config = ServerConfig {
+            address: "127.0.0.1:0".to_string(),
+            ..ServerConfig::default()
+        };
+        config.logging.requests = false;
+        config.tls.enabled = true;
+        config.tls.cert = Some(self.path("default.pem"));
+        config.tls.key = Some(self.path("default.key"));
+        config.tls.certificates = vec![SniCertificate {
+            names: vec!["example.test".to_string(), "*.wild.test".to_string()],
+            cert: self.path("example.pem"),
+            key: self.path("example.key"),
+        }];
+        config.tls.reload_interval = Duration::ZERO;
+        config
+    }
+
+    /// A client trusting only the fixture's CA.
+    fn client(&self) -> Arc<ClientConfig> {
+        let mut roots = RootCertStore::empty();
+        roots.add(self.ca_der.clone()).unwrap();
+        client_config(roots)
+    }
+}
+
+impl Drop for Fixture {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.root);
+    }
+}
+
+/// A running server, shut down and joined again when dropped.
+struct Running {
+    address: SocketAddr,
+    handle: ShutdownHandle,
+    thread: Option<JoinHandle<Result<(), ServerError>>>,
+}
+
+impl Running {
+    fn start(server: Server) -> Self {
+        let address = server.local_addr().unwrap();
+        let handle = server.shutdown_handle();
+        let thread = Some(thread::spawn(move || server.run()));
+        Self {
+            address,
+            handle,
+            thread,
+        }
+    }
+}
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        self.handle.shutdown();
+        if let Some(thread) = self.thread.take() {
+            thread.join().unwrap().unwrap();
+        }
+    }
+}
+
+fn client_config(roots: RootCertStore) -> Arc<ClientConfig> {
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let config = ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    Arc::new(config)
+}
+
+/// Sends `GET /healthz` over TLS to `address`, asking for `server_name` through SNI.
+///
+/// # Returns
+/// - `Ok((response, certificate))`: The raw response and the leaf certificate the server sent.
+/// - `Err(io::Error)`: If the handshake or the request failed.
+fn get(
+    address: SocketAddr,
+    client: Arc<ClientConfig>,
+    server_name: &str,
+) -> std::io::Result<(String, CertificateDer<'static>)> {
+    let server_name = ServerName::try_from(server_name.to_string()).unwrap();
+    let connection = ClientConnection::new(client, server_name).unwrap();
+    let mut stream = StreamOwned::new(connection, TcpStream::connect(address)?);
+    stream.write_all(b"GET /healthz HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")?;
+
+    let mut response = Vec::new();
+    match stream.read_to_end(&mut response) {
+        Ok(_) => {}
+        /* Not every server sends close_notify; the response is complete either way. */
+        Err(error) if error.kind() == std::io::ErrorKind::UnexpectedEof => {}
+        Err(error) => return Err(error),
+    }
+    let certificate = stream.conn.peer_certificates().unwrap()[0].clone();
+    Ok((String::from_utf8(response).unwrap(), certificate))
+}
+
+fn certificate(path: &Path) -> CertificateDer<'static> {
+    CertificateDer::from_pem_file(path).unwrap()
+}
+
+#[test]
+fn serves_https_with_the_default_certificate() {
+    let fixture = Fixture::new("default");
+    let server = Running::start(Server::from_config(fixture.config()).unwrap());
+
+    let (response, sent) = get(server.address, fixture.client(), "localhost").unwrap();
+    assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+    assert!(response.ends_with("ok\n"), "{response}");
+    assert_eq!(sent, certificate(&fixture.path("default.pem")));
+}
+
+#[test]
+fn sni_selects_the_matching_certificate() {
+    let fixture = Fixture::new("sni");
+    let server = Running::start(Server::from_config(fixture.config()).unwrap());
+    let example = certificate(&fixture.path("example.pem"));
+
+    for name in ["example.test", "EXAMPLE.test", "api.wild.test"] {
+        let (response, sent) = get(server.address, fixture.client(), name).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200"), "{name}: {response}");
+        assert_eq!(sent, example, "{name}");
+    }
+
+    /* Wildcards are one label deep, and unknown names get the default certificate. */
+    for name in ["a.b.wild.test", "other.test"] {
+        let error = get(server.address, fixture.client(), name).unwrap_err();
+        assert!(error.to_string().contains("certificate"), "{name}: {error}");
+    }
+}
+
+#[test]
+fn clients_that_do_not_trust_the_ca_fail_the_handshake() {
+    let fixture = Fixture::new("untrusted");
+    let server = Running::start(Server::from_config(fixture.config()).unwrap());
+
+    let client = client_config(RootCertStore::empty());
+    assert!(get(server.address, client, "localhost").is_err());
+
+    /* The server keeps serving everyone else. */
+    assert!(get(server.address, fixture.client(), "localhost").is_ok());
+}
+
+#[test]
+fn reload_serves_new_certificates_to_new_connections() {
+    let fixture = Fixture::new("reload");
+    let server = Server::from_config(fixture.config()).unwrap();
+    let certificates = server.certificates().unwrap();
+    let server = Running::start(server);
+
+    let (_, before) = get(server.address, fixture.client(), "localhost").unwrap();
+    assert!(!certificates.reload_if_changed().unwrap());
+
+    let renewed = fixture.issue("default", &["localhost"]);
+    assert_ne!(before, renewed);
+    assert!(certificates.reload_if_changed().unwrap());
+    let (_, after) = get(server.address, fixture.client(), "localhost").unwrap();
+    assert_eq!(after, renewed);
+
+    /* A broken file is refused and the last good certificates stay in use. */
+    fs::write(fixture.path("default.key"), "not a key").unwrap();
+    assert!(certificates.reload().is_err());
+    let (_, kept) = get(server.address, fixture.client(), "localhost").unwrap();
+    assert_eq!(kept, renewed);
+}
+
+#[test]
+fn watcher_reloads_changed_files() {
+    let fixture = Fixture::new("watch");
+    let mut config = fixture.config();
+    config.tls.reload_interval = Duration::from_millis(100);
+    let server = Running::start(Server::from_config(config).unwrap());
+
+    let renewed = fixture.issue("default", &["localhost"]);
+    for _ in 0..50 {
+        let (_, sent) = get(server.address, fixture.client(), "localhost").unwrap();
+        if sent == renewed {
+            return;
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    panic!("the renewed certificate was never served");
+}
+
+#[test]
+fn redirect_listener_sends_plain_http_to_https() {
+    let fixture = Fixture::new("redirect");
+    let redirect_address = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let mut config = fixture.config();
+    config.tls.redirect_address = Some(redirect_address.to_string());
+    let server = Running::start(Server::from_config(config).unwrap());
+    let https_port = server.address.port();
+
+    let request = |raw: &str| {
+        /* The redirect listener is bound once the server runs. */
+        let mut client = (0..50)
+            .find_map(|_| {
+                TcpStream::connect(redirect_address)
+                    .inspect_err(|_| thread::sleep(Duration::from_millis(20)))
+                    .ok()
+            })
+            .unwrap();
+        client.write_all(raw.as_bytes()).unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        response
+    };
+
+    let response = request("GET /docs/a%20b?x=1 HTTP/1.1\r\nHost: localhost:8080\r\n\r\n");
+    assert!(response.starts_with("HTTP/1.1 301"), "{response}");
+    let location = format!("Location: https://localhost:{https_port}/docs/a%20b?x=1\r\n");
+    assert!(response.contains(&location), "{response}");
+
+    let response = request("POST /form HTTP/1.1\r\nHost: localhost\r\nContent-Length: 0\r\n\r\n");
+    assert!(response.starts_with("HTTP/1.1 308"), "{response}");
+
+    let response = request("GET / HTTP/1.1\r\nHost: bad/host\r\n\r\n");
+    assert!(response.starts_with("HTTP/1.1 400"), "{response}");
+}
+
+#[test]
+fn redirect_listener_is_not_held_up_by_a_silent_client() {
+    let fixture = Fixture::new("redirect_silent");
+    let redirect_address = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let mut config = fixture.config();
+    config.tls.redirect_address = Some(redirect_address.to_string());
+    config.timeouts.request_line = Duration::from_secs(1);
+    let _server = Running::start(Server::from_config(config).unwrap());
+
+    let connect = || {
+        (0..50)
+            .find_map(|_| {
+                TcpStream::connect(redirect_address)
+                    .inspect_err(|_| thread::sleep(Duration::from_millis(20)))
+                    .ok()
+            })
+            .unwrap()
+    };
+    let _silent = connect();
+    thread::sleep(Duration::from_millis(100));
+
+    let started = Instant::now();
+    let mut client = connect();
+    client
+        .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .unwrap();
+    let mut response = String::new();
+    client.read_to_string(&mut response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 301"), "{response}");
+    assert!(started.elapsed() < Duration::from_millis(500));
+}
+
+#[test]
+fn unusable_certificates_stop_the_server_from_starting() {
+    let fixture = Fixture::new("invalid");
+    fs::write(fixture.path("example.key"), "not a key").unwrap();
+    assert!(matches!(
+        Server::from_config(fixture.config()),
+        Err(ServerError::Tls(_))
+    ));
+
+    /* A key that doesn't belong to its certificate is refused as well. */
+    let fixture = Fixture::new("mismatched");
+    fs::copy(fixture.path("example.key"), fixture.path("default.key")).unwrap();
+    assert!(matches!(
+        Server::from_config(fixture.config()),
+        Err(ServerError::Tls(_))
+    ));
+}